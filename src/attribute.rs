@@ -0,0 +1,915 @@
+// rustyVM - Java VM written in pure Rust
+// Copyright (c) 2013 Alexander Gessler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+
+// Parsing of the attribute tables attached to classes, fields, methods
+// and Code attributes, based on
+// http://docs.oracle.com/javase/specs/jvms/se7/html/jvms-4.html#jvms-4.7
+//
+// Every attribute is read in full as given by its attribute_length,
+// so an attribute we do not understand never desyncs the reader.
+// Known attributes are kept as typed values, all others are retained
+// as raw bytes.
+
+use std::io::{result, BufReader};
+
+use def::*;
+use classloader::{ClassLoader};
+use code::{CodeBlock, ExceptionHandler};
+use field::{ConstantValue};
+
+
+// Location of an attribute table in the class file. Predefined
+// attributes that appear in a location they are not defined for
+// are not interpreted and kept as ATTR_Unknown.
+#[deriving(Eq)]
+pub enum AttributeContext {
+	AC_Class,
+	AC_Field,
+	AC_Method,
+	AC_Code,
+	AC_RecordComponent,
+}
+
+
+pub struct LineNumber {
+	start_pc : uint,
+	line_number : uint,
+}
+
+
+// Entry in a LocalVariableTable or LocalVariableTypeTable. For the
+// latter, `desc` holds the generic signature of the variable.
+pub struct LocalVariable {
+	start_pc : uint,
+	length : uint,
+	name : ~str,
+	desc : ~str,
+	index : uint,
+}
+
+
+pub enum VerificationType {
+	VT_Top,
+	VT_Integer,
+	VT_Float,
+	VT_Double,
+	VT_Long,
+	VT_Null,
+	VT_UninitializedThis,
+	VT_Object(~str),
+
+	// bytecode offset of the `new` instruction that created the object
+	VT_Uninitialized(uint),
+}
+
+
+// StackMapTable frames. The first tuple element is always the offset_delta
+pub enum StackMapFrame {
+	SMF_Same(uint),
+	SMF_SameLocals1StackItem(uint, VerificationType),
+	// number of chopped locals
+	SMF_Chop(uint, uint),
+	SMF_Append(uint, ~[VerificationType]),
+	// locals, stack
+	SMF_Full(uint, ~[VerificationType], ~[VerificationType]),
+}
+
+
+pub struct InnerClass {
+	inner_class : ~str,
+	outer_class : Option<~str>,
+	inner_name : Option<~str>,
+	access : uint,
+}
+
+
+// Bootstrap method specifier. Both the method handle and the arguments
+// are kept as constant pool indices as they are only resolved when
+// the corresponding invokedynamic call site is linked.
+pub struct BootstrapMethod {
+	method_ref : uint,
+	arguments : ~[uint],
+}
+
+
+pub struct Annotation {
+	// field descriptor of the annotation type
+	type_desc : ~str,
+	elements : ~[(~str, ElementValue)],
+}
+
+
+pub enum ElementValue {
+	// tag (one of BCDFIJSZs) and constant pool index of the value
+	EV_Const(char, uint),
+	// field descriptor of the enum type, name of the constant
+	EV_Enum(~str, ~str),
+	// return descriptor of the class literal, i.e. Ljava/lang/Object;
+	EV_Class(~str),
+	EV_Annotation(Annotation),
+	EV_Array(~[ElementValue]),
+}
+
+
+pub struct RecordComponent {
+	name : ~str,
+	desc : ~str,
+	attributes : ~[Attribute],
+}
+
+
+pub struct MethodParameter {
+	// None for parameters without a name
+	name : Option<~str>,
+	access : uint,
+}
+
+
+pub enum Attribute {
+	ATTR_ConstantValue(ConstantValue),
+	ATTR_Code(CodeBlock),
+	ATTR_StackMapTable(~[StackMapFrame]),
+	ATTR_Exceptions(~[~str]),
+	ATTR_InnerClasses(~[InnerClass]),
+	// class, and, if the class is enclosed by a method, its (name, descriptor)
+	ATTR_EnclosingMethod(~str, Option<(~str, ~str)>),
+	ATTR_Synthetic,
+	ATTR_Signature(~str),
+	ATTR_SourceFile(~str),
+	ATTR_LineNumberTable(~[LineNumber]),
+	ATTR_LocalVariableTable(~[LocalVariable]),
+	ATTR_LocalVariableTypeTable(~[LocalVariable]),
+	ATTR_Deprecated,
+	ATTR_RuntimeVisibleAnnotations(~[Annotation]),
+	ATTR_RuntimeInvisibleAnnotations(~[Annotation]),
+	ATTR_RuntimeVisibleParameterAnnotations(~[~[Annotation]]),
+	ATTR_RuntimeInvisibleParameterAnnotations(~[~[Annotation]]),
+	ATTR_AnnotationDefault(ElementValue),
+	ATTR_BootstrapMethods(~[BootstrapMethod]),
+	ATTR_MethodParameters(~[MethodParameter]),
+	ATTR_Record(~[RecordComponent]),
+
+	// name and raw contents of any other attribute
+	ATTR_Unknown(~str, ~[u8]),
+}
+
+
+// ----------------------------------------------
+// Read an attribute table, i.e. an u2 attributes_count followed by
+// as many attribute_info structures, from the given reader.
+pub fn read_attributes(constants : &[Constant], reader : &mut Reader,
+	context : AttributeContext) -> Result<~[Attribute], ~str>
+{
+	let mut attrs : ~[Attribute] = ~[];
+	let attr_count = reader.read_be_u16() as uint;
+	for _ in range(0, attr_count) {
+		let name = match ClassLoader::resolve_name_cpool_entry(constants,
+			reader.read_be_u16() as uint) {
+			Err(s) => return Err(s),
+			Ok(n) => n
+		};
+
+		// always consume the whole attribute, regardless of
+		// how much of it we actually understand.
+		let attr_len = reader.read_be_u32() as uint;
+		let attr_bytes = reader.read_bytes(attr_len);
+
+		match read_attribute_body(constants, name, attr_bytes, context) {
+			Err(s) => return Err(s),
+			Ok(a) => attrs.push(a)
+		}
+	}
+	Ok(attrs)
+}
+
+
+// ----------------------------------------------
+// Locate the [Code] attribute in a list of attributes and move it out
+pub fn take_code(attrs : &mut ~[Attribute]) -> Option<CodeBlock> {
+	match attrs.iter().position(|a| match *a { ATTR_Code(_) => true, _ => false }) {
+		None => None,
+		Some(i) => match attrs.remove(i) {
+			ATTR_Code(c) => Some(c),
+			_ => fail!("invariant")
+		}
+	}
+}
+
+
+// ----------------------------------------------
+// Locate the [ConstantValue] attribute in a list of attributes and move it out
+pub fn take_constant_value(attrs : &mut ~[Attribute]) -> Option<ConstantValue> {
+	match attrs.iter().position(|a| match *a { ATTR_ConstantValue(_) => true, _ => false }) {
+		None => None,
+		Some(i) => match attrs.remove(i) {
+			ATTR_ConstantValue(c) => Some(c),
+			_ => fail!("invariant")
+		}
+	}
+}
+
+
+// ----------------------------------------------
+// Count how often an attribute with the given name occurs in a list
+pub fn count_attributes(attrs : &[Attribute], name : &str) -> uint {
+	attrs.iter().count(|a| a.get_name() == name)
+}
+
+
+impl Attribute {
+
+	// ----------------------------------------------
+	// Get the name of the attribute as it appears in the class file
+	pub fn get_name<'a>(&'a self) -> &'a str {
+		match *self {
+			ATTR_ConstantValue(_) => "ConstantValue",
+			ATTR_Code(_) => "Code",
+			ATTR_StackMapTable(_) => "StackMapTable",
+			ATTR_Exceptions(_) => "Exceptions",
+			ATTR_InnerClasses(_) => "InnerClasses",
+			ATTR_EnclosingMethod(_, _) => "EnclosingMethod",
+			ATTR_Synthetic => "Synthetic",
+			ATTR_Signature(_) => "Signature",
+			ATTR_SourceFile(_) => "SourceFile",
+			ATTR_LineNumberTable(_) => "LineNumberTable",
+			ATTR_LocalVariableTable(_) => "LocalVariableTable",
+			ATTR_LocalVariableTypeTable(_) => "LocalVariableTypeTable",
+			ATTR_Deprecated => "Deprecated",
+			ATTR_RuntimeVisibleAnnotations(_) => "RuntimeVisibleAnnotations",
+			ATTR_RuntimeInvisibleAnnotations(_) => "RuntimeInvisibleAnnotations",
+			ATTR_RuntimeVisibleParameterAnnotations(_) => "RuntimeVisibleParameterAnnotations",
+			ATTR_RuntimeInvisibleParameterAnnotations(_) => "RuntimeInvisibleParameterAnnotations",
+			ATTR_AnnotationDefault(_) => "AnnotationDefault",
+			ATTR_BootstrapMethods(_) => "BootstrapMethods",
+			ATTR_MethodParameters(_) => "MethodParameters",
+			ATTR_Record(_) => "Record",
+			ATTR_Unknown(ref name, _) => name.as_slice(),
+		}
+	}
+}
+
+
+// IMPL
+
+
+// ----------------------------------------------
+// Check whether a predefined attribute is meaningful in a given context
+fn is_attribute_allowed(name : &str, context : AttributeContext) -> bool {
+	match name {
+		"ConstantValue" => context == AC_Field,
+		"Code" | "Exceptions" | "RuntimeVisibleParameterAnnotations" |
+		"RuntimeInvisibleParameterAnnotations" | "AnnotationDefault" |
+		"MethodParameters" => context == AC_Method,
+		"StackMapTable" | "LineNumberTable" | "LocalVariableTable" |
+		"LocalVariableTypeTable" => context == AC_Code,
+		"InnerClasses" | "EnclosingMethod" | "SourceFile" | "BootstrapMethods" |
+		"Record" => context == AC_Class,
+		"Synthetic" | "Deprecated" =>
+			context == AC_Class || context == AC_Field || context == AC_Method,
+		"Signature" | "RuntimeVisibleAnnotations" | "RuntimeInvisibleAnnotations" =>
+			context != AC_Code,
+		_ => false
+	}
+}
+
+
+// ----------------------------------------------
+fn read_attribute_body(constants : &[Constant], name : ~str, bytes : ~[u8],
+	context : AttributeContext) -> Result<Attribute, ~str>
+{
+	if !is_attribute_allowed(name, context) {
+		return Ok(ATTR_Unknown(name, bytes));
+	}
+
+	let mut reader = BufReader::new(bytes);
+	let res = match result(|| {
+		let r = &mut reader as &mut Reader;
+		match name.as_slice() {
+			"ConstantValue" =>
+				match ClassLoader::resolve_constant_value_cpool_entry(constants,
+					r.read_be_u16() as uint) {
+					Err(s) => Err(s),
+					Ok(cv) => Ok(ATTR_ConstantValue(cv))
+				},
+			"Code" =>
+				match read_code(constants, r) {
+					Err(s) => Err(s),
+					Ok(c) => Ok(ATTR_Code(c))
+				},
+			"StackMapTable" =>
+				match read_stack_map_table(constants, r) {
+					Err(s) => Err(s),
+					Ok(t) => Ok(ATTR_StackMapTable(t))
+				},
+			"Exceptions" => {
+				let mut v : ~[~str] = ~[];
+				for _ in range(0, r.read_be_u16()) {
+					match ClassLoader::resolve_class_cpool_entry(constants, r.read_be_u16() as uint) {
+						Err(s) => return Err(s),
+						Ok(c) => v.push(c)
+					}
+				}
+				Ok(ATTR_Exceptions(v))
+			},
+			"InnerClasses" =>
+				match read_inner_classes(constants, r) {
+					Err(s) => Err(s),
+					Ok(t) => Ok(ATTR_InnerClasses(t))
+				},
+			"EnclosingMethod" => {
+				let class = match ClassLoader::resolve_class_cpool_entry(constants,
+					r.read_be_u16() as uint) {
+					Err(s) => return Err(s),
+					Ok(c) => c
+				};
+				let method_index = r.read_be_u16() as uint;
+				if method_index == 0 {
+					Ok(ATTR_EnclosingMethod(class, None))
+				}
+				else {
+					match resolve_name_and_type(constants, method_index) {
+						Err(s) => Err(s),
+						Ok(nt) => Ok(ATTR_EnclosingMethod(class, Some(nt)))
+					}
+				}
+			},
+			"Synthetic" => Ok(ATTR_Synthetic),
+			"Deprecated" => Ok(ATTR_Deprecated),
+			"Signature" =>
+				match ClassLoader::resolve_name_cpool_entry(constants, r.read_be_u16() as uint) {
+					Err(s) => Err(s),
+					Ok(s) => Ok(ATTR_Signature(s))
+				},
+			"SourceFile" =>
+				match ClassLoader::resolve_name_cpool_entry(constants, r.read_be_u16() as uint) {
+					Err(s) => Err(s),
+					Ok(s) => Ok(ATTR_SourceFile(s))
+				},
+			"LineNumberTable" => {
+				let mut v : ~[LineNumber] = ~[];
+				for _ in range(0, r.read_be_u16()) {
+					let start_pc = r.read_be_u16() as uint;
+					v.push(LineNumber {
+						start_pc : start_pc,
+						line_number : r.read_be_u16() as uint
+					});
+				}
+				Ok(ATTR_LineNumberTable(v))
+			},
+			"LocalVariableTable" =>
+				match read_local_variables(constants, r) {
+					Err(s) => Err(s),
+					Ok(t) => Ok(ATTR_LocalVariableTable(t))
+				},
+			"LocalVariableTypeTable" =>
+				match read_local_variables(constants, r) {
+					Err(s) => Err(s),
+					Ok(t) => Ok(ATTR_LocalVariableTypeTable(t))
+				},
+			"RuntimeVisibleAnnotations" =>
+				match read_annotations(constants, r) {
+					Err(s) => Err(s),
+					Ok(t) => Ok(ATTR_RuntimeVisibleAnnotations(t))
+				},
+			"RuntimeInvisibleAnnotations" =>
+				match read_annotations(constants, r) {
+					Err(s) => Err(s),
+					Ok(t) => Ok(ATTR_RuntimeInvisibleAnnotations(t))
+				},
+			"RuntimeVisibleParameterAnnotations" =>
+				match read_parameter_annotations(constants, r) {
+					Err(s) => Err(s),
+					Ok(t) => Ok(ATTR_RuntimeVisibleParameterAnnotations(t))
+				},
+			"RuntimeInvisibleParameterAnnotations" =>
+				match read_parameter_annotations(constants, r) {
+					Err(s) => Err(s),
+					Ok(t) => Ok(ATTR_RuntimeInvisibleParameterAnnotations(t))
+				},
+			"AnnotationDefault" =>
+				match read_element_value(constants, r) {
+					Err(s) => Err(s),
+					Ok(ev) => Ok(ATTR_AnnotationDefault(ev))
+				},
+			"BootstrapMethods" => {
+				let mut v : ~[BootstrapMethod] = ~[];
+				for _ in range(0, r.read_be_u16()) {
+					let method_ref = r.read_be_u16() as uint;
+					let mut args : ~[uint] = ~[];
+					for _ in range(0, r.read_be_u16()) {
+						args.push(r.read_be_u16() as uint);
+					}
+					v.push(BootstrapMethod {
+						method_ref : method_ref,
+						arguments : args
+					});
+				}
+				Ok(ATTR_BootstrapMethods(v))
+			},
+			"MethodParameters" => {
+				let mut v : ~[MethodParameter] = ~[];
+				for _ in range(0, r.read_u8()) {
+					let name_index = r.read_be_u16() as uint;
+					let name = if name_index == 0 {
+						None
+					}
+					else {
+						match ClassLoader::resolve_name_cpool_entry(constants, name_index) {
+							Err(s) => return Err(s),
+							Ok(n) => Some(n)
+						}
+					};
+					v.push(MethodParameter {
+						name : name,
+						access : r.read_be_u16() as uint
+					});
+				}
+				Ok(ATTR_MethodParameters(v))
+			},
+			"Record" =>
+				match read_record_components(constants, r) {
+					Err(s) => Err(s),
+					Ok(t) => Ok(ATTR_Record(t))
+				},
+			_ => fail!("invariant, attribute should not be allowed")
+		}
+	}) {
+		Err(e) => return Err(format!("[{}] attribute is truncated", name)),
+		Ok(res) => res
+	};
+
+	// the contents must fill the attribute exactly
+	if res.is_ok() && !reader.eof() {
+		return Err(format!("[{}] attribute is longer than its contents", name));
+	}
+	res.map_err(|s| format!("failed to read [{}] attribute: {}", name, s))
+}
+
+
+// ----------------------------------------------
+// Read the contents of a [Code] attribute
+// http://docs.oracle.com/javase/specs/jvms/se7/html/jvms-4.html#jvms-4.7.3
+fn read_code(constants : &[Constant], reader : &mut Reader) -> Result<CodeBlock, ~str> {
+	let max_stack = reader.read_be_u16() as uint;
+	let max_locals = reader.read_be_u16() as uint;
+	let code_len = reader.read_be_u32() as uint;
+	if code_len == 0 || code_len >= 65536 {
+		return Err(format!("code_length must be in [1,65535], but is {}", code_len));
+	}
+
+	let codebytes = reader.read_bytes(code_len);
+
+	let exc_len = reader.read_be_u16() as uint;
+	let mut exc_rec : ~[ExceptionHandler] = ~[];
+	for _ in range(0, exc_len) {
+		let start_pc = reader.read_be_u16() as uint;
+		let end_pc = reader.read_be_u16() as uint;
+		let handler_pc = reader.read_be_u16() as uint;
+		let catch_type_index = reader.read_be_u16() as uint;
+
+		// catch_type 0 marks a handler that catches all exceptions, 
+		// as used to implement finally.
+		let catch_type = if catch_type_index == 0 {
+			None
+		}
+		else {
+			match ClassLoader::resolve_class_cpool_entry(constants, catch_type_index) {
+				Ok(cl) => Some(cl),
+				Err(s) => return Err(s)
+			}
+		};
+		exc_rec.push(ExceptionHandler {
+			start_pc : start_pc,
+			end_pc : end_pc,
+			handler_pc : handler_pc,
+			catch_type : catch_type,
+		});
+	}
+
+	let attrs = match read_attributes(constants, reader, AC_Code) {
+		Err(s) => return Err(s),
+		Ok(a) => a
+	};
+	CodeBlock::new(max_stack, max_locals, codebytes, exc_rec, attrs)
+}
+
+
+// ----------------------------------------------
+fn read_stack_map_table(constants : &[Constant], reader : &mut Reader) ->
+	Result<~[StackMapFrame], ~str>
+{
+	let mut frames : ~[StackMapFrame] = ~[];
+	for _ in range(0, reader.read_be_u16()) {
+		let frame_type = reader.read_u8() as uint;
+		let frame = match frame_type {
+			0..63 => SMF_Same(frame_type),
+			64..127 => match read_verification_type(constants, reader) {
+				Err(s) => return Err(s),
+				Ok(vt) => SMF_SameLocals1StackItem(frame_type - 64, vt)
+			},
+			247 => {
+				let delta = reader.read_be_u16() as uint;
+				match read_verification_type(constants, reader) {
+					Err(s) => return Err(s),
+					Ok(vt) => SMF_SameLocals1StackItem(delta, vt)
+				}
+			},
+			248..250 => SMF_Chop(reader.read_be_u16() as uint, 251 - frame_type),
+			251 => SMF_Same(reader.read_be_u16() as uint),
+			252..254 => {
+				let delta = reader.read_be_u16() as uint;
+				match read_verification_types(constants, reader, frame_type - 251) {
+					Err(s) => return Err(s),
+					Ok(v) => SMF_Append(delta, v)
+				}
+			},
+			255 => {
+				let delta = reader.read_be_u16() as uint;
+				let nlocals = reader.read_be_u16() as uint;
+				let locals = match read_verification_types(constants, reader, nlocals) {
+					Err(s) => return Err(s),
+					Ok(v) => v
+				};
+				let nstack = reader.read_be_u16() as uint;
+				match read_verification_types(constants, reader, nstack) {
+					Err(s) => return Err(s),
+					Ok(v) => SMF_Full(delta, locals, v)
+				}
+			},
+			_ => return Err(format!("reserved stack map frame type: {}", frame_type))
+		};
+		frames.push(frame);
+	}
+	Ok(frames)
+}
+
+
+// ----------------------------------------------
+fn read_verification_types(constants : &[Constant], reader : &mut Reader, count : uint) ->
+	Result<~[VerificationType], ~str>
+{
+	let mut v : ~[VerificationType] = ~[];
+	for _ in range(0, count) {
+		match read_verification_type(constants, reader) {
+			Err(s) => return Err(s),
+			Ok(vt) => v.push(vt)
+		}
+	}
+	Ok(v)
+}
+
+
+// ----------------------------------------------
+fn read_verification_type(constants : &[Constant], reader : &mut Reader) ->
+	Result<VerificationType, ~str>
+{
+	let tag = reader.read_u8();
+	Ok(match tag {
+		0 => VT_Top,
+		1 => VT_Integer,
+		2 => VT_Float,
+		3 => VT_Double,
+		4 => VT_Long,
+		5 => VT_Null,
+		6 => VT_UninitializedThis,
+		7 => match ClassLoader::resolve_class_cpool_entry(constants, reader.read_be_u16() as uint) {
+			Err(s) => return Err(s),
+			Ok(c) => VT_Object(c)
+		},
+		8 => VT_Uninitialized(reader.read_be_u16() as uint),
+		_ => return Err(format!("unrecognized verification type tag: {}", tag))
+	})
+}
+
+
+// ----------------------------------------------
+fn read_inner_classes(constants : &[Constant], reader : &mut Reader) ->
+	Result<~[InnerClass], ~str>
+{
+	let mut v : ~[InnerClass] = ~[];
+	for _ in range(0, reader.read_be_u16()) {
+		let inner = match ClassLoader::resolve_class_cpool_entry(constants,
+			reader.read_be_u16() as uint) {
+			Err(s) => return Err(s),
+			Ok(c) => c
+		};
+
+		let outer_index = reader.read_be_u16() as uint;
+		let outer = if outer_index == 0 {
+			None
+		}
+		else {
+			match ClassLoader::resolve_class_cpool_entry(constants, outer_index) {
+				Err(s) => return Err(s),
+				Ok(c) => Some(c)
+			}
+		};
+
+		// anonymous classes have no name
+		let name_index = reader.read_be_u16() as uint;
+		let name = if name_index == 0 {
+			None
+		}
+		else {
+			match ClassLoader::resolve_name_cpool_entry(constants, name_index) {
+				Err(s) => return Err(s),
+				Ok(n) => Some(n)
+			}
+		};
+
+		v.push(InnerClass {
+			inner_class : inner,
+			outer_class : outer,
+			inner_name : name,
+			access : reader.read_be_u16() as uint
+		});
+	}
+	Ok(v)
+}
+
+
+// ----------------------------------------------
+fn read_local_variables(constants : &[Constant], reader : &mut Reader) ->
+	Result<~[LocalVariable], ~str>
+{
+	let mut v : ~[LocalVariable] = ~[];
+	for _ in range(0, reader.read_be_u16()) {
+		let start_pc = reader.read_be_u16() as uint;
+		let length = reader.read_be_u16() as uint;
+		let name = match ClassLoader::resolve_name_cpool_entry(constants,
+			reader.read_be_u16() as uint) {
+			Err(s) => return Err(s),
+			Ok(n) => n
+		};
+		let desc = match ClassLoader::resolve_name_cpool_entry(constants,
+			reader.read_be_u16() as uint) {
+			Err(s) => return Err(s),
+			Ok(n) => n
+		};
+		v.push(LocalVariable {
+			start_pc : start_pc,
+			length : length,
+			name : name,
+			desc : desc,
+			index : reader.read_be_u16() as uint
+		});
+	}
+	Ok(v)
+}
+
+
+// ----------------------------------------------
+fn read_parameter_annotations(constants : &[Constant], reader : &mut Reader) ->
+	Result<~[~[Annotation]], ~str>
+{
+	let mut v : ~[~[Annotation]] = ~[];
+	for _ in range(0, reader.read_u8()) {
+		match read_annotations(constants, reader) {
+			Err(s) => return Err(s),
+			Ok(a) => v.push(a)
+		}
+	}
+	Ok(v)
+}
+
+
+// ----------------------------------------------
+fn read_annotations(constants : &[Constant], reader : &mut Reader) ->
+	Result<~[Annotation], ~str>
+{
+	let mut v : ~[Annotation] = ~[];
+	for _ in range(0, reader.read_be_u16()) {
+		match read_annotation(constants, reader) {
+			Err(s) => return Err(s),
+			Ok(a) => v.push(a)
+		}
+	}
+	Ok(v)
+}
+
+
+// ----------------------------------------------
+fn read_annotation(constants : &[Constant], reader : &mut Reader) ->
+	Result<Annotation, ~str>
+{
+	let type_desc = match ClassLoader::resolve_name_cpool_entry(constants,
+		reader.read_be_u16() as uint) {
+		Err(s) => return Err(s),
+		Ok(n) => n
+	};
+
+	let mut elements : ~[(~str, ElementValue)] = ~[];
+	for _ in range(0, reader.read_be_u16()) {
+		let name = match ClassLoader::resolve_name_cpool_entry(constants,
+			reader.read_be_u16() as uint) {
+			Err(s) => return Err(s),
+			Ok(n) => n
+		};
+		match read_element_value(constants, reader) {
+			Err(s) => return Err(s),
+			Ok(ev) => elements.push((name, ev))
+		}
+	}
+
+	Ok(Annotation {
+		type_desc : type_desc,
+		elements : elements
+	})
+}
+
+
+// ----------------------------------------------
+fn read_element_value(constants : &[Constant], reader : &mut Reader) ->
+	Result<ElementValue, ~str>
+{
+	let tag = reader.read_u8() as char;
+	Ok(match tag {
+		'B' | 'C' | 'D' | 'F' | 'I' | 'J' | 'S' | 'Z' | 's' =>
+			EV_Const(tag, reader.read_be_u16() as uint),
+		'e' => {
+			let type_name = match ClassLoader::resolve_name_cpool_entry(constants,
+				reader.read_be_u16() as uint) {
+				Err(s) => return Err(s),
+				Ok(n) => n
+			};
+			match ClassLoader::resolve_name_cpool_entry(constants, reader.read_be_u16() as uint) {
+				Err(s) => return Err(s),
+				Ok(n) => EV_Enum(type_name, n)
+			}
+		},
+		'c' => match ClassLoader::resolve_name_cpool_entry(constants, reader.read_be_u16() as uint) {
+			Err(s) => return Err(s),
+			Ok(n) => EV_Class(n)
+		},
+		'@' => match read_annotation(constants, reader) {
+			Err(s) => return Err(s),
+			Ok(a) => EV_Annotation(a)
+		},
+		'[' => {
+			let mut v : ~[ElementValue] = ~[];
+			for _ in range(0, reader.read_be_u16()) {
+				match read_element_value(constants, reader) {
+					Err(s) => return Err(s),
+					Ok(ev) => v.push(ev)
+				}
+			}
+			EV_Array(v)
+		},
+		_ => return Err(format!("unrecognized element value tag: {}", tag))
+	})
+}
+
+
+// ----------------------------------------------
+fn read_record_components(constants : &[Constant], reader : &mut Reader) ->
+	Result<~[RecordComponent], ~str>
+{
+	let mut v : ~[RecordComponent] = ~[];
+	for _ in range(0, reader.read_be_u16()) {
+		let name = match ClassLoader::resolve_name_cpool_entry(constants,
+			reader.read_be_u16() as uint) {
+			Err(s) => return Err(s),
+			Ok(n) => n
+		};
+		let desc = match ClassLoader::resolve_name_cpool_entry(constants,
+			reader.read_be_u16() as uint) {
+			Err(s) => return Err(s),
+			Ok(n) => n
+		};
+		match read_attributes(constants, reader, AC_RecordComponent) {
+			Err(s) => return Err(s),
+			Ok(attrs) => v.push(RecordComponent {
+				name : name,
+				desc : desc,
+				attributes : attrs
+			})
+		}
+	}
+	Ok(v)
+}
+
+
+// ----------------------------------------------
+// Resolve a CONSTANT_NameAndType entry to a (name, descriptor) tuple
+fn resolve_name_and_type(constants : &[Constant], oneb_index : uint) ->
+	Result<(~str, ~str), ~str>
+{
+	if oneb_index == 0 || oneb_index > constants.len() {
+		return Err(format!("constant pool index out of range: {}", oneb_index));
+	}
+	match constants[oneb_index - 1] {
+		CONSTANT_nameandtype_info(name_idx, desc_idx) => {
+			let name = match ClassLoader::resolve_name_cpool_entry(constants, name_idx as uint) {
+				Err(s) => return Err(s),
+				Ok(n) => n
+			};
+			match ClassLoader::resolve_name_cpool_entry(constants, desc_idx as uint) {
+				Err(s) => Err(s),
+				Ok(d) => Ok((name, d))
+			}
+		},
+		_ => Err(~"not a CONSTANT_NameAndType entry"),
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use std::io::{BufReader};
+	use attribute::*;
+	use def::*;
+
+	fn test_constants() -> ~[Constant] {
+		~[
+			CONSTANT_utf8_info(~"SourceFile"),
+			CONSTANT_utf8_info(~"Foo.java"),
+			CONSTANT_utf8_info(~"FancyVendorAttribute"),
+			CONSTANT_utf8_info(~"LineNumberTable"),
+		]
+	}
+
+
+	#[test]
+	fn test_attributes_known_and_unknown() {
+		let bytes = ~[
+			0x00, 0x02,
+			// SourceFile -> "Foo.java"
+			0x00, 0x01, 0x00, 0x00, 0x00, 0x02, 0x00, 0x02,
+			// unknown attribute with 3 bytes of payload
+			0x00, 0x03, 0x00, 0x00, 0x00, 0x03, 0xDE, 0xAD, 0xBF
+		];
+		let mut reader = BufReader::new(bytes);
+		let attrs = read_attributes(test_constants(), &mut reader as &mut Reader, AC_Class).unwrap();
+		assert_eq!(attrs.len(), 2);
+
+		match attrs[0] {
+			ATTR_SourceFile(ref s) => assert_eq!(*s, ~"Foo.java"),
+			_ => assert!(false)
+		}
+		match attrs[1] {
+			ATTR_Unknown(ref name, ref raw) => {
+				assert_eq!(*name, ~"FancyVendorAttribute");
+				assert_eq!(*raw, ~[0xDE, 0xAD, 0xBF]);
+			},
+			_ => assert!(false)
+		}
+		assert!(reader.eof());
+	}
+
+
+	#[test]
+	fn test_attributes_wrong_context() {
+		// a LineNumberTable outside of a Code attribute is kept as is
+		let bytes = ~[
+			0x00, 0x01,
+			0x00, 0x04, 0x00, 0x00, 0x00, 0x06, 0x00, 0x01, 0x00, 0x00, 0x00, 0x07
+		];
+		let mut reader = BufReader::new(bytes);
+		let attrs = read_attributes(test_constants(), &mut reader as &mut Reader, AC_Method).unwrap();
+		match attrs[0] {
+			ATTR_Unknown(ref name, ref raw) => {
+				assert_eq!(*name, ~"LineNumberTable");
+				assert_eq!(raw.len(), 6);
+			},
+			_ => assert!(false)
+		}
+	}
+
+
+	#[test]
+	fn test_attributes_length_mismatch() {
+		// SourceFile with one byte of trailing garbage
+		let bytes = ~[
+			0x00, 0x01,
+			0x00, 0x01, 0x00, 0x00, 0x00, 0x03, 0x00, 0x02, 0x00
+		];
+		let mut reader = BufReader::new(bytes);
+		assert!(read_attributes(test_constants(), &mut reader as &mut Reader, AC_Class).is_err());
+
+		// SourceFile that is too short
+		let bytes2 = ~[
+			0x00, 0x01,
+			0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00
+		];
+		let mut reader2 = BufReader::new(bytes2);
+		assert!(read_attributes(test_constants(), &mut reader2 as &mut Reader, AC_Class).is_err());
+	}
+}
@@ -28,6 +28,12 @@ use extra::arc::{Arc, MutexArc};
 use def::Constant;
 use method::JavaMethod;
 use field::JavaField;
+use attribute::{Attribute, ATTR_SourceFile};
+use classloaderror::ClassLoadError;
+use layout::{FieldLayout};
+use dispatch::{DispatchTables};
+use def::{ACC_INTERFACE};
+use field::{FieldDescriptor, FD_BaseType, FD_ObjectType, FD_ArrayType};
 
 
 
@@ -40,14 +46,14 @@ pub type JavaClassRef = Arc<JavaClass>;
 // due to cyclic dependencies between classes.
 pub struct JavaClassFutureRef
 {
-	priv inner : Future<Result<JavaClassRef,~str>>,
+	priv inner : Future<Result<JavaClassRef,ClassLoadError>>,
 }
 
 
 impl JavaClassFutureRef
 {
 	// ----------------------------------------------
-	pub fn new(val : Future<Result<JavaClassRef,~str>>) -> JavaClassFutureRef
+	pub fn new(val : Future<Result<JavaClassRef,ClassLoadError>>) -> JavaClassFutureRef
 	{
 		JavaClassFutureRef {
 			inner : val,
@@ -55,16 +61,16 @@ impl JavaClassFutureRef
 	}
 
 	// ----------------------------------------------
-	pub fn new_error(s : &str) -> JavaClassFutureRef
+	pub fn new_error(e : ClassLoadError) -> JavaClassFutureRef
 	{
 		JavaClassFutureRef {
-			inner : Future::from_value(Err(s.into_owned()))
+			inner : Future::from_value(Err(e))
 		}
 	}
 
 	// ----------------------------------------------
 	// Awaits the class being loaded and returns the result
-	pub fn await(&mut self) -> Result<JavaClassRef,~str> {
+	pub fn await(&mut self) -> Result<JavaClassRef,ClassLoadError> {
 		return self.inner.get_ref().clone();
 	}
 
@@ -76,38 +82,510 @@ impl JavaClassFutureRef
 }
 
 
-// internal representation of a loaded java class.
-// TODO: add different states - linked y/n etc
+// internal representation of a loaded java class. JavaClass instances
+// are immutable and shared between threads. The lifecycle state of a
+// class (i.e. whether it has been initialized) is global state that is 
+// kept by the object broker, see classinit.rs.
 pub struct JavaClass {
 	priv name : ~str,
 	priv attrs : uint,
 	priv constants : ~[Constant],
 	priv parents : ~[ JavaClassRef ],
-	priv methods : ~HashMap<~str, ~JavaMethod>,
 
-	// TODO: runtime layout table constructed for instance fields and class fields
+	// methods declared by the class, and an index to look
+	// them up by (name, descriptor) pairs.
+	priv methods : ~[JavaMethod],
+	priv method_index : ~HashMap<(~str, ~str), uint>,
+
+	// fields as declared in the class file, separated by whether
+	// they are class (static) or instance fields. Inherited fields
+	// are not included.
+	priv static_fields : ~[JavaField],
+	priv instance_fields : ~[JavaField],
+
+	// class-level attributes, i.e. SourceFile or InnerClasses
+	priv attributes : ~[Attribute],
+
+	// runtime layout of instance fields, including inherited fields,
+	// and of the static fields declared by this class.
+	priv instance_layout : FieldLayout,
+	priv static_layout : FieldLayout,
+
+	// vtable and itables for invoking methods on instances
+	priv dispatch : DispatchTables,
+
+	// for array classes, the type of the array elements
+	priv component_type : Option<FieldDescriptor>,
 }
 
 
 impl JavaClass {
 
 	// ----------------------------------------------
-	pub fn new(name : &str, constants : ~[Constant], parents : ~[ JavaClassRef ] ) 
+	pub fn new(name : &str, access : uint, constants : ~[Constant], 
+		parents : ~[ JavaClassRef ], fields : ~[JavaField], methods : ~[JavaMethod],
+		attributes : ~[Attribute]) 
 	-> JavaClass 
 	{
+		let (static_fields, instance_fields) = fields.partition(|f : &JavaField| f.is_static());
+
+		// for classes, the first parent is the super class. Interfaces
+		// list only super interfaces, none of which has instance fields.
+		let instance_layout = if (access & ACC_INTERFACE) == 0 && parents.len() > 0 {
+			FieldLayout::new_instance_layout(name, Some(parents[0].get().get_instance_layout()), 
+				instance_fields)
+		}
+		else {
+			FieldLayout::new_instance_layout(name, None, instance_fields)
+		};
+		let static_layout = FieldLayout::new_static_layout(name, static_fields);
+
+		let dispatch = DispatchTables::new(name, access, parents, methods);
+
+		let mut method_index = ~HashMap::with_capacity(methods.len());
+		for (i, m) in methods.iter().enumerate() {
+			method_index.insert((m.get_name().clone(), m.get_desc().clone()), i);
+		}
+
 		JavaClass { 
 			name: name.into_owned(), 
-			attrs: 0, 
-			methods : ~HashMap::with_capacity(16),
+			attrs: access, 
+			methods : methods,
+			method_index : method_index,
 			constants : constants,
 			parents : parents,
+			static_fields : static_fields,
+			instance_fields : instance_fields,
+			attributes : attributes,
+			instance_layout : instance_layout,
+			static_layout : static_layout,
+			dispatch : dispatch,
+			component_type : None,
 		}
 	}
 
 
+	// ----------------------------------------------
+	// Construct an array class. Array classes have no class file, 
+	// `name` is their descriptor (i.e. [I or [Ljava.lang.String;) and
+	// `parents` are java.lang.Object, java.lang.Cloneable and 
+	// java.io.Serializable (JVMS 4.10.1.2).
+	pub fn new_array(name : &str, access : uint, parents : ~[ JavaClassRef ], 
+		component_type : FieldDescriptor) 
+	-> JavaClass 
+	{
+		let mut jclass = JavaClass::new(name, access, ~[], parents, ~[], ~[], ~[]);
+		jclass.component_type = Some(component_type);
+		jclass
+	}
+
+
 	// ----------------------------------------------
 	pub fn get_name<'a>(&'a self) -> &'a ~str {
 		return &self.name
 	} 
-}
 
+
+	// ----------------------------------------------
+	// Get the name of the package the class is in, which is empty
+	// for classes in the unnamed package.
+	pub fn get_package_name<'a>(&'a self) -> &'a str {
+		match self.name.rfind('.') {
+			Some(pos) => self.name.slice_to(pos),
+			None => ""
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Check if two classes are in the same runtime package. All classes
+	// are loaded by the same loader, so this only compares package names.
+	pub fn is_same_package(&self, other : &JavaClass) -> bool {
+		self.get_package_name() == other.get_package_name()
+	}
+
+
+	// ----------------------------------------------
+	// Get the access flags of the class (ACC_XXX)
+	pub fn get_access(&self) -> uint {
+		self.attrs
+	}
+
+
+	// ----------------------------------------------
+	// Get the super class of the class. This is None for interfaces
+	// and for java.lang.Object.
+	pub fn get_superclass<'a>(&'a self) -> Option<&'a JavaClassRef> {
+		if (self.attrs & ACC_INTERFACE) != 0 || self.parents.len() == 0 {
+			None
+		}
+		else {
+			Some(&self.parents[0])
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Get the constant pool of the class as read from the class
+	// file. Indices into it are zero-based, see rtpool.rs for the
+	// runtime representation.
+	pub fn get_constants<'a>(&'a self) -> &'a ~[Constant] {
+		&self.constants
+	}
+
+
+	// ----------------------------------------------
+	pub fn get_attributes<'a>(&'a self) -> &'a ~[Attribute] {
+		&self.attributes
+	}
+
+
+	// ----------------------------------------------
+	// Get the name of the source file the class was compiled from,
+	// if the class has a [SourceFile] attribute.
+	pub fn get_source_file<'a>(&'a self) -> Option<&'a ~str> {
+		for a in self.attributes.iter() {
+			match *a {
+				ATTR_SourceFile(ref s) => return Some(s),
+				_ => ()
+			}
+		}
+		None
+	}
+
+
+	// ----------------------------------------------
+	// Get all static fields declared by this class
+	pub fn get_static_fields<'a>(&'a self) -> &'a ~[JavaField] {
+		&self.static_fields
+	}
+
+
+	// ----------------------------------------------
+	// Get all instance fields declared by this class. This
+	// does not include fields declared by super classes.
+	pub fn get_instance_fields<'a>(&'a self) -> &'a ~[JavaField] {
+		&self.instance_fields
+	}
+
+
+	// ----------------------------------------------
+	// Lookup a static field declared by this class by name
+	pub fn find_static_field<'a>(&'a self, name : &str) -> Option<&'a JavaField> {
+		self.static_fields.iter().find(|f| f.get_name().as_slice() == name)
+	}
+
+
+	// ----------------------------------------------
+	// Lookup an instance field declared by this class by name
+	pub fn find_instance_field<'a>(&'a self, name : &str) -> Option<&'a JavaField> {
+		self.instance_fields.iter().find(|f| f.get_name().as_slice() == name)
+	}
+
+
+	// ----------------------------------------------
+	// Get the layout of all instance fields, including those
+	// declared by super classes.
+	pub fn get_instance_layout<'a>(&'a self) -> &'a FieldLayout {
+		&self.instance_layout
+	}
+
+
+	// ----------------------------------------------
+	// Get the layout of the static fields declared by this class
+	pub fn get_static_layout<'a>(&'a self) -> &'a FieldLayout {
+		&self.static_layout
+	}
+
+
+	// ----------------------------------------------
+	// Get the vtable and itables of the class, see dispatch.rs
+	pub fn get_dispatch_tables<'a>(&'a self) -> &'a DispatchTables {
+		&self.dispatch
+	}
+
+
+	// ----------------------------------------------
+	// Get all methods declared by this class
+	pub fn get_methods<'a>(&'a self) -> &'a ~[JavaMethod] {
+		&self.methods
+	}
+
+
+	// ----------------------------------------------
+	// Lookup the index of a method declared by this class given its 
+	// name and descriptor. The index is stable for the lifetime of
+	// the class and can be used with get_method_by_index().
+	pub fn find_method_index(&self, name : &str, desc : &str) -> Option<uint> {
+		match self.method_index.find(&(name.into_owned(), desc.into_owned())) {
+			Some(idx) => Some(*idx),
+			None => None
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Lookup a method declared by this class given its name and descriptor
+	pub fn find_method<'a>(&'a self, name : &str, desc : &str) -> Option<&'a JavaMethod> {
+		match self.find_method_index(name, desc) {
+			Some(idx) => Some(&self.methods[idx]),
+			None => None
+		}
+	}
+
+
+	// ----------------------------------------------
+	pub fn get_method_by_index<'a>(&'a self, idx : uint) -> &'a JavaMethod {
+		&self.methods[idx]
+	}
+
+
+	// ----------------------------------------------
+	// Get the interfaces directly implemented by a class, or the
+	// direct super interfaces of an interface.
+	pub fn get_interfaces<'a>(&'a self) -> &'a [JavaClassRef] {
+		match self.get_superclass() {
+			Some(_) => self.parents.slice_from(1),
+			None => self.parents.slice_from(0)
+		}
+	}
+
+
+	// ----------------------------------------------
+	#[inline]
+	pub fn is_interface(&self) -> bool {
+		(self.attrs & ACC_INTERFACE) != 0
+	}
+
+
+	// ----------------------------------------------
+	#[inline]
+	pub fn is_array(&self) -> bool {
+		self.component_type.is_some()
+	}
+
+
+	// ----------------------------------------------
+	// Get the element type of an array class, None for other classes
+	pub fn get_component_type<'a>(&'a self) -> &'a Option<FieldDescriptor> {
+		&self.component_type
+	}
+
+
+	// ----------------------------------------------
+	// Get the type of references to instances of the class as a
+	// field descriptor, i.e. for use with is_type_assignable()
+	pub fn get_type_descriptor(&self) -> FieldDescriptor {
+		match self.component_type {
+			Some(ref component) => FD_ArrayType(~component.clone()),
+			None => FD_ObjectType(self.name.clone())
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Get the name of the array class whose elements are of this class
+	pub fn get_array_class_name(&self) -> ~str {
+		FD_ArrayType(~self.get_type_descriptor()).get_class_name().unwrap()
+	}
+
+
+	// ----------------------------------------------
+	// Check if the class is the class with the given name or
+	// a (direct or indirect) subclass of it.
+	pub fn is_subclass_of(&self, name : &str) -> bool {
+		if self.name.as_slice() == name {
+			return true;
+		}
+		match self.get_superclass() {
+			Some(c) => c.get().is_subclass_of(name),
+			None => false
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Check if the class implements the interface with the given name,
+	// either directly, through one of its super classes or through
+	// a super interface. For interfaces, this checks if the given
+	// interface is the interface itself or one of its super interfaces.
+	pub fn implements_interface(&self, name : &str) -> bool {
+		if self.is_interface() && self.name.as_slice() == name {
+			return true;
+		}
+		self.parents.iter().any(|c| c.get().implements_interface(name))
+	}
+
+
+	// ----------------------------------------------
+	// Check if a reference to an instance of this class can be assigned
+	// to a variable of the given class or interface type.
+	pub fn is_assignable_to(&self, name : &str) -> bool {
+		name == "java.lang.Object" || self.is_subclass_of(name) || self.implements_interface(name)
+	}
+
+
+	// ----------------------------------------------
+	// Check if a reference to an instance of the given class can be 
+	// assigned to a variable of the type of this class, array types
+	// included. `resolve` is used as by is_type_assignable().
+	pub fn is_assignable_from(&self, other : &JavaClass, resolve : |&str| -> Option<JavaClassRef>) -> bool {
+		JavaClass::is_type_assignable(&self.get_type_descriptor(), &other.get_type_descriptor(), resolve)
+	}
+
+
+	// ----------------------------------------------
+	// Check if a value of type `source` can be assigned to a variable of
+	// type `target`, following the rules for checkcast and instanceof
+	// from JVMS 6.5. `resolve` is used to obtain (loaded) classes by name
+	// and is only invoked for the source type.
+	pub fn is_type_assignable(target : &FieldDescriptor, source : &FieldDescriptor, 
+		resolve : |&str| -> Option<JavaClassRef>) -> bool {
+
+		match (target, source) {
+			(&FD_BaseType(t), &FD_BaseType(s)) => t == s,
+			(&FD_ObjectType(ref t), &FD_ObjectType(ref s)) => {
+				match resolve(s.as_slice()) {
+					Some(c) => c.get().is_assignable_to(t.as_slice()),
+					None => false
+				}
+			},
+			// arrays implement Cloneable and Serializable, see JVMS 4.10.1.2
+			(&FD_ObjectType(ref t), &FD_ArrayType(_)) => {
+				match t.as_slice() {
+					"java.lang.Object" | "java.lang.Cloneable" | "java.io.Serializable" => true,
+					_ => false
+				}
+			},
+			(&FD_ArrayType(~FD_BaseType(t)), &FD_ArrayType(~FD_BaseType(s))) => t == s,
+			(&FD_ArrayType(~FD_BaseType(_)), &FD_ArrayType(_)) | 
+			(&FD_ArrayType(_), &FD_ArrayType(~FD_BaseType(_))) => false,
+			(&FD_ArrayType(ref t), &FD_ArrayType(ref s)) => 
+				JavaClass::is_type_assignable(&**t, &**s, resolve),
+			_ => false
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Lookup a field by name and descriptor as per field resolution
+	// (JVMS 5.4.3.2): fields declared by the class come first, then
+	// the super interfaces are searched recursively and finally the
+	// super class. Returns the declaring class along with the field.
+	pub fn lookup_field<'a>(&'a self, name : &str, desc : &str) 
+		-> Option<(&'a JavaClass, &'a JavaField)> {
+
+		let matches = |f : &&JavaField| {
+			f.get_name().as_slice() == name && f.get_type().to_str().as_slice() == desc
+		};
+		match self.instance_fields.iter().find(|f| matches(f)) {
+			Some(f) => return Some((self, f)),
+			None => ()
+		}
+		match self.static_fields.iter().find(|f| matches(f)) {
+			Some(f) => return Some((self, f)),
+			None => ()
+		}
+		for c in self.get_interfaces().iter() {
+			match c.get().lookup_field(name, desc) {
+				Some(res) => return Some(res),
+				None => ()
+			}
+		}
+		match self.get_superclass() {
+			Some(c) => c.get().lookup_field(name, desc),
+			None => None
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Lookup a method by name and descriptor as per method resolution
+	// (JVMS 5.4.3.3): the class and its super classes are searched 
+	// first, then the super interfaces. Returns the declaring class
+	// along with the method.
+	pub fn lookup_method<'a>(&'a self, name : &str, desc : &str) 
+		-> Option<(&'a JavaClass, &'a JavaMethod)> {
+
+		match self.lookup_method_in_superclasses(name, desc) {
+			Some(res) => Some(res),
+			None => self.lookup_method_in_interfaces(name, desc)
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Lookup a method by name and descriptor as per interface method 
+	// resolution (JVMS 5.4.3.4). Interfaces do not list java.lang.Object
+	// as a parent, so the final lookup in java.lang.Object that the 
+	// spec mandates is up to the caller.
+	pub fn lookup_interface_method<'a>(&'a self, name : &str, desc : &str) 
+		-> Option<(&'a JavaClass, &'a JavaMethod)> {
+		self.lookup_method_in_interfaces(name, desc)
+	}
+
+
+	// ----------------------------------------------
+	// Find the shared ref for a class given its name, searching a class 
+	// and all of its super classes and super interfaces.
+	pub fn find_class_ref(jclass : &JavaClassRef, name : &str) -> Option<JavaClassRef> {
+		let c = jclass.get();
+		if c.get_name().as_slice() == name {
+			return Some(jclass.clone());
+		}
+		match c.get_superclass() {
+			Some(s) => match JavaClass::find_class_ref(s, name) {
+				Some(res) => return Some(res),
+				None => ()
+			},
+			None => ()
+		}
+		for i in c.get_interfaces().iter() {
+			match JavaClass::find_class_ref(i, name) {
+				Some(res) => return Some(res),
+				None => ()
+			}
+		}
+		None
+	}
+
+
+	// IMPL
+
+
+	// ----------------------------------------------
+	fn lookup_method_in_superclasses<'a>(&'a self, name : &str, desc : &str) 
+		-> Option<(&'a JavaClass, &'a JavaMethod)> {
+
+		match self.find_method(name, desc) {
+			Some(m) => return Some((self, m)),
+			None => ()
+		}
+		match self.get_superclass() {
+			Some(c) => c.get().lookup_method_in_superclasses(name, desc),
+			None => None
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Search the class itself (if it is an interface) and all of its 
+	// super interfaces, including those of super classes.
+	fn lookup_method_in_interfaces<'a>(&'a self, name : &str, desc : &str) 
+		-> Option<(&'a JavaClass, &'a JavaMethod)> {
+
+		if self.is_interface() {
+			match self.find_method(name, desc) {
+				Some(m) => return Some((self, m)),
+				None => ()
+			}
+		}
+		for c in self.parents.iter() {
+			match c.get().lookup_method_in_interfaces(name, desc) {
+				Some(res) => return Some(res),
+				None => ()
+			}
+		}
+		None
+	}
+}
@@ -0,0 +1,303 @@
+// rustyVM - Java VM written in pure Rust
+// Copyright (c) 2013 Alexander Gessler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+
+
+// Global book-keeping for class initialization, see
+// http://docs.oracle.com/javase/specs/jvms/se7/html/jvms-5.html#jvms-5.5
+//
+// Class initialization state is global to the VM and thus, like
+// thread state (see threadmanager.rs), lives in the broker task.
+// Threads request initialization of a class through CLASS_INIT_REQUEST
+// messages. The first thread to do so is told to run the initializer
+// itself, all other threads are parked until the initializing thread
+// reports back and are then told the outcome. Serializing all requests
+// through the broker task gives us the JVMS initialization lock.
+//
+// The broker also keeps the java.lang.Class object of every class, 
+// which threads obtain through CLASS_MIRROR_REQUEST messages in the
+// same way as interned Strings (see stringintern.rs).
+
+use std::hashmap::{HashMap};
+
+use object::{JavaObjectId};
+
+
+// Lifecycle states of a class after loading. Loading itself, up to 
+// the point where a class is linked, is tracked by the ClassLoader
+// (see ClassPending in classloader.rs).
+#[deriving(Eq, Clone, ToStr)]
+pub enum ClassInitState {
+	// super classes and interfaces are loaded and field layouts are 
+	// computed. This is the state of every JavaClass handed out by
+	// the ClassLoader, until initialization is first requested.
+	CIS_Linked,
+
+	// the thread with the given tid is running the class initializer
+	CIS_BeingInitialized(uint),
+
+	// initialization completed, the object holding the static
+	// fields of the class has the given oid.
+	CIS_Initialized(JavaObjectId),
+
+	// initialization failed. Any further attempt to use the class
+	// raises a NoClassDefFoundError.
+	CIS_Erroneous,
+}
+
+
+// OB_CLASS_OP() detail messages
+pub enum ClassOpMessage {
+	// thread a asks for class b to be initialized
+	CLASS_INIT_REQUEST,
+
+	// sent by the broker in response to CLASS_INIT_REQUEST: thread
+	// a is to initialize class b itself. 
+	CLASS_INIT_PROCEED,
+
+	// sent by the broker in response to CLASS_INIT_REQUEST: class b
+	// is initialized and its static fields are held by the given object.
+	CLASS_INIT_DONE(JavaObjectId),
+
+	// sent by the broker in response to CLASS_INIT_REQUEST: class b
+	// is in erroneous state.
+	CLASS_INIT_ERRONEOUS,
+
+	// thread a reports successful initialization of class b, with
+	// the object holding the static fields of the class.
+	CLASS_INIT_COMPLETE(JavaObjectId),
+
+	// thread a reports that initializing class b failed.
+	CLASS_INIT_FAILED,
+
+	// thread a asks for the java.lang.Class object of class b, offering
+	// the given object. The broker keeps the first object offered.
+	CLASS_MIRROR_REQUEST(JavaObjectId),
+
+	// sent by the broker in response to CLASS_MIRROR_REQUEST: the
+	// java.lang.Class object of class b.
+	CLASS_MIRROR_DONE(JavaObjectId),
+}
+
+
+struct ClassInitEntry {
+	state : ClassInitState,
+
+	// threads waiting for initialization to complete
+	waiters : ~[uint],
+
+	// java.lang.Class object of the class, if anyone asked for it yet
+	mirror : Option<JavaObjectId>,
+}
+
+
+pub struct ClassInitManager {
+	priv classes : HashMap<~str, ClassInitEntry>,
+}
+
+
+impl ClassInitManager {
+
+	// ----------------------------------------------
+	pub fn new() -> ClassInitManager {
+		ClassInitManager {
+			classes : HashMap::new(),
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Get the initialization state of a class. Classes for which
+	// initialization has never been requested are CIS_Linked.
+	pub fn get_state(&self, name : &str) -> ClassInitState {
+		match self.classes.find(&name.into_owned()) {
+			Some(entry) => entry.state.clone(),
+			None => CIS_Linked
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Process a message from thread src_tid pertaining to class
+	// `name`. Returns the list of (tid, message) responses that are
+	// to be sent to threads.
+	pub fn process_message(&mut self, src_tid : uint, name : &str, 
+		op : ClassOpMessage) -> ~[(uint, ClassOpMessage)] {
+
+		let entry = self.classes.find_or_insert_with(name.into_owned(), |_| ClassInitEntry {
+			state : CIS_Linked,
+			waiters : ~[],
+			mirror : None,
+		});
+
+		match op {
+			CLASS_INIT_REQUEST => {
+				match entry.state {
+					CIS_Linked => {
+						entry.state = CIS_BeingInitialized(src_tid);
+						~[(src_tid, CLASS_INIT_PROCEED)]
+					},
+					CIS_BeingInitialized(tid) => {
+						// threads keep track of the classes they are 
+						// initializing and do not ask again.
+						assert!(tid != src_tid);
+						entry.waiters.push(src_tid);
+						~[]
+					},
+					CIS_Initialized(oid) => ~[(src_tid, CLASS_INIT_DONE(oid))],
+					CIS_Erroneous => ~[(src_tid, CLASS_INIT_ERRONEOUS)],
+				}
+			},
+
+			CLASS_INIT_COMPLETE(oid) => {
+				assert_eq!(entry.state, CIS_BeingInitialized(src_tid));
+				entry.state = CIS_Initialized(oid);
+				entry.waiters.iter().map(|tid| (*tid, CLASS_INIT_DONE(oid))).collect()
+			},
+
+			CLASS_INIT_FAILED => {
+				assert_eq!(entry.state, CIS_BeingInitialized(src_tid));
+				entry.state = CIS_Erroneous;
+				entry.waiters.iter().map(|tid| (*tid, CLASS_INIT_ERRONEOUS)).collect()
+			},
+
+			CLASS_MIRROR_REQUEST(oid) => {
+				if entry.mirror.is_none() {
+					entry.mirror = Some(oid);
+				}
+				~[(src_tid, CLASS_MIRROR_DONE(entry.mirror.unwrap()))]
+			},
+
+			CLASS_INIT_PROCEED | CLASS_INIT_DONE(_) | CLASS_INIT_ERRONEOUS | CLASS_MIRROR_DONE(_) => 
+				fail!("logic error, message only sent by broker"),
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Handle the death of a thread. Classes that the thread was
+	// initializing become erroneous, as initialization can never
+	// complete. Returns the (tid, class name, message) responses to
+	// be sent to waiting threads. 
+	pub fn remove_thread(&mut self, tid : uint) -> ~[(uint, ~str, ClassOpMessage)] {
+		let mut out = ~[];
+		for (name, entry) in self.classes.mut_iter() {
+			if entry.state == CIS_BeingInitialized(tid) {
+				entry.state = CIS_Erroneous;
+				for w in entry.waiters.iter() {
+					out.push((*w, name.clone(), CLASS_INIT_ERRONEOUS));
+				}
+			}
+		}
+		out
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use classinit::*;
+
+	fn test_single_msg(msgs : ~[(uint, ClassOpMessage)]) -> (uint, ClassOpMessage) {
+		assert_eq!(msgs.len(), 1);
+		msgs.move_iter().next().unwrap()
+	}
+
+	#[test]
+	fn test_classinit_protocol() {
+		let mut m = ClassInitManager::new();
+		assert_eq!(m.get_state("Foo"), CIS_Linked);
+
+		// first thread to ask initializes the class, others wait
+		match test_single_msg(m.process_message(1, "Foo", CLASS_INIT_REQUEST)) {
+			(1, CLASS_INIT_PROCEED) => (),
+			_ => fail!("expected PROCEED")
+		}
+		assert_eq!(m.get_state("Foo"), CIS_BeingInitialized(1));
+		assert_eq!(m.process_message(2, "Foo", CLASS_INIT_REQUEST).len(), 0);
+		assert_eq!(m.process_message(3, "Foo", CLASS_INIT_REQUEST).len(), 0);
+
+		let msgs = m.process_message(1, "Foo", CLASS_INIT_COMPLETE(42));
+		assert_eq!(msgs.len(), 2);
+		for &(tid, ref msg) in msgs.iter() {
+			assert!(tid == 2 || tid == 3);
+			match *msg {
+				CLASS_INIT_DONE(42) => (),
+				_ => fail!("expected DONE")
+			}
+		}
+		assert_eq!(m.get_state("Foo"), CIS_Initialized(42));
+
+		// later requests complete immediately
+		match test_single_msg(m.process_message(4, "Foo", CLASS_INIT_REQUEST)) {
+			(4, CLASS_INIT_DONE(42)) => (),
+			_ => fail!("expected DONE")
+		}
+	}
+
+
+	#[test]
+	fn test_classinit_failure() {
+		let mut m = ClassInitManager::new();
+		m.process_message(1, "Foo", CLASS_INIT_REQUEST);
+		m.process_message(2, "Foo", CLASS_INIT_REQUEST);
+
+		match test_single_msg(m.process_message(1, "Foo", CLASS_INIT_FAILED)) {
+			(2, CLASS_INIT_ERRONEOUS) => (),
+			_ => fail!("expected ERRONEOUS")
+		}
+		assert_eq!(m.get_state("Foo"), CIS_Erroneous);
+
+		match test_single_msg(m.process_message(3, "Foo", CLASS_INIT_REQUEST)) {
+			(3, CLASS_INIT_ERRONEOUS) => (),
+			_ => fail!("expected ERRONEOUS")
+		}
+
+		// the initializing thread dying also fails the class
+		m.process_message(1, "Bar", CLASS_INIT_REQUEST);
+		m.process_message(2, "Bar", CLASS_INIT_REQUEST);
+		let msgs = m.remove_thread(1);
+		assert_eq!(msgs.len(), 1);
+		match msgs[0] {
+			(2, ref name, CLASS_INIT_ERRONEOUS) if *name == ~"Bar" => (),
+			_ => fail!("expected ERRONEOUS")
+		}
+		assert_eq!(m.get_state("Bar"), CIS_Erroneous);
+	}
+
+
+	#[test]
+	fn test_classinit_mirror() {
+		let mut m = ClassInitManager::new();
+
+		// the first Class object offered is kept
+		match test_single_msg(m.process_message(1, "Foo", CLASS_MIRROR_REQUEST(10))) {
+			(1, CLASS_MIRROR_DONE(10)) => (),
+			_ => fail!("expected MIRROR_DONE")
+		}
+		match test_single_msg(m.process_message(2, "Foo", CLASS_MIRROR_REQUEST(20))) {
+			(2, CLASS_MIRROR_DONE(10)) => (),
+			_ => fail!("expected MIRROR_DONE")
+		}
+
+		// independently of initialization
+		assert_eq!(m.get_state("Foo"), CIS_Linked);
+	}
+}
@@ -20,10 +20,9 @@
 //
 
 
-use std::hashmap::{HashMap};
+use std::hashmap::{HashMap, HashSet};
 use std::path::{PosixPath};
-use std::io::{result, IoError, BufReader};
-use std::str::{from_utf8_owned};
+use std::io::{result, IoError, BufReader, Seek};
 
 use extra::future::{Future};
 use extra::arc::{Arc, MutexArc};
@@ -31,8 +30,14 @@ use extra::arc::{Arc, MutexArc};
 use def::*;
 use class::{JavaClass, JavaClassRef, JavaClassFutureRef};
 use classpath::{ClassPath};
-use code::{CodeBlock, ExceptionHandler};
+use classloaderror::{ClassLoadError, CLE_NoClassDefFoundError, CLE_IncompatibleClassChangeError,
+	CLE_UnsupportedClassVersionError, CLE_ClassCircularityError};
 use method::{JavaMethod};
+use attribute;
+use attribute::{AC_Class, AC_Field, AC_Method};
+use mutf8;
+use field::{JavaField, ConstantValue, CV_Integer, CV_Float, CV_Long, CV_Double, CV_String};
+use field::{FD_ArrayType};
 
 
 // Abstract trait to describe a class loader's basic behaviour
@@ -41,18 +46,19 @@ pub trait AbstractClassLoader {
 	// ----------------------------------------------
 	// Asynchronously loads a class with the given name using
 	// an implementation-defined method to locate the class file. In 
-	// case of failure, a simple string error message is returned.
+	// case of failure, a ClassLoadError describing the cause is returned.
 	//
 	// See ClassLoader::add_from_classfile() for the default impl.
 	fn load(&mut self, name : &str) -> JavaClassFutureRef;
 
 
 	// ----------------------------------------------
-	// Asynchronously loads a class with the given name from
-	// the provided byte buffer.
+	// Asynchronously loads a class from the provided byte buffer. If a
+	// name is given, loading fails unless the class file defines a class
+	// of that name. Otherwise, the name is taken from the class file.
 	//
 	// See ClassLoader::add_from_bytes() for the default impl.
-	fn load_from_bytes(&mut self, name : &str, bytes : ~[u8]) -> JavaClassFutureRef;
+	fn load_from_bytes(&mut self, name : Option<&str>, bytes : ~[u8]) -> JavaClassFutureRef;
 }
 
 
@@ -61,7 +67,12 @@ pub trait AbstractClassLoader {
 // their internal state through a concurrent hash map.
 pub struct ClassLoader {
 	priv classpath : ClassPath,
-	priv ClassTableRef : ClassTableRef
+	priv ClassTableRef : ClassTableRef,
+
+	// range of class file major versions that are accepted,
+	// both ends inclusive. 
+	priv min_major_version : uint,
+	priv max_major_version : uint,
 }
 
 
@@ -71,8 +82,11 @@ impl AbstractClassLoader for ClassLoader {
 		return self.add_from_classfile(name);
 	}
 
-	fn load_from_bytes(&mut self, name : &str, bytes : ~[u8]) -> JavaClassFutureRef	{
-		return self.add_from_bytes(name, bytes);
+	fn load_from_bytes(&mut self, name : Option<&str>, bytes : ~[u8]) -> JavaClassFutureRef	{
+		match name {
+			Some(n) => self.add_from_bytes(n, bytes),
+			None => self.add_from_bytes_derive_name(bytes)
+		}
 	}
 }
 
@@ -80,16 +94,43 @@ impl AbstractClassLoader for ClassLoader {
 
 enum JavaClassOrWaitQueue {
 	ClassLoaded(JavaClassRef),
-	ClassPending(~[Chan<Result<JavaClassRef, ~str>>]),
+	ClassPending(~[Chan<Result<JavaClassRef, ClassLoadError>>]),
+
+	// loading failed. Per JVMS 5.3, subsequent attempts to load
+	// the same class fail with the same error.
+	ClassFailed(ClassLoadError),
 }
 
 // table of java classes indexed by fully qualified name
-type ClassTable = HashMap<~str, JavaClassOrWaitQueue>;
+struct ClassTable {
+	classes : HashMap<~str, JavaClassOrWaitQueue>,
+
+	// for every class that is being loaded and currently blocks on 
+	// loading one of its super classes or interfaces, the name of 
+	// that parent. Used to detect circular class hierarchies,
+	// which would otherwise deadlock.
+	waits_for : HashMap<~str, ~str>,
+}
+
 type ClassTableRef = MutexArc<ClassTable>;
 
 
 static INITIAL_CLASSLOADER_CAPACITY : uint = 1024;
 
+// JDK 1.1 up to and including Java SE 8
+pub static DEFAULT_MIN_MAJOR_VERSION : uint = 45;
+pub static DEFAULT_MAX_MAJOR_VERSION : uint = 52;
+
+// reference_kind values for CONSTANT_MethodHandle, see
+// http://docs.oracle.com/javase/specs/jvms/se7/html/jvms-5.html#jvms-5.4.3.5
+static REF_getField : u8 = 1;
+static REF_putStatic : u8 = 4;
+static REF_invokeVirtual : u8 = 5;
+static REF_invokeStatic : u8 = 6;
+static REF_invokeSpecial : u8 = 7;
+static REF_newInvokeSpecial : u8 = 8;
+static REF_invokeInterface : u8 = 9;
+
 impl ClassLoader {
 
 	// ----------------------------------------------
@@ -98,7 +139,10 @@ impl ClassLoader {
 	pub fn new_from_string(classpath : &str) -> ClassLoader {
 		ClassLoader::new(
 				ClassPath::new_from_string(classpath),
-				MutexArc::new(HashMap::with_capacity(INITIAL_CLASSLOADER_CAPACITY))
+				MutexArc::new(ClassTable {
+					classes : HashMap::with_capacity(INITIAL_CLASSLOADER_CAPACITY),
+					waits_for : HashMap::new(),
+				})
 		)
 	}
 
@@ -111,10 +155,30 @@ impl ClassLoader {
 		ClassLoader {
 			classpath : classpath,
 			ClassTableRef : ClassTableRef,
+			min_major_version : DEFAULT_MIN_MAJOR_VERSION,
+			max_major_version : DEFAULT_MAX_MAJOR_VERSION,
 		}
 	}
 
 
+	// ----------------------------------------------
+	// Set the range of class file major versions that this loader
+	// accepts. Class files outside this range fail to load with
+	// an UnsupportedClassVersionError.
+	pub fn set_supported_major_versions(&mut self, min : uint, max : uint) {
+		assert!(min <= max);
+		self.min_major_version = min;
+		self.max_major_version = max;
+	}
+
+
+	// ----------------------------------------------
+	// Get the (inclusive) range of supported class file major versions
+	pub fn get_supported_major_versions(&self) -> (uint, uint) {
+		(self.min_major_version, self.max_major_version)
+	}
+
+
 	// ----------------------------------------------
 	// Get the immutable classpath that backs this classloader
 	pub fn get_classpath(&self) -> ClassPath
@@ -132,7 +196,7 @@ impl ClassLoader {
 		let cname = name.into_owned();
 		unsafe { 
 			self.ClassTableRef.unsafe_access(|table : &mut ClassTable| {
-				match table.find(&cname) {
+				match table.classes.find(&cname) {
 					Some(&ClassLoaded(ref elem)) => Some((*elem).clone()),
 					_ => None
 				}
@@ -145,7 +209,8 @@ impl ClassLoader {
 	// Load a class given its fully name. The .class file for the class is
 	// located in the classpath and loaded. Afterwards, the java class is
 	// prepared for use with the VM and ultimatively returned. Loading is
-	// asynchronous.
+	// asynchronous. Array classes (i.e. [I or [Ljava.lang.String;) have
+	// no class file and are synthesized instead.
 	pub fn add_from_classfile(&mut self, name : &str) -> JavaClassFutureRef {
 		// do nothing if the class is already loaded,
 		// if it is already being loaded, add ourselves to the list of waiters
@@ -156,30 +221,47 @@ impl ClassLoader {
 			return res.unwrap();
 		}
 
-		debug!("start async loading of class {} from a classpath location", name);
+		if name.starts_with("[") {
+			return self.add_array_class(name);
+		}
 
-		// TODO: inform waiters also if loading fails
+		debug!("start async loading of class {} from a classpath location", name);
 
 		let self_clone_outer = self.clone();
 		let fut = do Future::spawn {
 			// TODO: if we don't clone() twice, borrowch complains.
 			// May be resolved through https://github.com/mozilla/rust/issues/10617
 			let mut self_clone = self_clone_outer.clone();
-			match self_clone.classpath.locate_and_read(cname) {
-				None => Err(~"failed to locate class file for " + cname),
-				Some(bytes) => {
+			let res = match self_clone.classpath.locate_and_read(cname) {
+				Err(e) => Err(e),
+				Ok(bytes) => {
 					self_clone.intern_add_from_classfile_bytes(cname, bytes)
 				}
-			}
+			};
+			self_clone.register_failure_if_err(cname, res)
 		};
 		JavaClassFutureRef::new(fut)
 	}
 
 
+	// ----------------------------------------------
+	// Load a class given a byte stream containing a valid .class file
+	// whose name is not known in advance. The name under which the
+	// class is added to the class hierarchy is the name of the class
+	// defined by the class file.
+	pub fn add_from_bytes_derive_name(&mut self, bytes : ~[u8]) -> JavaClassFutureRef {
+		match ClassLoader::peek_class_name(bytes) {
+			Err(e) => JavaClassFutureRef::new_error(e),
+			Ok(name) => self.add_from_bytes(name, bytes)
+		}
+	}
+
+
 	// ----------------------------------------------
 	// Load a class given a byte stream containing a valid .class file.
 	// The given name is the fully qualified name name under which the class 
-	// is added to the class hierarchy.
+	// is added to the class hierarchy. It must match the name of the
+	// class defined by the class file.
 	pub fn add_from_bytes(&mut self, name : &str, bytes : ~[u8]) -> JavaClassFutureRef {
 		let res = self.check_is_present_or_enqueue(name);
 		if res.is_some() {
@@ -188,8 +270,6 @@ impl ClassLoader {
 
 		debug!("start async loading of class {} from a memory location", name);
 
-		// TODO: inform waiters also if loading fails
-
 		let self_clone_outer = self.clone();
 		let cname = name.into_owned();
 
@@ -197,7 +277,8 @@ impl ClassLoader {
 			// TODO: if we don't clone() twice, borrowch complains.
 			// May be resolved through https://github.com/mozilla/rust/issues/10617
 			let mut self_clone = self_clone_outer.clone();
-			self_clone.intern_add_from_classfile_bytes(cname, bytes)
+			let res = self_clone.intern_add_from_classfile_bytes(cname, bytes);
+			self_clone.register_failure_if_err(cname, res)
 		};
 		JavaClassFutureRef::new(fut)
 	}
@@ -206,16 +287,66 @@ impl ClassLoader {
 	// IMPL
 
 
+	// ----------------------------------------------
+	// Asynchronously synthesize an array class. The caller must have
+	// enqueued the class via check_is_present_or_enqueue().
+	fn add_array_class(&mut self, name : &str) -> JavaClassFutureRef {
+		debug!("start async creation of array class {}", name);
+
+		let self_clone_outer = self.clone();
+		let cname = name.into_owned();
+
+		let fut = do Future::spawn {
+			let mut self_clone = self_clone_outer.clone();
+			let res = self_clone.intern_add_array_class(cname);
+			self_clone.register_failure_if_err(cname, res)
+		};
+		JavaClassFutureRef::new(fut)
+	}
+
+
+	// ----------------------------------------------
+	// Create an array class from its name, which is the descriptor of
+	// the array type. The element class is loaded first, it determines
+	// whether the array class is public (JVMS 5.3.3).
+	fn intern_add_array_class(&mut self, name : &str) -> Result<JavaClassRef, ClassLoadError> {
+		let component = match JavaField::resolve_field_desc(name) {
+			Ok(FD_ArrayType(component)) => *component,
+			_ => return Err(ClassLoadError::new(CLE_NoClassDefFoundError, 
+				format!("{} is not a valid array class name", name)))
+		};
+
+		let mut access = ACC_PUBLIC | ACC_FINAL | ACC_ABSTRACT;
+		match component.get_class_name() {
+			None => (),
+			Some(component_name) => match self.add_from_classfile(component_name).await() {
+				Err(e) => return Err(ClassLoader::wrap_parent_error(e, "array component class")),
+				Ok(cl) => access = (cl.get().get_access() & ACC_PUBLIC) | ACC_FINAL | ACC_ABSTRACT
+			}
+		}
+
+		let mut parents : ~[ JavaClassRef ] = ~[];
+		for parent in ["java.lang.Object", "java.lang.Cloneable", "java.io.Serializable"].iter() {
+			match self.add_from_classfile(*parent).await() {
+				Err(e) => return Err(ClassLoader::wrap_parent_error(e, "array super type")),
+				Ok(cl) => parents.push(cl)
+			}
+		}
+		Ok(self.register_class(name, Arc::new(JavaClass::new_array(name, access, parents, component))))
+	}
+
+
 	// ----------------------------------------------
 	// Check if a class with the given name is pending loading or
 	// has been loaded already. In the first case a waiter is enqueued
 	// to receive the result of the pending loading and in the latter 
 	// case the future is constructed directly from the class value and
-	// is thus immediately available.
+	// is thus immediately available. The same holds for classes that
+	// failed loading before, whose error is returned right away.
 	fn check_is_present_or_enqueue(&mut self,  name : &str) -> Option<JavaClassFutureRef> {
 		unsafe { 
 		self.ClassTableRef.unsafe_access(|table : &mut ClassTable| -> Option<JavaClassFutureRef> {
-			match table.find_mut(&name.into_owned()) {
+			match table.classes.find_mut(&name.into_owned()) {
 				Some(&ClassLoaded(ref elem)) => {
 					return Some(JavaClassFutureRef::new(Future::from_value(Ok((*elem).clone()))));
 				},
@@ -224,11 +355,14 @@ impl ClassLoader {
 					chans.push(chan);
 					return Some(JavaClassFutureRef::new(Future::from_port(port)));
 				},
+				Some(&ClassFailed(ref e)) => {
+					return Some(JavaClassFutureRef::new_error(e.clone()));
+				},
 				None => (),
 			}
 
 			// add a new waiting queue
-			table.insert(name.into_owned(), ClassPending(~[]));
+			table.classes.insert(name.into_owned(), ClassPending(~[]));
 			None
 		})}
 	}
@@ -236,73 +370,107 @@ impl ClassLoader {
 
 	// ----------------------------------------------
 	fn intern_add_from_classfile_bytes(&mut self, name : ~str, bytes : ~[u8]) -> 
-		Result<JavaClassRef, ~str> {
-		match result(|| { 
-			let reader = &mut BufReader::new(bytes) as &mut Reader;
+		Result<JavaClassRef, ClassLoadError> {
+		let mut buf_reader = BufReader::new(bytes);
+		let res = match result(|| { 
+			let reader = &mut buf_reader as &mut Reader;
 			
 			let magic = reader.read_be_u32() as uint;
 			if magic != 0xCAFEBABE {
-				return Err(~"magic word not found");
+				return Err(ClassLoadError::new_format_error("magic word not found"));
 			}
 
 			let minor = reader.read_be_u16() as uint;
 			let major = reader.read_be_u16() as uint;
 
-			// TODO: check whether we support this format
 			debug!("class file version {}.{}", major, minor);
+			if major < self.min_major_version || major > self.max_major_version {
+				return Err(ClassLoadError::new(CLE_UnsupportedClassVersionError, 
+					format!("class file version {}.{} is not supported, supported major versions are {} to {}", 
+					major, minor, self.min_major_version, self.max_major_version)));
+			}
 
 			// 1.
 			// constant pool
 			let constants = match ClassLoader::load_constant_pool(reader) {
-				Err(s) => return Err(s), 
+				Err(e) => return Err(e), 
 				Ok(n) => n
 			};
 
+			match ClassLoader::validate_constant_pool(constants, major) {
+				Err(s) => return Err(ClassLoadError::new_format_error(s)), 
+				Ok(_) => ()
+			}
+
 			let access = reader.read_be_u16() as uint;
 	
 			// 2.
 			// our own name - only used for verification
 			let own_name = match  ClassLoader::resolve_class_cpool_entry(constants, 
 				reader.read_be_u16() as uint) {
-				Err(s) => return Err(s), 
+				Err(s) => return Err(ClassLoadError::new_format_error(s)), 
 				Ok(n) => n
 			};
 			debug!("class name embedded in .class file is {}", own_name);
+			if own_name != name {
+				return Err(ClassLoadError::new(CLE_NoClassDefFoundError, 
+					format!("wrong name: {}", own_name)));
+			}
 			
 			// 3.
 			// super class name and implemented interfaces - must be loaded
-			let future_parents = match self.load_class_parents(constants, reader) {
-				Err(s) => return Err(s), 
+			let future_parents = match self.load_class_parents(name, constants, reader) {
+				Err(e) => return Err(e), 
 				Ok(n) => n
 			};
 
 			if future_parents.len() == 0 {
 				if name != ~"java.lang.Object" && (access & ACC_INTERFACE) == 0 {
-					return Err(~"Only interfaces and java.lang.Object can go without super class");
+					return Err(ClassLoadError::new_format_error(
+						"Only interfaces and java.lang.Object can go without super class"));
 				}
 			}
 
 			// 4. class and instance fields
-			let fields_count = reader.read_be_u16() as uint;
+			let fields = match ClassLoader::read_fields(reader, constants) {
+				Err(e) => return Err(e), 
+				Ok(n) => n
+			};
 
 			// 5. class and instance methods
-			//let methods = self.read_methods(reader, constants);
-		
+			let methods = match self.read_methods(reader, constants) {
+				Err(e) => return Err(e), 
+				Ok(n) => n
+			};
+
+			// 6. class attributes
+			let attrs = match attribute::read_attributes(constants, reader, AC_Class) {
+				Err(s) => return Err(ClassLoadError::new_format_error(s)), 
+				Ok(n) => n
+			};
 
-			/*
-				// 6. class attributes - we skip them for now
-				let attrs_count = reader.read_be_u16() as uint;
-			*/
+			if !reader.eof() {
+				return Err(ClassLoadError::new_format_error(
+					"unexpected trailing bytes after end of class file"));
+			}
 
 			return Ok(self.register_class(name, Arc::new(JavaClass::new(
 				name,
+				access,
 				constants,
-				future_parents
+				future_parents,
+				fields,
+				methods,
+				attrs
 			))))
 		}) {
-			Err(e) => Err(~"ClassLoader: unexpected end-of-file or read error"),
+			Err(e) => Err(ClassLoadError::new_format_error(
+				"unexpected end-of-file or read error: " + e.desc)),
 			Ok(T) => T
-		}
+		};
+
+		// attribute the error to this class and the position we were at
+		res.map_err(|e| e.with_class(name).with_offset(buf_reader.tell() as uint))
 	}
 
 
@@ -313,7 +481,7 @@ impl ClassLoader {
 		debug!("loaded class {}", name);
 		unsafe { 
 			self.ClassTableRef.unsafe_access(|table : &mut ClassTable| {
-				let mut entry = table.get_mut(&name.into_owned());
+				let mut entry = table.classes.get_mut(&name.into_owned());
 
 				match *entry {
 					ClassPending(ref mut queue) => {
@@ -335,14 +503,84 @@ impl ClassLoader {
 	}
 
 
+	// ----------------------------------------------
+	// Read the name of the class defined by a .class file without
+	// loading the class.
+	fn peek_class_name(bytes : &[u8]) -> Result<~str, ClassLoadError> {
+		let mut buf_reader = BufReader::new(bytes);
+		let res = match result(|| { 
+			let reader = &mut buf_reader as &mut Reader;
+			if reader.read_be_u32() != 0xCAFEBABE {
+				return Err(ClassLoadError::new_format_error("magic word not found"));
+			}
+
+			// minor, major version
+			reader.read_be_u16();
+			reader.read_be_u16();
+
+			let constants = match ClassLoader::load_constant_pool(reader) {
+				Err(e) => return Err(e), 
+				Ok(n) => n
+			};
+
+			// access flags
+			reader.read_be_u16();
+			match ClassLoader::resolve_class_cpool_entry(constants, reader.read_be_u16() as uint) {
+				Err(s) => Err(ClassLoadError::new_format_error(s)), 
+				Ok(n) => Ok(n)
+			}
+		}) {
+			Err(e) => Err(ClassLoadError::new_format_error(
+				"unexpected end-of-file or read error: " + e.desc)),
+			Ok(T) => T
+		};
+		res.map_err(|e| e.with_offset(buf_reader.tell() as uint))
+	}
+
+
+	// ----------------------------------------------
+	// If the given result of loading a class is an error, mark the class
+	// as failed and pass the error on to all tasks waiting for the class. 
+	// Returns the result unchanged.
+	fn register_failure_if_err(&mut self, name : &str, res : Result<JavaClassRef, ClassLoadError>) 
+		-> Result<JavaClassRef, ClassLoadError> {
+
+		let e = match res {
+			Ok(_) => return res,
+			Err(ref e) => e.clone()
+		};
+
+		debug!("failed to load class {}: {}", name, e.to_str());
+		unsafe { 
+			self.ClassTableRef.unsafe_access(|table : &mut ClassTable| {
+				let mut entry = table.classes.get_mut(&name.into_owned());
+
+				match *entry {
+					ClassPending(ref mut queue) => {
+						for k in queue.mut_iter() {
+							if !k.try_send(Err(e.clone())) {
+								debug!("failed to send error back to listener, port is hung up");
+							}
+						}
+					},
+					_ => fail!("logic error, class not marked as pending"),
+				}
+
+				*entry = ClassFailed(e.clone());
+			}) 
+		};
+		res
+	}
+
+
 	// ----------------------------------------------
 	// Load the portion of the .class file header that contains 
 	// the constant value pool (cpool) and parse all entries
 	// into proper structures.
-	fn load_constant_pool(reader: &mut Reader) ->  Result<~[Constant], ~str> {
+	fn load_constant_pool(reader: &mut Reader) ->  Result<~[Constant], ClassLoadError> {
 		let cpool_count = reader.read_be_u16() as uint;
 		if cpool_count == 0 {
-			return Err(~"invalid constant pool size");
+			return Err(ClassLoadError::new_format_error("invalid constant pool size"));
 		}
 
 		debug!("{} constant pool entries", cpool_count - 1);
@@ -357,7 +595,8 @@ impl ClassLoader {
 
 			let mut skip = 0;
 			let maybe_centry = match parsed_tag {
-				None => Err(format!("constant pool tag not recognized: {}", tag)),
+				None => Err(ClassLoadError::new_format_error(
+					format!("constant pool tag not recognized: {}", tag))),
 				Some(c) => {
 					ClassLoader::read_cpool_entry_body(c, 
 						reader, 
@@ -376,12 +615,113 @@ impl ClassLoader {
 				}
 			}
 
+			if skip > 0 {
+				constants.push(CONSTANT_unusable_info);
+			}
+
 			i += skip + 1;
 		}
 		return Ok(constants);
 	}
 
 
+	// ----------------------------------------------
+	// Check that all cross-references between constant pool entries 
+	// refer to entries of the expected type, and that method handles
+	// use valid reference kinds. Indices are already known to be in
+	// range as read_cpool_entry_body() checks this.
+	fn validate_constant_pool(constants : &[Constant], major : uint) -> Result<(), ~str> {
+		let is_utf8 = |i : u16| match constants[i - 1] { 
+			CONSTANT_utf8_info(_) => true, _ => false 
+		};
+		let is_class = |i : u16| match constants[i - 1] { 
+			CONSTANT_class_info(_) => true, _ => false 
+		};
+		let is_name_and_type = |i : u16| match constants[i - 1] { 
+			CONSTANT_nameandtype_info(_, _) => true, _ => false 
+		};
+
+		for (n, c) in constants.iter().enumerate() {
+			let ok = match *c {
+				CONSTANT_class_info(name) => is_utf8(name),
+				CONSTANT_string_info(s) => is_utf8(s),
+				CONSTANT_methodtype_info(desc) => is_utf8(desc),
+				CONSTANT_nameandtype_info(name, desc) => is_utf8(name) && is_utf8(desc),
+
+				CONSTANT_fieldref_info(cls, nat) |
+				CONSTANT_methodref_info(cls, nat) |
+				CONSTANT_ifacemethodref_info(cls, nat) => is_class(cls) && is_name_and_type(nat),
+
+				CONSTANT_invokedynamic_info(_, nat) => is_name_and_type(nat),
+
+				CONSTANT_methodhandle_info(kind, r) => {
+					let target = &constants[r - 1];
+					let ok = match kind {
+						REF_getField .. REF_putStatic => match *target { 
+							CONSTANT_fieldref_info(_, _) => true, _ => false 
+						},
+						REF_invokeVirtual | REF_newInvokeSpecial => match *target { 
+							CONSTANT_methodref_info(_, _) => true, _ => false 
+						},
+						REF_invokeStatic | REF_invokeSpecial => match *target { 
+							CONSTANT_methodref_info(_, _) => true, 
+							// since Java 8, static and private interface methods
+							// can be referenced.
+							CONSTANT_ifacemethodref_info(_, _) => major >= 52,
+							_ => false 
+						},
+						REF_invokeInterface => match *target { 
+							CONSTANT_ifacemethodref_info(_, _) => true, _ => false 
+						},
+						_ => return Err(format!("constant pool entry {}: invalid method handle reference kind {}", 
+							n + 1, kind))
+					};
+
+					// only REF_newInvokeSpecial handles reference constructors,
+					// and no handle may reference a class initializer.
+					match ClassLoader::get_member_name(constants, target) {
+						Some(name) if ok && kind >= REF_invokeVirtual => {
+							if (kind == REF_newInvokeSpecial) != (name == "<init>") || name == "<clinit>" {
+								return Err(format!("constant pool entry {}: method handle of reference kind {} cannot reference {}", 
+									n + 1, kind, name));
+							}
+						},
+						_ => ()
+					}
+					ok
+				},
+				_ => true
+			};
+
+			if !ok {
+				return Err(format!("constant pool entry {}: cross-referenced entry has the wrong type", n + 1));
+			}
+		}
+		Ok(())
+	}
+
+
+	// ----------------------------------------------
+	// Get the name of the field or method referenced by a constant 
+	// pool entry, or None if the entry is no member reference or its
+	// name cannot be determined.
+	fn get_member_name<'a>(constants : &'a [Constant], c : &Constant) -> Option<&'a str> {
+		let nat = match *c {
+			CONSTANT_fieldref_info(_, nat) |
+			CONSTANT_methodref_info(_, nat) |
+			CONSTANT_ifacemethodref_info(_, nat) => nat,
+			_ => return None
+		};
+		match constants[nat - 1] {
+			CONSTANT_nameandtype_info(name, _) => match constants[name - 1] {
+				CONSTANT_utf8_info(ref s) => Some(s.as_slice()),
+				_ => None
+			},
+			_ => None
+		}
+	}
+
+
 	// ----------------------------------------------
 	// Loads a referenced class that is given by an entry in the cpool.
 	// When calling this method, be sure to do so in a manner that 
@@ -390,19 +730,69 @@ impl ClassLoader {
 	// between fields and exception handlers may include cycles. Use
 	// load_future_class_from_cpool for this purpose.
 	//
-	fn load_class_from_cpool(&mut self, constants : &[Constant], index : uint)
-		-> Result<JavaClassRef, ~str> {
+	// `requester` is the name of the class on whose behalf the class
+	// is loaded. If the requester is (indirectly) waiting for itself,
+	// a ClassCircularityError is returned instead of blocking forever.
+	fn load_class_from_cpool(&mut self, requester : &str, constants : &[Constant], index : uint)
+		-> Result<JavaClassRef, ClassLoadError> {
 
-		match ClassLoader::resolve_class_cpool_entry(
+		let class_name = match ClassLoader::resolve_class_cpool_entry(
 			constants, index
 		) {
-			Err(s) => Err(s),
-			Ok(class_name) => {
-				match self.add_from_classfile(class_name).await() {
-					Err(s) => Err("failure loading referenced class: " + s),
-					Ok(cl) => Ok(cl),
+			Err(s) => return Err(ClassLoadError::new_format_error(s)),
+			Ok(class_name) => class_name
+		};
+
+		match self.add_wait_edge(requester, class_name) {
+			Err(e) => return Err(e),
+			Ok(_) => ()
+		}
+		let res = self.add_from_classfile(class_name).await();
+		self.remove_wait_edge(requester);
+		res
+	}
+
+
+	// ----------------------------------------------
+	// Record that `requester` waits for `class_name` to be loaded. Fails
+	// with ClassCircularityError if this closes a cycle in the graph of
+	// classes waiting for each other, in which case no edge is added.
+	fn add_wait_edge(&mut self, requester : &str, class_name : &str) -> Result<(), ClassLoadError> {
+		let req = requester.into_owned();
+		unsafe { 
+			self.ClassTableRef.unsafe_access(|table : &mut ClassTable| {
+				// follow the chain of waiting classes starting with 
+				// the class we are about to wait for.
+				let mut cur = class_name.into_owned();
+				let mut chain = ~[req.clone(), cur.clone()];
+				loop {
+					if cur == req {
+						return Err(ClassLoadError::new(CLE_ClassCircularityError, 
+							format!("circular class hierarchy: {}", chain.connect(" -> "))));
+					}
+					match table.waits_for.find(&cur) {
+						Some(next) => {
+							cur = next.clone();
+							chain.push(cur.clone());
+						},
+						None => break
+					}
 				}
-			}
+
+				table.waits_for.insert(req.clone(), class_name.into_owned());
+				Ok(())
+			})
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Remove the edge added by add_wait_edge()
+	fn remove_wait_edge(&mut self, requester : &str) {
+		unsafe { 
+			self.ClassTableRef.unsafe_access(|table : &mut ClassTable| {
+				table.waits_for.remove(&requester.into_owned());
+			})
 		}
 	}
 
@@ -417,7 +807,7 @@ impl ClassLoader {
 		match ClassLoader::resolve_class_cpool_entry(
 			constants, index
 		) {
-			Err(s) => JavaClassFutureRef::new_error(s),
+			Err(s) => JavaClassFutureRef::new_error(ClassLoadError::new_format_error(s)),
 			Ok(class_name) => self.add_from_classfile(class_name),
 		}
 	}
@@ -427,17 +817,23 @@ impl ClassLoader {
 	// Load the portion of a .class file header that lists the class'
 	// super class as well as all implemented interfaces and loads
 	// all of them
-	fn load_class_parents(&mut self, constants : &[Constant], reader: &mut Reader)  
-		-> Result<~[ JavaClassRef ], ~str> {
+	fn load_class_parents(&mut self, name : &str, constants : &[Constant], reader: &mut Reader)  
+		-> Result<~[ JavaClassRef ], ClassLoadError> {
 
 		let mut future_parents : ~[ JavaClassRef ] = ~[];
 		let parent_index = reader.read_be_u16() as uint;
 
 		// parent_index is 0 for interfaces, and for java.lang.Object
 		if parent_index != 0 {
-			match self.load_class_from_cpool(constants, parent_index) {
-				Err(s) => return Err("failure loading parent class: " + s),
-				Ok(cl) => future_parents.push(cl)
+			match self.load_class_from_cpool(name, constants, parent_index) {
+				Err(e) => return Err(ClassLoader::wrap_parent_error(e, "parent class")),
+				Ok(cl) => {
+					if (cl.get().get_access() & ACC_INTERFACE) != 0 {
+						return Err(ClassLoadError::new(CLE_IncompatibleClassChangeError, 
+							format!("super class {} is an interface", *cl.get().get_name())));
+					}
+					future_parents.push(cl)
+				}
 			}
 		}
 				
@@ -445,9 +841,15 @@ impl ClassLoader {
 		let mut i = 0;
 		while i < ifaces_count {
 			let iindex = reader.read_be_u16() as uint;
-			match self.load_class_from_cpool(constants, iindex) {
-				Err(s) => return Err("failure loading parent interface: " + s),
-				Ok(cl) => future_parents.push(cl)
+			match self.load_class_from_cpool(name, constants, iindex) {
+				Err(e) => return Err(ClassLoader::wrap_parent_error(e, "parent interface")),
+				Ok(cl) => {
+					if (cl.get().get_access() & ACC_INTERFACE) == 0 {
+						return Err(ClassLoadError::new(CLE_IncompatibleClassChangeError, 
+							format!("implemented interface {} is a class", *cl.get().get_name())));
+					}
+					future_parents.push(cl)
+				}
 			}
 			i += 1;
 		}
@@ -456,105 +858,117 @@ impl ClassLoader {
 
 
 	// ----------------------------------------------
-	// Loads the methods (+ static functions) section from a .class file
-	fn read_methods(&self, reader: &mut Reader, constants : &[Constant]) -> Result<~[JavaMethod], ~str> {
-		let mut methods : ~[JavaMethod] = ~[];
-		let methods_count = reader.read_be_u16() as uint;
-		for i in range(0, methods_count) {
+	// Derive the error for a class from the error that occured while
+	// loading one of its super classes or interfaces. The class fails
+	// with the same kind of error as the parent did, see
+	// http://docs.oracle.com/javase/specs/jvms/se7/html/jvms-5.html#jvms-5.3.5
+	fn wrap_parent_error(e : ClassLoadError, what : &str) -> ClassLoadError {
+		let kind = e.get_kind();
+		ClassLoadError::new(kind, format!("failure loading {}: {}", what, e.to_str()))
+	}
+
+
+	// ----------------------------------------------
+	// Loads the fields (both class and instance fields) section from a .class file
+	fn read_fields(reader: &mut Reader, constants : &[Constant]) -> Result<~[JavaField], ClassLoadError> {
+		let mut fields : ~[JavaField] = ~[];
+		let fields_count = reader.read_be_u16() as uint;
+		for _ in range(0, fields_count) {
 			let access = reader.read_be_u16() as uint;
-			// I definitely want some kind of a monadic "DO" notation as for Haskell
 			let name = match ClassLoader::resolve_name_cpool_entry(constants, 
 				reader.read_be_u16() as uint) {
-				Err(s) => return Err(s),
+				Err(s) => return Err(ClassLoadError::new_format_error(s)),
 				Ok(n) => n
 			};
 
 			let desc = match ClassLoader::resolve_name_cpool_entry(constants, 
 				reader.read_be_u16() as uint) {
-				Err(s) => return Err(s),
+				Err(s) => return Err(ClassLoadError::new_format_error(s)),
 				Ok(n) => n
 			};
 
-			// scan for the "Code" attribute
-			// TODO: for proper interpretation and fully secure linking we will
-			// need to also process other attributes.
-			let mut code_attr : Option<CodeBlock> = None;
-			let attr_count = reader.read_be_u16() as uint;
-			for i in range(0, attr_count) {
-				let name = match ClassLoader::resolve_name_cpool_entry(constants, 
-					reader.read_be_u16() as uint) {
-					Err(s) => return Err(s),
-					Ok(n) => n
-				};
-
-				if name == ~"Code" {
-					code_attr = match self.load_code_attribute(constants, reader) {
-						Err(s) => return Err(s),
-						Ok(n) => Some(n),
-					};
-				}
-			}
+			let mut attrs = match attribute::read_attributes(constants, reader, AC_Field) {
+				Err(s) => return Err(ClassLoadError::new_format_error(format!("failed to load field {}: {}", name, s))),
+				Ok(a) => a
+			};
 
-			if code_attr.is_none() {
-				return Err(~"failed to read [Code] attribute from method attribute table");
+			if attribute::count_attributes(attrs, "ConstantValue") > 1 {
+				return Err(ClassLoadError::new_format_error(format!("field {} has more than one [ConstantValue] attribute", name)));
 			}
+			let constant_value = attribute::take_constant_value(&mut attrs);
 
-			methods.push(JavaMethod::new(name,desc,code_attr.unwrap()));
+			match JavaField::new(name, access, desc, constant_value, attrs) {
+				Err(s) => return Err(ClassLoadError::new_format_error(format!("failed to load field {}: {}", name, s))),
+				Ok(f) => fields.push(f)
+			}
 		}
-		Ok(methods)
+		Ok(fields)
 	}
 
 
 	// ----------------------------------------------
-	// Load a single attribute of type [Code] from a a .class file reader 
-	// that is positioned correctly to be right behind the attribute head.
-	// http://docs.oracle.com/javase/specs/jvms/se7/html/jvms-4.html#jvms-4.7.3
-	pub fn load_code_attribute(&self, constants : &[Constant], reader: &mut Reader) -> 
-		Result<CodeBlock, ~str> {
+	// Loads the methods (+ static functions) section from a .class file
+	fn read_methods(&self, reader: &mut Reader, constants : &[Constant]) -> Result<~[JavaMethod], ClassLoadError> {
+		let mut methods : ~[JavaMethod] = ~[];
+		let mut seen : HashSet<(~str, ~str)> = HashSet::new();
 
-		let max_stack = reader.read_be_u16() as uint;
-		let max_locals = reader.read_be_u16() as uint;
-		let code_len = reader.read_be_u16() as uint;
+		let methods_count = reader.read_be_u16() as uint;
+		for _ in range(0, methods_count) {
+			let access = reader.read_be_u16() as uint;
+			// I definitely want some kind of a monadic "DO" notation as for Haskell
+			let name = match ClassLoader::resolve_name_cpool_entry(constants, 
+				reader.read_be_u16() as uint) {
+				Err(s) => return Err(ClassLoadError::new_format_error(s)),
+				Ok(n) => n
+			};
 
-		let codebytes = reader.read_bytes(code_len);
+			let desc = match ClassLoader::resolve_name_cpool_entry(constants, 
+				reader.read_be_u16() as uint) {
+				Err(s) => return Err(ClassLoadError::new_format_error(s)),
+				Ok(n) => n
+			};
 
-		// TODO: translate code bytes
+			if !seen.insert((name.clone(), desc.clone())) {
+				return Err(ClassLoadError::new_format_error(format!("duplicate method {}{}", name, desc)));
+			}
 
-		let exc_len = reader.read_be_u16() as uint;
-		let mut i = 0;
-		let mut exc_rec : ~[ExceptionHandler] = ~[];
-		while i < exc_len {
+			let mut attrs = match attribute::read_attributes(constants, reader, AC_Method) {
+				Err(s) => return Err(ClassLoadError::new_format_error(format!("failed to load method {}{}: {}", name, desc, s))),
+				Ok(a) => a
+			};
 
-			let start_pc = reader.read_be_u16() as uint;
-			let end_pc = reader.read_be_u16() as uint;
-			let handler_pc = reader.read_be_u16() as uint;
-			let catch_type_index = reader.read_be_u16() as uint;
+			if attribute::count_attributes(attrs, "Code") > 1 {
+				return Err(ClassLoadError::new_format_error(format!("method {}{} has more than one [Code] attribute", name, desc)));
+			}
+			let code_attr = attribute::take_code(&mut attrs);
 
-			match ClassLoader::resolve_class_cpool_entry(constants, catch_type_index) {
-				Ok(cl) => {
-					exc_rec.push(ExceptionHandler {
-						start_pc : start_pc,
-						end_pc : end_pc,
-						handler_pc : handler_pc,
-						catch_type : cl,
-					})
-				},
-				Err(s) => return Err(s)
+			// abstract and native methods have no code, all others must have
+			let has_no_code = (access & (ACC_ABSTRACT | ACC_NATIVE)) != 0;
+			if has_no_code && code_attr.is_some() {
+				return Err(ClassLoadError::new_format_error(format!("abstract or native method {}{} must not have a [Code] attribute", 
+					name, desc)));
+			}
+			if !has_no_code && code_attr.is_none() {
+				return Err(ClassLoadError::new_format_error(format!("failed to read [Code] attribute for method {}{}", name, desc)));
 			}
 
-			i += 1;
+			match JavaMethod::new(name, desc, access, code_attr, attrs) {
+				Err(s) => return Err(ClassLoadError::new_format_error(format!("failed to load method {}{}: {}", name, desc, s))),
+				Ok(m) => methods.push(m)
+			}
 		}
-
-		return Ok(CodeBlock::new(max_stack, max_locals, codebytes, exc_rec));
+		Ok(methods)
 	}
 
 
 	// ----------------------------------------------
 	// Given a parsed constant pool and locate an UTF8 string entry in it
-	fn resolve_name_cpool_entry(constants : &[Constant], oneb_index : uint) ->
+	pub fn resolve_name_cpool_entry(constants : &[Constant], oneb_index : uint) ->
 		Result<~str,~str>	{
 
-		assert!(oneb_index != 0 && oneb_index <= constants.len());
+		if oneb_index == 0 || oneb_index > constants.len() {
+			return Err(format!("constant pool index out of range: {}", oneb_index));
+		}
 		match constants[oneb_index - 1] {
 			CONSTANT_utf8_info(ref s) => Ok(s.clone()),
 			_ => Err(~"name cpool entry is not a CONSTANT_Utf8"),
@@ -565,17 +979,18 @@ impl ClassLoader {
 	// ----------------------------------------------
 	// Given a parsed constant pool, locate a class entry in it and
 	// resolve the UTF8 name of the class.
-	fn resolve_class_cpool_entry(constants : &[Constant], oneb_index : uint) ->
+	pub fn resolve_class_cpool_entry(constants : &[Constant], oneb_index : uint) ->
 		Result<~str,~str>	{
 
-		assert!(oneb_index != 0 && oneb_index <= constants.len());
+		if oneb_index == 0 || oneb_index > constants.len() {
+			return Err(format!("constant pool index out of range: {}", oneb_index));
+		}
 
 		match constants[oneb_index - 1] {
 			CONSTANT_class_info(ref utf8_idx) => {
-				assert!(*utf8_idx != 0 && (*utf8_idx as uint) <= constants.len());
-				match constants[*utf8_idx - 1] {
-					CONSTANT_utf8_info(ref s) => Ok(s.clone().replace("/",".")),
-					_ => Err(~"class name cpool entry is not a CONSTANT_Utf8"),
+				match ClassLoader::resolve_name_cpool_entry(constants, *utf8_idx as uint) {
+					Ok(s) => Ok(s.replace("/",".")),
+					Err(_) => Err(~"class name cpool entry is not a CONSTANT_Utf8"),
 				}
 			},
 			_ => Err(~"not a CONSTANT_class entry"),
@@ -583,10 +998,72 @@ impl ClassLoader {
 	}
 
 
+	// ----------------------------------------------
+	// Given a parsed constant pool, locate a Fieldref, Methodref or
+	// InterfaceMethodref entry in it and resolve the name of the class
+	// as well as the name and descriptor of the member.
+	pub fn resolve_member_cpool_entry(constants : &[Constant], oneb_index : uint) ->
+		Result<(~str, ~str, ~str),~str>	{
+
+		if oneb_index == 0 || oneb_index > constants.len() {
+			return Err(format!("constant pool index out of range: {}", oneb_index));
+		}
+
+		let (class_idx, nat_idx) = match constants[oneb_index - 1] {
+			CONSTANT_fieldref_info(c, n) | CONSTANT_methodref_info(c, n) | 
+			CONSTANT_ifacemethodref_info(c, n) => (c as uint, n as uint),
+			_ => return Err(~"not a CONSTANT_{Fieldref,Methodref,InterfaceMethodref} entry"),
+		};
+
+		let class_name = match ClassLoader::resolve_class_cpool_entry(constants, class_idx) {
+			Err(s) => return Err(s),
+			Ok(s) => s
+		};
+		if nat_idx == 0 || nat_idx > constants.len() {
+			return Err(format!("constant pool index out of range: {}", nat_idx));
+		}
+		match constants[nat_idx - 1] {
+			CONSTANT_nameandtype_info(name_idx, desc_idx) => {
+				match (ClassLoader::resolve_name_cpool_entry(constants, name_idx as uint),
+					ClassLoader::resolve_name_cpool_entry(constants, desc_idx as uint)) {
+					(Ok(name), Ok(desc)) => Ok((class_name, name, desc)),
+					_ => Err(~"name and type cpool entry does not refer to CONSTANT_Utf8 entries"),
+				}
+			},
+			_ => Err(~"not a CONSTANT_NameAndType entry"),
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Given a parsed constant pool, locate the entry referenced by
+	// a [ConstantValue] attribute and convert it to a ConstantValue.
+	pub fn resolve_constant_value_cpool_entry(constants : &[Constant], oneb_index : uint) ->
+		Result<ConstantValue,~str>	{
+
+		if oneb_index == 0 || oneb_index > constants.len() {
+			return Err(format!("constant pool index out of range: {}", oneb_index));
+		}
+		match constants[oneb_index - 1] {
+			CONSTANT_integer_info(i) => Ok(CV_Integer(i)),
+			CONSTANT_float_info(f) => Ok(CV_Float(f)),
+			CONSTANT_long_info(l) => Ok(CV_Long(l)),
+			CONSTANT_double_info(d) => Ok(CV_Double(d)),
+			CONSTANT_string_info(utf8_idx) => {
+				match ClassLoader::resolve_name_cpool_entry(constants, utf8_idx as uint) {
+					Err(s) => Err(s),
+					Ok(s) => Ok(CV_String(s))
+				}
+			},
+			_ => Err(~"ConstantValue cpool entry is not a CONSTANT_{Integer,Float,Long,Double,String}"),
+		}
+	}
+
+
 	// ----------------------------------------------
 	fn read_cpool_entry_body(tag : ConstantPoolTags, reader : &mut Reader, count : uint, 
 		skip : &mut uint) -> 
-		Result<Constant, ~str> {
+		Result<Constant, ClassLoadError> {
 
 		let mut err : Option<~str> = None;
 		let cindex = || {
@@ -598,7 +1075,8 @@ impl ClassLoader {
 			return index;
 		};
 
-		// TODO: verify the type of cross-referenced cpool entries
+		// the type of cross-referenced entries is checked afterwards
+		// by validate_constant_pool() as they may be forward references.
 		// TODO: do the read_be .. variants properly raise io_error
 		// for our caller to trap?
 
@@ -627,12 +1105,12 @@ impl ClassLoader {
 				let length = reader.read_be_u16() as uint;
 				let raw = reader.read_bytes(length);
 
-				// TODO: Java uses a "modified UTF8", which
+				// Java uses a "modified UTF8", which
 				//  - encodes NIL as two bytes
-				//  - uss two three-byte sequences to encode four byte encodings
-				match from_utf8_owned(raw) {
+				//  - uses two three-byte sequences to encode four byte encodings
+				match mutf8::decode(raw) {
 					None => {
-						err = Some(~"constant pool entry is not  valid UTF8 string");
+						err = Some(~"constant pool entry is not valid modified UTF8 string");
 						CONSTANT_utf8_info(~"")
 					},
 					Some(s) => {
@@ -658,7 +1136,7 @@ impl ClassLoader {
 
 		match err {
 			None => Ok(res),
-			Some(msg) => Err(msg)
+			Some(msg) => Err(ClassLoadError::new_format_error(msg))
 		}
 	}
 }
@@ -668,22 +1146,26 @@ impl Clone for ClassLoader {
 		ClassLoader {
 			classpath : self.classpath.clone(),
 			ClassTableRef : self.ClassTableRef.clone(),
+			min_major_version : self.min_major_version,
+			max_major_version : self.max_major_version,
 		}
 	}
 }
 
 
 // Mock class loader that refuses to load any classes and instead just
-// returns the "DUMMY" error string. Used for testing.
+// returns a NoClassDefFoundError with the "DUMMY" message. Used for testing.
 pub struct DummyClassLoader;
 impl AbstractClassLoader for DummyClassLoader {
 	
 	fn load(&mut self, name : &str) -> JavaClassFutureRef	{
-		return JavaClassFutureRef::new(Future::from_value(Err(~"DUMMY")));
+		return JavaClassFutureRef::new_error(
+			ClassLoadError::new(CLE_NoClassDefFoundError, "DUMMY").with_class(name));
 	}
 
-	fn load_from_bytes(&mut self, name : &str, bytes : ~[u8]) -> JavaClassFutureRef	{
-		return JavaClassFutureRef::new(Future::from_value(Err(~"DUMMY")));
+	fn load_from_bytes(&mut self, name : Option<&str>, bytes : ~[u8]) -> JavaClassFutureRef	{
+		return JavaClassFutureRef::new_error(
+			ClassLoadError::new(CLE_NoClassDefFoundError, "DUMMY").with_class(name.unwrap_or("")));
 	}
 }
 
@@ -693,7 +1175,15 @@ impl AbstractClassLoader for DummyClassLoader {
 #[cfg(test)]
 pub mod tests {
 	use classloader::*;
+	use classloaderror::*;
+	use field::{CV_Integer, CV_Long, CV_Double, CV_String};
 	use util::{assert_no_err};
+	use std::io::File;
+	use std::path::PosixPath;
+	use std::comm::SharedChan;
+	use class::{JavaClass};
+	use field::{JavaField};
+	use def::{ACC_PUBLIC};
 
 	pub fn test_get_dummy_classloader() -> DummyClassLoader
 	{
@@ -710,7 +1200,196 @@ pub mod tests {
 	#[test]
 	fn test_class_loader_fail() {
 		let mut cl = ClassLoader::new_from_string("");
-		assert!(cl.add_from_classfile("FooClassDoesNotExist").await().is_err());
+		let res = cl.add_from_classfile("FooClassDoesNotExist").await();
+		assert!(res.is_err());
+
+		let e = res.unwrap_err();
+		assert_eq!(e.get_kind(), CLE_NoClassDefFoundError);
+		assert_eq!(*e.get_class_name(), ~"FooClassDoesNotExist");
+	}
+
+
+	#[test]
+	fn test_class_loader_version_check() {
+		let mut cl = test_get_real_classloader();
+		let bytes = File::open(&PosixPath::new("../test/java/EmptyClass.class")).read_to_end();
+
+		// pretend to be a Java 9 class file
+		let mut newer = bytes.clone();
+		newer[6] = 0;
+		newer[7] = 53;
+		let e = cl.add_from_bytes("EmptyClass", newer).await().unwrap_err();
+		assert_eq!(e.get_kind(), CLE_UnsupportedClassVersionError);
+
+		// after narrowing the accepted range, version 51 is rejected as well
+		let mut cl2 = ClassLoader::new_from_string("../test/java;../rt");
+		cl2.set_supported_major_versions(45, 50);
+		assert_eq!(cl2.get_supported_major_versions(), (45, 50));
+		let e2 = cl2.add_from_bytes("EmptyClass", bytes).await().unwrap_err();
+		assert_eq!(e2.get_kind(), CLE_UnsupportedClassVersionError);
+	}
+
+
+	#[test]
+	fn test_class_loader_cpool_validation() {
+		let mut cl = test_get_real_classloader();
+		let header = ~[0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 51];
+
+		// CONSTANT_String pointing to a CONSTANT_Integer
+		let e = cl.add_from_bytes("BadString", header + ~[0, 3, 
+			8, 0, 2, 
+			3, 0, 0, 0, 42]).await().unwrap_err();
+		assert_eq!(e.get_kind(), CLE_ClassFormatError);
+
+		// CONSTANT_Class pointing to itself instead of a CONSTANT_Utf8
+		let e2 = cl.add_from_bytes("BadClass", header + ~[0, 2, 
+			7, 0, 1]).await().unwrap_err();
+		assert_eq!(e2.get_kind(), CLE_ClassFormatError);
+
+		// CONSTANT_MethodHandle with reference kind 10
+		let e3 = cl.add_from_bytes("BadHandle", header + ~[0, 6, 
+			15, 10, 0, 2,
+			10, 0, 3, 0, 4,
+			7, 0, 5,
+			12, 0, 5, 0, 5,
+			1, 0, 1, 0x41]).await().unwrap_err();
+		assert_eq!(e3.get_kind(), CLE_ClassFormatError);
+		assert!(e3.get_message().contains("reference kind"));
+
+		// REF_newInvokeSpecial handle to a method other than <init>
+		let e4 = cl.add_from_bytes("BadNewHandle", header + ~[0, 6, 
+			15, 8, 0, 2,
+			10, 0, 3, 0, 4,
+			7, 0, 5,
+			12, 0, 5, 0, 5,
+			1, 0, 1, 0x41]).await().unwrap_err();
+		assert_eq!(e4.get_kind(), CLE_ClassFormatError);
+		assert!(e4.get_message().contains("cannot reference A"));
+
+		// REF_invokeStatic handle to <clinit>
+		let e5 = cl.add_from_bytes("BadClinitHandle", header + ~[0, 7, 
+			15, 6, 0, 2,
+			10, 0, 3, 0, 4,
+			7, 0, 5,
+			12, 0, 6, 0, 5,
+			1, 0, 1, 0x41,
+			1, 0, 8, 0x3C, 0x63, 0x6C, 0x69, 0x6E, 0x69, 0x74, 0x3E]).await().unwrap_err();
+		assert_eq!(e5.get_kind(), CLE_ClassFormatError);
+		assert!(e5.get_message().contains("cannot reference <clinit>"));
+	}
+
+
+	#[test]
+	fn test_class_loader_failure_is_cached() {
+		let mut cl = test_get_real_classloader();
+
+		// the first attempt fails with a format error ...
+		let e = cl.add_from_bytes("Broken", ~[0xCA, 0xFE, 0xBA, 0xBF]).await().unwrap_err();
+		assert_eq!(e.get_kind(), CLE_ClassFormatError);
+		assert!(cl.get_class("Broken").is_none());
+
+		// ... and subsequent attempts to load it fail with the same error,
+		// even if valid bytes are now provided. 
+		let bytes = File::open(&PosixPath::new("../test/java/EmptyClass.class")).read_to_end();
+		let e2 = cl.add_from_bytes("Broken", bytes).await().unwrap_err();
+		assert_eq!(e2.to_str(), e.to_str());
+
+		let e3 = cl.add_from_classfile("Broken").await().unwrap_err();
+		assert_eq!(e3.to_str(), e.to_str());
+	}
+
+
+	#[test]
+	fn test_class_loader_failure_notifies_waiters() {
+		let cl = test_get_real_classloader();
+		let (port, chan) = SharedChan::new();
+
+		// concurrently request a missing class, all requests must
+		// complete with an error instead of waiting forever.
+		for _ in range(0, 8) {
+			let cl_clone = cl.clone();
+			let chan_clone = chan.clone();
+			do spawn {
+				let res = cl_clone.clone().add_from_classfile("FooClassDoesNotExist").await();
+				chan_clone.send(res.is_err());
+			}
+		}
+		for _ in range(0, 8) {
+			assert!(port.recv());
+		}
+	}
+
+
+	#[test]
+	fn test_class_loader_circularity() {
+		let mut cl = test_get_real_classloader();
+		let e = cl.add_from_classfile("CircularA").await().unwrap_err();
+		assert_eq!(e.get_kind(), CLE_ClassCircularityError);
+
+		// both classes in the cycle are marked as failed
+		let e2 = cl.add_from_classfile("CircularB").await().unwrap_err();
+		assert_eq!(e2.get_kind(), CLE_ClassCircularityError);
+	}
+
+
+	#[test]
+	fn test_class_loader_circularity_concurrent() {
+		let cl = test_get_real_classloader();
+		let (port, chan) = SharedChan::new();
+
+		// load both ends of the cycle at the same time
+		for name in (~[~"CircularA", ~"CircularB"]).move_iter() {
+			let cl_clone = cl.clone();
+			let chan_clone = chan.clone();
+			do spawn {
+				let res = cl_clone.clone().add_from_classfile(name).await();
+				chan_clone.send(res.unwrap_err().get_kind());
+			}
+		}
+		assert_eq!(port.recv(), CLE_ClassCircularityError);
+		assert_eq!(port.recv(), CLE_ClassCircularityError);
+	}
+
+
+	#[test]
+	fn test_class_loader_wrong_name() {
+		let mut cl = test_get_real_classloader();
+		let bytes = File::open(&PosixPath::new("../test/java/EmptyClass.class")).read_to_end();
+
+		let e = cl.add_from_bytes("foo.EmptyClass", bytes.clone()).await().unwrap_err();
+		assert_eq!(e.get_kind(), CLE_NoClassDefFoundError);
+		assert_eq!(*e.get_class_name(), ~"foo.EmptyClass");
+		assert!(e.get_message().contains("wrong name: EmptyClass"));
+		assert!(cl.get_class("foo.EmptyClass").is_none());
+
+		// without a given name, the name from the class file is used
+		let mut v = cl.load_from_bytes(None, bytes).await();
+		assert_no_err(&v);
+		assert_eq!(*v.unwrap().get().get_name(), ~"EmptyClass");
+		assert!(cl.get_class("EmptyClass").is_some());
+	}
+
+
+	#[test]
+	fn test_class_loader_format_errors() {
+		let mut cl = test_get_real_classloader();
+
+		// bad magic word
+		let e = cl.add_from_bytes("BadMagic", ~[0xCA, 0xFE, 0xBA, 0xBF, 0, 0, 0, 51]).await().unwrap_err();
+		assert_eq!(e.get_kind(), CLE_ClassFormatError);
+		assert_eq!(*e.get_class_name(), ~"BadMagic");
+		assert_eq!(e.get_offset(), Some(4));
+
+		// truncated right in the constant pool
+		let e2 = cl.add_from_bytes("Truncated", ~[0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 51, 0, 2, 1]).await().unwrap_err();
+		assert_eq!(e2.get_kind(), CLE_ClassFormatError);
+
+		// a class whose name points to a non-existing constant pool entry
+		let e3 = cl.add_from_bytes("BadIndex", ~[0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 51, 
+			0, 2, 1, 0, 1, 0x41, 
+			0, 0x21, 0, 5]).await().unwrap_err();
+		assert_eq!(e3.get_kind(), CLE_ClassFormatError);
+		assert_eq!(e3.get_offset(), Some(18));
 	}
 
 
@@ -728,6 +1407,203 @@ pub mod tests {
 	}
 
 
+	#[test]
+	fn test_class_loader_fields() {
+		let mut cl = test_get_real_classloader();
+		let v = cl.add_from_classfile("FieldAccess").unwrap_all();
+		let c = v.get();
+
+		assert_eq!(c.get_static_fields().len(), 1);
+		assert_eq!(c.get_instance_fields().len(), 1);
+		assert!(c.find_static_field("globalCount").is_some());
+		assert!(c.find_instance_field("myCount").is_some());
+		assert!(c.find_instance_field("globalCount").is_none());
+	}
+
+
+	#[test]
+	fn test_class_loader_methods() {
+		let mut cl = test_get_real_classloader();
+		let v = cl.add_from_classfile("EmptyClassWithMain").unwrap_all();
+		let c = v.get();
+
+		let main = c.find_method("main", "([Ljava/lang/String;)V");
+		assert!(main.is_some());
+		assert!(main.unwrap().is_static());
+		assert!(main.unwrap().get_code().is_some());
+
+		assert!(c.find_method("<init>", "()V").is_some());
+		assert!(c.find_method("main", "()V").is_none());
+
+		// interface methods are abstract and have no code
+		let i = cl.add_from_classfile("TestInterface").unwrap_all();
+		let foo = i.get().find_method("foo", "(Ljava/lang/String;)V");
+		assert!(foo.is_some());
+		assert!(foo.unwrap().is_abstract());
+		assert!(foo.unwrap().get_code().is_none());
+	}
+
+
+	#[test]
+	fn test_class_loader_constant_values() {
+		let mut cl = test_get_real_classloader();
+		let v = cl.add_from_classfile("StaticConstants").unwrap_all();
+		let c = v.get();
+
+		// LONG_VALUE precedes the others in the constant pool and
+		// thus also checks for correct handling of 2-slot entries.
+		match *c.find_static_field("LONG_VALUE").unwrap().get_constant_value() {
+			Some(CV_Long(l)) => assert_eq!(l, 1234567890123),
+			_ => assert!(false)
+		}
+		match *c.find_static_field("INT_VALUE").unwrap().get_constant_value() {
+			Some(CV_Integer(i)) => assert_eq!(i, 42),
+			_ => assert!(false)
+		}
+		match *c.find_static_field("DOUBLE_VALUE").unwrap().get_constant_value() {
+			Some(CV_Double(d)) => assert_eq!(d, 2.5),
+			_ => assert!(false)
+		}
+		match *c.find_static_field("STRING_VALUE").unwrap().get_constant_value() {
+			Some(CV_String(ref s)) => assert_eq!(*s, ~"rusty"),
+			_ => assert!(false)
+		}
+
+		// non-final statics do not have a ConstantValue
+		assert!(c.find_static_field("counter").unwrap().get_constant_value().is_none());
+	}
+
+
+	#[test]
+	fn test_class_loader_attributes() {
+		let mut cl = test_get_real_classloader();
+		let v = cl.add_from_classfile("FieldAccess").unwrap_all();
+		let c = v.get();
+
+		assert_eq!(*c.get_source_file().unwrap(), ~"FieldAccess.java");
+
+		// javac always emits line numbers for Code attributes
+		let code = c.find_method("main", "([Ljava/lang/String;)V").unwrap().get_code().unwrap();
+		assert!(code.get_line_number(0).is_some());
+	}
+
+
+	#[test]
+	fn test_class_loader_field_layout() {
+		let mut cl = test_get_real_classloader();
+		let mut v = cl.add_from_classfile("LayoutDerived").await();
+		assert_no_err(&v);
+
+		let c = v.unwrap();
+		let layout = c.get().get_instance_layout();
+
+		// int a, long b from LayoutBase, then Object c, double a
+		assert_eq!(layout.get_size(), 7);
+		assert_eq!(layout.find_declared("LayoutBase", "a", "I").unwrap().get_offset(), 0);
+		assert_eq!(layout.find("b", "J").unwrap().get_offset(), 1);
+		assert_eq!(layout.find("c", "Ljava/lang/Object;").unwrap().get_offset(), 3);
+		assert_eq!(*layout.find("a", "D").unwrap().get_declaring_class(), ~"LayoutDerived");
+		assert_eq!(layout.find("a", "D").unwrap().get_offset(), 5);
+
+		// statics are not inherited
+		let statics = c.get().get_static_layout();
+		assert_eq!(statics.get_size(), 1);
+		assert!(statics.find_by_name("shared").is_none());
+	}
+
+
+	#[test]
+	fn test_class_hierarchy_queries() {
+		let mut cl = test_get_real_classloader();
+		let mut v = cl.add_from_classfile("HierarchyImpl").await();
+		assert_no_err(&v);
+
+		let cref = v.unwrap();
+		let c = cref.get();
+		assert!(c.is_subclass_of("LayoutBase"));
+		assert!(!c.is_subclass_of("SubInterface"));
+		assert!(c.implements_interface("SubInterface"));
+		assert!(c.implements_interface("TestInterface"));
+		assert!(!c.implements_interface("LayoutBase"));
+		assert!(c.is_assignable_to("java.lang.Object"));
+		assert_eq!(c.get_interfaces().len(), 1);
+
+		let base = cl.get_class("LayoutBase").unwrap();
+		assert!(base.get().is_assignable_from(c, |n| cl.get_class(n)));
+		assert!(!c.is_assignable_from(base.get(), |n| cl.get_class(n)));
+
+		// fields: own class, then interfaces, then super classes
+		let (decl, _) = c.lookup_field("CONSTANT", "I").unwrap();
+		assert_eq!(*decl.get_name(), ~"SubInterface");
+		let (decl2, _) = c.lookup_field("a", "I").unwrap();
+		assert_eq!(*decl2.get_name(), ~"LayoutBase");
+		let (decl3, _) = c.lookup_field("a", "D").unwrap();
+		assert_eq!(*decl3.get_name(), ~"LayoutDerived");
+		assert!(c.lookup_field("a", "J").is_none());
+
+		// methods
+		let (mdecl, m) = c.lookup_method("foo", "(Ljava/lang/String;)V").unwrap();
+		assert_eq!(*mdecl.get_name(), ~"HierarchyImpl");
+		assert!(!m.is_abstract());
+		let (mdecl2, _) = c.lookup_method("hashCode", "()I").unwrap();
+		assert_eq!(*mdecl2.get_name(), ~"java.lang.Object");
+
+		let iface = cl.get_class("SubInterface").unwrap();
+		let (mdecl3, m3) = iface.get().lookup_interface_method("foo", "(Ljava/lang/String;)V").unwrap();
+		assert_eq!(*mdecl3.get_name(), ~"TestInterface");
+		assert!(m3.is_abstract());
+		assert!(iface.get().lookup_interface_method("hashCode", "()I").is_none());
+
+		// types, including arrays
+		let strings = cl.add_from_classfile("[Ljava.lang.String;").await().unwrap();
+		let objects = cl.add_from_classfile("[Ljava.lang.Object;").await().unwrap();
+		let ints = cl.add_from_classfile("[I").await().unwrap();
+		let longs = cl.add_from_classfile("[J").await().unwrap();
+		let resolve = |name : &str| cl.get_class(name);
+		let desc = |s : &str| JavaField::resolve_field_desc(s).unwrap();
+		assert!(JavaClass::is_type_assignable(&desc("[LTestInterface;"), &desc("[LHierarchyImpl;"), |n| resolve(n)));
+		assert!(!JavaClass::is_type_assignable(&desc("[LHierarchyImpl;"), &desc("[LTestInterface;"), |n| resolve(n)));
+		assert!(JavaClass::is_type_assignable(&desc("Ljava/lang/Cloneable;"), &desc("[[I"), |n| resolve(n)));
+		assert!(JavaClass::is_type_assignable(&desc("[Ljava/lang/Object;"), &desc("[[I"), |n| resolve(n)));
+		assert!(!JavaClass::is_type_assignable(&desc("[Ljava/lang/Object;"), &desc("[I"), |n| resolve(n)));
+		assert!(!JavaClass::is_type_assignable(&desc("[J"), &desc("[I"), |n| resolve(n)));
+
+		// and the same for array classes
+		assert!(objects.get().is_assignable_from(strings.get(), |n| resolve(n)));
+		assert!(!strings.get().is_assignable_from(objects.get(), |n| resolve(n)));
+		assert!(!longs.get().is_assignable_from(ints.get(), |n| resolve(n)));
+		assert!(!ints.get().is_assignable_from(longs.get(), |n| resolve(n)));
+		assert!(!objects.get().is_assignable_from(ints.get(), |n| resolve(n)));
+		assert!(ints.get().is_assignable_from(ints.get(), |n| resolve(n)));
+	}
+
+
+	#[test]
+	fn test_class_loader_array_classes() {
+		let mut cl = test_get_real_classloader();
+		let mut v = cl.add_from_classfile("[[Ljava.lang.String;").await();
+		assert_no_err(&v);
+
+		let cref = v.unwrap();
+		let c = cref.get();
+		assert!(c.is_array());
+		assert_eq!(c.get_access() & ACC_PUBLIC, ACC_PUBLIC);
+		assert_eq!(c.get_component_type().get_ref().to_str(), ~"[Ljava/lang/String;");
+		assert_eq!(c.get_superclass().unwrap().get().get_name(), &~"java.lang.Object");
+		assert!(c.implements_interface("java.lang.Cloneable"));
+		assert!(c.implements_interface("java.io.Serializable"));
+
+		// the component class is loaded along with the array class
+		assert!(cl.get_class("[Ljava.lang.String;").is_some());
+		assert!(cl.get_class("java.lang.String").is_some());
+
+		let prim = cl.add_from_classfile("[J").await().unwrap();
+		assert_eq!(prim.get().get_array_class_name(), ~"[[J");
+		assert!(cl.add_from_classfile("[Q").await().is_err());
+		assert!(cl.add_from_classfile("[LNonExistingClass;").await().is_err());
+	}
+
+
 	#[test]
 	fn test_class_loader_concurrent_loading() {
 		let mut cl_outer = test_get_real_classloader();
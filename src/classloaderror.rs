@@ -0,0 +1,227 @@
+// rustyVM - Java VM written in pure Rust
+// Copyright (c) 2013 Alexander Gessler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+
+// Error type for everything that can go wrong while locating, reading and
+// linking a class. The error kinds correspond to the LinkageErrors that
+// the JVM spec mandates for class loading and for the resolution of
+// symbolic references, see
+// http://docs.oracle.com/javase/specs/jvms/se7/html/jvms-5.html#jvms-5.3
+// http://docs.oracle.com/javase/specs/jvms/se7/html/jvms-5.html#jvms-5.4.3
+
+
+#[deriving(Clone, Eq, ToStr)]
+pub enum ClassLoadErrorKind {
+	// the class file is malformed
+	CLE_ClassFormatError,
+
+	// the class file's major/minor version is not supported
+	CLE_UnsupportedClassVersionError,
+
+	// the class (or one of its super classes or interfaces) could not
+	// be found, or it was found under the wrong name
+	CLE_NoClassDefFoundError,
+
+	// the class would be its own super class or super interface
+	CLE_ClassCircularityError,
+
+	// i.e. a class that extends an interface
+	CLE_IncompatibleClassChangeError,
+
+	// the class file was found, but reading it failed
+	CLE_IoError,
+
+	// a symbolic reference names a field or method that does not
+	// exist in the referenced class or its super types
+	CLE_NoSuchFieldError,
+	CLE_NoSuchMethodError,
+
+	// a symbolic reference names a class or member that the 
+	// referencing class is not allowed to access
+	CLE_IllegalAccessError,
+}
+
+
+#[deriving(Clone)]
+pub struct ClassLoadError {
+	priv kind : ClassLoadErrorKind,
+
+	// fully qualified name of the class that failed to load,
+	// empty if not known (yet) at the point the error occured.
+	priv class_name : ~str,
+
+	// offset into the class file at which the error was detected,
+	// if the error relates to the contents of the class file.
+	priv offset : Option<uint>,
+
+	priv message : ~str,
+}
+
+
+impl ClassLoadError {
+
+	// ----------------------------------------------
+	pub fn new(kind : ClassLoadErrorKind, message : &str) -> ClassLoadError
+	{
+		ClassLoadError {
+			kind : kind,
+			class_name : ~"",
+			offset : None,
+			message : message.into_owned(),
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Shorthand for constructing a ClassFormatError
+	pub fn new_format_error(message : &str) -> ClassLoadError
+	{
+		ClassLoadError::new(CLE_ClassFormatError, message)
+	}
+
+
+	// ----------------------------------------------
+	// Attach the name of the offending class, unless the error
+	// is already attributed to a class.
+	pub fn with_class(mut self, class_name : &str) -> ClassLoadError
+	{
+		if self.class_name.len() == 0 {
+			self.class_name = class_name.into_owned();
+		}
+		self
+	}
+
+
+	// ----------------------------------------------
+	// Attach the class file offset at which the error was detected,
+	// unless the error already carries an offset.
+	pub fn with_offset(mut self, offset : uint) -> ClassLoadError
+	{
+		if self.offset.is_none() {
+			self.offset = Some(offset);
+		}
+		self
+	}
+
+
+	// ----------------------------------------------
+	#[inline]
+	pub fn get_kind(&self) -> ClassLoadErrorKind {
+		self.kind
+	}
+
+
+	// ----------------------------------------------
+	pub fn get_class_name<'a>(&'a self) -> &'a ~str {
+		&self.class_name
+	}
+
+
+	// ----------------------------------------------
+	#[inline]
+	pub fn get_offset(&self) -> Option<uint> {
+		self.offset
+	}
+
+
+	// ----------------------------------------------
+	pub fn get_message<'a>(&'a self) -> &'a ~str {
+		&self.message
+	}
+
+
+	// ----------------------------------------------
+	// Get the fully qualified name of the Java exception that
+	// the error maps to when raised to Java code.
+	pub fn get_java_exception_name(&self) -> &'static str {
+		match self.kind {
+			CLE_ClassFormatError => "java.lang.ClassFormatError",
+			CLE_UnsupportedClassVersionError => "java.lang.UnsupportedClassVersionError",
+			CLE_NoClassDefFoundError => "java.lang.NoClassDefFoundError",
+			CLE_ClassCircularityError => "java.lang.ClassCircularityError",
+			CLE_IncompatibleClassChangeError => "java.lang.IncompatibleClassChangeError",
+			// the JVM spec leaves open how to report I/O failures,
+			// HotSpot raises NoClassDefFoundError for them.
+			CLE_IoError => "java.lang.NoClassDefFoundError",
+			CLE_NoSuchFieldError => "java.lang.NoSuchFieldError",
+			CLE_NoSuchMethodError => "java.lang.NoSuchMethodError",
+			CLE_IllegalAccessError => "java.lang.IllegalAccessError",
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Get the detail message of the Java exception that the error 
+	// maps to, i.e. Foo (offset 10): bad magic
+	pub fn get_detail_message(&self) -> ~str {
+		let mut s = self.class_name.clone();
+		match self.offset {
+			Some(off) => {
+				if s.len() > 0 {
+					s.push_char(' ');
+				}
+				s.push_str(format!("(offset {})", off));
+			},
+			None => ()
+		}
+		if self.message.len() > 0 {
+			if s.len() > 0 {
+				s.push_str(": ");
+			}
+			s.push_str(self.message);
+		}
+		s
+	}
+}
+
+
+// Renders the error in the same form the Java exception would be
+// printed, i.e. java.lang.ClassFormatError: Foo (offset 10): bad magic
+impl ToStr for ClassLoadError {
+	fn to_str(&self) -> ~str {
+		let detail = self.get_detail_message();
+		if detail.len() > 0 {
+			format!("{}: {}", self.get_java_exception_name(), detail)
+		}
+		else {
+			self.get_java_exception_name().to_owned()
+		}
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use classloaderror::*;
+
+	#[test]
+	fn test_class_load_error_context() {
+		let e = ClassLoadError::new_format_error("bad magic")
+			.with_class("Foo")
+			.with_offset(4);
+		assert_eq!(e.get_kind(), CLE_ClassFormatError);
+		assert_eq!(e.to_str(), ~"java.lang.ClassFormatError: Foo (offset 4): bad magic");
+
+		// context that is already present is not overwritten
+		let e2 = e.with_class("Bar").with_offset(8);
+		assert_eq!(*e2.get_class_name(), ~"Foo");
+		assert_eq!(e2.get_offset(), Some(4));
+	}
+}
@@ -24,7 +24,9 @@ extern mod extra;
 
 use extra::arc::{Arc};
 
-use std::io::{File,result, IoError};
+use std::io::{File,result, IoError, FileNotFound};
+
+use classloaderror::{ClassLoadError, CLE_NoClassDefFoundError, CLE_IoError};
 
 use std::path::{PosixPath};
 
@@ -64,8 +66,9 @@ impl ClassPath  {
 
 	// ----------------------------------------------
 	/** Locate a given class (given by fully qualified name) and return
-	 *  the bytes of its classfile. */
-	pub fn locate_and_read(&self, name : &str) -> Option<~[u8]>
+	 *  the bytes of its classfile. A class file that exists but cannot 
+	 *  be read is reported as CLE_IoError and not searched further. */
+	pub fn locate_and_read(&self, name : &str) -> Result<~[u8], ClassLoadError>
 	{
 		let cname = name.to_owned();
 		let pname = cname.replace(&".", "/") + ".class";
@@ -76,14 +79,19 @@ impl ClassPath  {
 				debug!("locate class {}, trying path {}", cname, p);
 				File::open(&PosixPath::new(p)).read_to_end()
 			}) {
-				Err(e) => continue,
+				Err(IoError { kind : FileNotFound, .. }) => continue,
+				Err(e) => {
+					return Err(ClassLoadError::new(CLE_IoError, 
+						"failed to read .class file: " + e.desc).with_class(cname))
+				},
 				Ok(bytes) => {
 					debug!("found .class file");
-					return Some(bytes)
+					return Ok(bytes)
 				}
 			};
 		}
-		return None
+		return Err(ClassLoadError::new(CLE_NoClassDefFoundError, 
+			"failed to locate class file in classpath").with_class(cname))
 	}
 }
 
@@ -100,6 +108,7 @@ impl Clone for ClassPath {
 #[cfg(test)]
 mod tests {
 	use classpath::*;
+	use classloaderror::*;
 
 	#[test]
 	fn test_class_path_decomposition() {
@@ -108,4 +117,15 @@ mod tests {
 		assert_eq!(*cp.get_paths(),~[~".",~"~/some/other/bar", ~"/bar/baz", ~"dir"]);
 	}
 
+
+	#[test]
+	fn test_class_path_locate() {
+		let cp = ClassPath::new_from_string("../test/java");
+		assert!(cp.locate_and_read("EmptyClass").is_ok());
+
+		let e = cp.locate_and_read("NoSuchClass").unwrap_err();
+		assert_eq!(e.get_kind(), CLE_NoClassDefFoundError);
+		assert_eq!(*e.get_class_name(), ~"NoSuchClass");
+	}
+
 }
\ No newline at end of file
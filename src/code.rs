@@ -19,15 +19,24 @@
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 //
 
+use std::hashmap::{HashMap};
+use std::num::FromPrimitive;
+
 use class::{JavaClassFutureRef};
+use attribute::{Attribute, ATTR_LineNumberTable};
+use opcode::*;
 
 
 pub struct ExceptionHandler
 {
+	// range of bytecode covered by the handler, end exclusive,
+	// and start of the handler
 	start_pc : uint,
 	end_pc : uint,
 	handler_pc : uint,
-	catch_type : ~str,
+
+	// class of exceptions caught by the handler, None to catch all
+	catch_type : Option<~str>,
 }
 
 
@@ -36,29 +45,727 @@ pub struct CodeBlock
 	priv max_stack : uint,
 	priv max_locals : uint,
 	priv code : ~[u8],
-	priv exceptions : ~[ExceptionHandler]
+	priv exceptions : ~[ExceptionHandler],
+
+	// decoded instructions, and the bytecode offset of each of them
+	priv opcodes : ~[DecodedOpcode],
+	priv pcs : ~[uint],
+
+	// attributes of the Code attribute, i.e. LineNumberTable
+	priv attributes : ~[Attribute],
 }
 
 
 impl CodeBlock
 {
 	// ----------------------------------------------
-	pub fn new(max_stack : uint, max_locals : uint, code : ~[u8], exceptions : ~[ExceptionHandler]) -> 
-		CodeBlock
+	// Construct a CodeBlock from the contents of a Code attribute. The
+	// bytecode is decoded right away, which fails if it is malformed.
+	pub fn new(max_stack : uint, max_locals : uint, code : ~[u8], exceptions : ~[ExceptionHandler],
+		attributes : ~[Attribute]) -> 
+		Result<CodeBlock, ~str>
 	{
-		CodeBlock {
+		// JVMS 4.7.3: code_length must be greater than zero
+		if code.len() == 0 {
+			return Err(~"empty code");
+		}
+		let (opcodes, pcs) = match CodeBlock::decode_opcodes(code) {
+			Err(s) => return Err(s),
+			Ok(res) => res
+		};
+
+		// exception handler ranges must start at instruction boundaries,
+		// the (exclusive) end may also be the end of the code.
+		for e in exceptions.iter() {
+			let is_boundary = |pc : uint| pcs.bsearch_elem(&pc).is_some();
+			if e.start_pc >= e.end_pc || !is_boundary(e.start_pc) || !is_boundary(e.handler_pc) ||
+				!(e.end_pc == code.len() || is_boundary(e.end_pc)) {
+				return Err(format!("invalid exception handler range [{}, {}) -> {}", 
+					e.start_pc, e.end_pc, e.handler_pc));
+			}
+		}
+
+		Ok(CodeBlock {
 			max_stack : max_stack,
 			max_locals : max_locals,
 			code : code,
-			exceptions : exceptions
+			exceptions : exceptions,
+			opcodes : opcodes,
+			pcs : pcs,
+			attributes : attributes
+		})
+	}
+
+
+	// ----------------------------------------------
+	#[inline]
+	pub fn get_max_stack(&self) -> uint {
+		self.max_stack
+	}
+
+
+	// ----------------------------------------------
+	#[inline]
+	pub fn get_max_locals(&self) -> uint {
+		self.max_locals
+	}
+
+
+	// ----------------------------------------------
+	pub fn get_exception_handlers<'a>(&'a self) -> &'a ~[ExceptionHandler] {
+		&self.exceptions
+	}
+
+
+	// ----------------------------------------------
+	// Get the decoded instructions. Branch targets in them are
+	// indices into this list.
+	pub fn get_opcodes<'a>(&'a self) -> &'a ~[DecodedOpcode] {
+		&self.opcodes
+	}
+
+
+	// ----------------------------------------------
+	// Get the bytecode offset of the instruction with the given index
+	#[inline]
+	pub fn get_pc(&self, index : uint) -> uint {
+		self.pcs[index]
+	}
+
+
+	// ----------------------------------------------
+	// Get the index of the instruction at the given bytecode offset
+	pub fn get_index_for_pc(&self, pc : uint) -> Option<uint> {
+		self.pcs.bsearch_elem(&pc)
+	}
+
+
+	// ----------------------------------------------
+	pub fn get_attributes<'a>(&'a self) -> &'a ~[Attribute] {
+		&self.attributes
+	}
+
+
+	// ----------------------------------------------
+	// Map a bytecode offset to a source line number using the 
+	// LineNumberTable attribute(s), if present.
+	pub fn get_line_number(&self, pc : uint) -> Option<uint> {
+		let mut best : Option<(uint, uint)> = None;
+		for a in self.attributes.iter() {
+			match *a {
+				ATTR_LineNumberTable(ref table) => {
+					for ln in table.iter() {
+						if ln.start_pc <= pc && best.map_default(true, |(spc, _)| ln.start_pc >= spc) {
+							best = Some((ln.start_pc, ln.line_number));
+						}
+					}
+				},
+				_ => ()
+			}
 		}
+		best.map(|(_, line)| line)
 	}
 
 
 	// ----------------------------------------------
-	pub fn decode_opcodes()
+	// Decode raw bytecode into DecodedOpcode values. Returns the decoded
+	// instructions and the bytecode offset of each instruction. Fails for
+	// unknown or reserved opcodes, truncated instructions, malformed 
+	// operands and branches that do not target the start of an 
+	// instruction within the code.
+	pub fn decode_opcodes(code : &[u8]) -> Result<(~[DecodedOpcode], ~[uint]), ~str>
 	{
-		// TODO
+		let mut opcodes : ~[DecodedOpcode] = ~[];
+		let mut pcs : ~[uint] = ~[];
+		let mut indices : HashMap<uint, uint> = HashMap::new();
+
+		// first pass: decode instructions, branch targets are offsets
+		let mut pc = 0;
+		while pc < code.len() {
+			let len = match get_instruction_length(code, pc) {
+				Err(s) => return Err(s),
+				Ok(len) => len
+			};
+			match decode_instruction(code, pc) {
+				Err(s) => return Err(s),
+				Ok(op) => {
+					indices.insert(pc, opcodes.len());
+					opcodes.push(op);
+					pcs.push(pc);
+				}
+			}
+			pc += len;
+		}
+
+		// second pass: map branch target offsets to instruction indices
+		for (i, op) in opcodes.mut_iter().enumerate() {
+			match map_branch_targets(op, |target| indices.find(&target).map(|idx| *idx)) {
+				None => (),
+				Some(target) => return Err(format!(
+					"branch target {} of instruction at offset {} is not the start of an instruction", 
+					target, pcs[i]))
+			}
+		}
+		Ok((opcodes, pcs))
 	}
 }
 
+
+// IMPL
+
+
+// ----------------------------------------------
+#[inline]
+fn read_u1(code : &[u8], pos : uint) -> uint {
+	code[pos] as uint
+}
+
+#[inline]
+fn read_u2(code : &[u8], pos : uint) -> uint {
+	(code[pos] as uint << 8) | code[pos + 1] as uint
+}
+
+#[inline]
+fn read_s2(code : &[u8], pos : uint) -> i32 {
+	read_u2(code, pos) as u16 as i16 as i32
+}
+
+#[inline]
+fn read_s4(code : &[u8], pos : uint) -> i32 {
+	((code[pos] as u32 << 24) | (code[pos + 1] as u32 << 16) | 
+		(code[pos + 2] as u32 << 8) | code[pos + 3] as u32) as i32
+}
+
+
+// ----------------------------------------------
+// Get the number of padding bytes that follow the opcode of a 
+// tableswitch or lookupswitch instruction at the given offset 
+#[inline]
+fn get_switch_padding(pc : uint) -> uint {
+	3 - (pc % 4)
+}
+
+
+// ----------------------------------------------
+// Determine the length, in bytes, of the instruction at the given
+// offset and check that it is entirely contained in the code.
+fn get_instruction_length(code : &[u8], pc : uint) -> Result<uint, ~str> {
+	let truncated = || Err(format!("truncated instruction at offset {}", pc));
+	let opcode : Option<Opcode> = FromPrimitive::from_u8(code[pc]);
+	let len = match opcode {
+		None => return Err(format!("unknown opcode {} at offset {}", code[pc], pc)),
+		Some(op) => match op {
+			OpCode_bipush | OpCode_ldc | OpCode_newarray | OpCode_ret |
+			OpCode_iload | OpCode_lload | OpCode_fload | OpCode_dload | OpCode_aload |
+			OpCode_istore | OpCode_lstore | OpCode_fstore | OpCode_dstore | OpCode_astore => 2,
+
+			OpCode_sipush | OpCode_ldc_w | OpCode_ldc2_w | OpCode_iinc |
+			OpCode_ifeq | OpCode_ifne | OpCode_iflt | OpCode_ifge | OpCode_ifgt | OpCode_ifle |
+			OpCode_if_icmpeq | OpCode_if_icmpne | OpCode_if_icmplt | OpCode_if_icmpge | 
+			OpCode_if_icmpgt | OpCode_if_icmple | OpCode_if_acmpeq | OpCode_if_acmpne |
+			OpCode_goto | OpCode_jsr | OpCode_ifnull | OpCode_ifnonnull |
+			OpCode_getstatic | OpCode_putstatic | OpCode_getfield | OpCode_putfield |
+			OpCode_invokevirtual | OpCode_invokespecial | OpCode_invokestatic |
+			OpCode_new | OpCode_anewarray | OpCode_checkcast | OpCode_instanceof => 3,
+
+			OpCode_multianewarray => 4,
+
+			OpCode_invokeinterface | OpCode_invokedynamic | OpCode_goto_w | OpCode_jsr_w => 5,
+
+			OpCode_wide => {
+				if pc + 1 >= code.len() {
+					return truncated();
+				}
+				if code[pc + 1] == OpCode_iinc as u8 { 6 } else { 4 }
+			},
+
+			OpCode_tableswitch => {
+				// default, low, high
+				let base = pc + 1 + get_switch_padding(pc);
+				if base + 12 > code.len() {
+					return truncated();
+				}
+				let low = read_s4(code, base + 4) as i64;
+				let high = read_s4(code, base + 8) as i64;
+				if low > high {
+					return Err(format!("tableswitch at offset {} has low > high", pc));
+				}
+				base + 12 + 4 * ((high - low + 1) as uint) - pc
+			},
+
+			OpCode_lookupswitch => {
+				// default, npairs
+				let base = pc + 1 + get_switch_padding(pc);
+				if base + 8 > code.len() {
+					return truncated();
+				}
+				let npairs = read_s4(code, base + 4);
+				if npairs < 0 {
+					return Err(format!("lookupswitch at offset {} has negative npairs", pc));
+				}
+				base + 8 + 8 * (npairs as uint) - pc
+			},
+
+			OpCode_breakpoint | OpCode_impdep1 | OpCode_impdep2 => 
+				return Err(format!("reserved opcode {} at offset {}", op.to_str(), pc)),
+
+			_ => 1
+		}
+	};
+
+	if pc + len > code.len() {
+		return truncated();
+	}
+	Ok(len)
+}
+
+
+// ----------------------------------------------
+// Compute the absolute target offset of a branch
+fn get_branch_target(code : &[u8], pc : uint, offset : i32) -> Result<uint, ~str> {
+	let target = pc as i64 + offset as i64;
+	if target < 0 || target >= code.len() as i64 {
+		return Err(format!("branch target of instruction at offset {} is outside the code", pc));
+	}
+	Ok(target as uint)
+}
+
+
+// ----------------------------------------------
+// Decode the instruction at the given offset. Its length has been 
+// checked by get_instruction_length() already. Branch targets are
+// absolute bytecode offsets.
+fn decode_instruction(code : &[u8], pc : uint) -> Result<DecodedOpcode, ~str> {
+	let op : Opcode = FromPrimitive::from_u8(code[pc]).unwrap();
+	let u1 = || read_u1(code, pc + 1);
+	let u2 = || read_u2(code, pc + 1);
+	let s2 = || read_s2(code, pc + 1);
+	let branch16 = || get_branch_target(code, pc, read_s2(code, pc + 1));
+
+	let decoded = match op {
+		OpCode_nop => DecodedOpcode_nop,
+		OpCode_aconst_null => DecodedOpcode_aconst_null,
+		OpCode_iconst_m1 => DecodedOpcode_iconst(-1),
+		OpCode_iconst_0 => DecodedOpcode_iconst(0),
+		OpCode_iconst_1 => DecodedOpcode_iconst(1),
+		OpCode_iconst_2 => DecodedOpcode_iconst(2),
+		OpCode_iconst_3 => DecodedOpcode_iconst(3),
+		OpCode_iconst_4 => DecodedOpcode_iconst(4),
+		OpCode_iconst_5 => DecodedOpcode_iconst(5),
+		OpCode_lconst_0 => DecodedOpcode_lconst(0),
+		OpCode_lconst_1 => DecodedOpcode_lconst(1),
+		OpCode_fconst_0 => DecodedOpcode_fconst(0.0),
+		OpCode_fconst_1 => DecodedOpcode_fconst(1.0),
+		OpCode_fconst_2 => DecodedOpcode_fconst(2.0),
+		OpCode_dconst_0 => DecodedOpcode_dconst(0.0),
+		OpCode_dconst_1 => DecodedOpcode_dconst(1.0),
+		OpCode_bipush => DecodedOpcode_iconst(u1() as u8 as i8 as i32),
+		OpCode_sipush => DecodedOpcode_iconst(s2()),
+		OpCode_ldc => DecodedOpcode_ldc(u1() as u16),
+		OpCode_ldc_w => DecodedOpcode_ldc(u2() as u16),
+		OpCode_ldc2_w => DecodedOpcode_ldc2_w(u2() as u16),
+
+		OpCode_iload => DecodedOpcode_iload(u1()),
+		OpCode_lload => DecodedOpcode_lload(u1()),
+		OpCode_fload => DecodedOpcode_fload(u1()),
+		OpCode_dload => DecodedOpcode_dload(u1()),
+		OpCode_aload => DecodedOpcode_aload(u1()),
+		OpCode_iload_0 => DecodedOpcode_iload(0),
+		OpCode_iload_1 => DecodedOpcode_iload(1),
+		OpCode_iload_2 => DecodedOpcode_iload(2),
+		OpCode_iload_3 => DecodedOpcode_iload(3),
+		OpCode_lload_0 => DecodedOpcode_lload(0),
+		OpCode_lload_1 => DecodedOpcode_lload(1),
+		OpCode_lload_2 => DecodedOpcode_lload(2),
+		OpCode_lload_3 => DecodedOpcode_lload(3),
+		OpCode_fload_0 => DecodedOpcode_fload(0),
+		OpCode_fload_1 => DecodedOpcode_fload(1),
+		OpCode_fload_2 => DecodedOpcode_fload(2),
+		OpCode_fload_3 => DecodedOpcode_fload(3),
+		OpCode_dload_0 => DecodedOpcode_dload(0),
+		OpCode_dload_1 => DecodedOpcode_dload(1),
+		OpCode_dload_2 => DecodedOpcode_dload(2),
+		OpCode_dload_3 => DecodedOpcode_dload(3),
+		OpCode_aload_0 => DecodedOpcode_aload(0),
+		OpCode_aload_1 => DecodedOpcode_aload(1),
+		OpCode_aload_2 => DecodedOpcode_aload(2),
+		OpCode_aload_3 => DecodedOpcode_aload(3),
+		OpCode_iaload => DecodedOpcode_iaload,
+		OpCode_laload => DecodedOpcode_laload,
+		OpCode_faload => DecodedOpcode_faload,
+		OpCode_daload => DecodedOpcode_daload,
+		OpCode_aaload => DecodedOpcode_aaload,
+		OpCode_baload => DecodedOpcode_baload,
+		OpCode_caload => DecodedOpcode_caload,
+		OpCode_saload => DecodedOpcode_saload,
+
+		OpCode_istore => DecodedOpcode_istore(u1()),
+		OpCode_lstore => DecodedOpcode_lstore(u1()),
+		OpCode_fstore => DecodedOpcode_fstore(u1()),
+		OpCode_dstore => DecodedOpcode_dstore(u1()),
+		OpCode_astore => DecodedOpcode_astore(u1()),
+		OpCode_istore_0 => DecodedOpcode_istore(0),
+		OpCode_istore_1 => DecodedOpcode_istore(1),
+		OpCode_istore_2 => DecodedOpcode_istore(2),
+		OpCode_istore_3 => DecodedOpcode_istore(3),
+		OpCode_lstore_0 => DecodedOpcode_lstore(0),
+		OpCode_lstore_1 => DecodedOpcode_lstore(1),
+		OpCode_lstore_2 => DecodedOpcode_lstore(2),
+		OpCode_lstore_3 => DecodedOpcode_lstore(3),
+		OpCode_fstore_0 => DecodedOpcode_fstore(0),
+		OpCode_fstore_1 => DecodedOpcode_fstore(1),
+		OpCode_fstore_2 => DecodedOpcode_fstore(2),
+		OpCode_fstore_3 => DecodedOpcode_fstore(3),
+		OpCode_dstore_0 => DecodedOpcode_dstore(0),
+		OpCode_dstore_1 => DecodedOpcode_dstore(1),
+		OpCode_dstore_2 => DecodedOpcode_dstore(2),
+		OpCode_dstore_3 => DecodedOpcode_dstore(3),
+		OpCode_astore_0 => DecodedOpcode_astore(0),
+		OpCode_astore_1 => DecodedOpcode_astore(1),
+		OpCode_astore_2 => DecodedOpcode_astore(2),
+		OpCode_astore_3 => DecodedOpcode_astore(3),
+		OpCode_iastore => DecodedOpcode_iastore,
+		OpCode_lastore => DecodedOpcode_lastore,
+		OpCode_fastore => DecodedOpcode_fastore,
+		OpCode_dastore => DecodedOpcode_dastore,
+		OpCode_aastore => DecodedOpcode_aastore,
+		OpCode_bastore => DecodedOpcode_bastore,
+		OpCode_castore => DecodedOpcode_castore,
+		OpCode_sastore => DecodedOpcode_sastore,
+
+		OpCode_pop => DecodedOpcode_pop,
+		OpCode_pop2 => DecodedOpcode_pop2,
+		OpCode_dup => DecodedOpcode_dup,
+		OpCode_dup_x1 => DecodedOpcode_dup_x1,
+		OpCode_dup_x2 => DecodedOpcode_dup_x2,
+		OpCode_dup2 => DecodedOpcode_dup2,
+		OpCode_dup2_x1 => DecodedOpcode_dup2_x1,
+		OpCode_dup2_x2 => DecodedOpcode_dup2_x2,
+		OpCode_swap => DecodedOpcode_swap,
+
+		OpCode_iadd => DecodedOpcode_iadd,
+		OpCode_ladd => DecodedOpcode_ladd,
+		OpCode_fadd => DecodedOpcode_fadd,
+		OpCode_dadd => DecodedOpcode_dadd,
+		OpCode_isub => DecodedOpcode_isub,
+		OpCode_lsub => DecodedOpcode_lsub,
+		OpCode_fsub => DecodedOpcode_fsub,
+		OpCode_dsub => DecodedOpcode_dsub,
+		OpCode_imul => DecodedOpcode_imul,
+		OpCode_lmul => DecodedOpcode_lmul,
+		OpCode_fmul => DecodedOpcode_fmul,
+		OpCode_dmul => DecodedOpcode_dmul,
+		OpCode_idiv => DecodedOpcode_idiv,
+		OpCode_ldiv => DecodedOpcode_ldiv,
+		OpCode_fdiv => DecodedOpcode_fdiv,
+		OpCode_ddiv => DecodedOpcode_ddiv,
+		OpCode_irem => DecodedOpcode_irem,
+		OpCode_lrem => DecodedOpcode_lrem,
+		OpCode_frem => DecodedOpcode_frem,
+		OpCode_drem => DecodedOpcode_drem,
+		OpCode_ineg => DecodedOpcode_ineg,
+		OpCode_lneg => DecodedOpcode_lneg,
+		OpCode_fneg => DecodedOpcode_fneg,
+		OpCode_dneg => DecodedOpcode_dneg,
+		OpCode_ishl => DecodedOpcode_ishl,
+		OpCode_lshl => DecodedOpcode_lshl,
+		OpCode_ishr => DecodedOpcode_ishr,
+		OpCode_lshr => DecodedOpcode_lshr,
+		OpCode_iushr => DecodedOpcode_iushr,
+		OpCode_lushr => DecodedOpcode_lushr,
+		OpCode_iand => DecodedOpcode_iand,
+		OpCode_land => DecodedOpcode_land,
+		OpCode_ior => DecodedOpcode_ior,
+		OpCode_lor => DecodedOpcode_lor,
+		OpCode_ixor => DecodedOpcode_ixor,
+		OpCode_lxor => DecodedOpcode_lxor,
+		OpCode_iinc => DecodedOpcode_iinc(u1(), read_u1(code, pc + 2) as u8 as i8 as i32),
+
+		OpCode_i2l => DecodedOpcode_i2l,
+		OpCode_i2f => DecodedOpcode_i2f,
+		OpCode_i2d => DecodedOpcode_i2d,
+		OpCode_l2i => DecodedOpcode_l2i,
+		OpCode_l2f => DecodedOpcode_l2f,
+		OpCode_l2d => DecodedOpcode_l2d,
+		OpCode_f2i => DecodedOpcode_f2i,
+		OpCode_f2l => DecodedOpcode_f2l,
+		OpCode_f2d => DecodedOpcode_f2d,
+		OpCode_d2i => DecodedOpcode_d2i,
+		OpCode_d2l => DecodedOpcode_d2l,
+		OpCode_d2f => DecodedOpcode_d2f,
+		OpCode_i2b => DecodedOpcode_i2b,
+		OpCode_i2c => DecodedOpcode_i2c,
+		OpCode_i2s => DecodedOpcode_i2s,
+		OpCode_lcmp => DecodedOpcode_lcmp,
+		OpCode_fcmpl => DecodedOpcode_fcmpl,
+		OpCode_fcmpg => DecodedOpcode_fcmpg,
+		OpCode_dcmpl => DecodedOpcode_dcmpl,
+		OpCode_dcmpg => DecodedOpcode_dcmpg,
+
+		OpCode_ifeq | OpCode_ifne | OpCode_iflt | OpCode_ifge | OpCode_ifgt | OpCode_ifle |
+		OpCode_if_icmpeq | OpCode_if_icmpne | OpCode_if_icmplt | OpCode_if_icmpge | 
+		OpCode_if_icmpgt | OpCode_if_icmple | OpCode_if_acmpeq | OpCode_if_acmpne |
+		OpCode_goto | OpCode_jsr | OpCode_ifnull | OpCode_ifnonnull => {
+			let target = match branch16() {
+				Err(s) => return Err(s),
+				Ok(t) => t
+			};
+			match op {
+				OpCode_ifeq => DecodedOpcode_ifeq(target),
+				OpCode_ifne => DecodedOpcode_ifne(target),
+				OpCode_iflt => DecodedOpcode_iflt(target),
+				OpCode_ifge => DecodedOpcode_ifge(target),
+				OpCode_ifgt => DecodedOpcode_ifgt(target),
+				OpCode_ifle => DecodedOpcode_ifle(target),
+				OpCode_if_icmpeq => DecodedOpcode_if_icmpeq(target),
+				OpCode_if_icmpne => DecodedOpcode_if_icmpne(target),
+				OpCode_if_icmplt => DecodedOpcode_if_icmplt(target),
+				OpCode_if_icmpge => DecodedOpcode_if_icmpge(target),
+				OpCode_if_icmpgt => DecodedOpcode_if_icmpgt(target),
+				OpCode_if_icmple => DecodedOpcode_if_icmple(target),
+				OpCode_if_acmpeq => DecodedOpcode_if_acmpeq(target),
+				OpCode_if_acmpne => DecodedOpcode_if_acmpne(target),
+				OpCode_goto => DecodedOpcode_goto(target),
+				OpCode_jsr => DecodedOpcode_jsr(target),
+				OpCode_ifnull => DecodedOpcode_ifnull(target),
+				_ => DecodedOpcode_ifnonnull(target),
+			}
+		},
+		OpCode_goto_w | OpCode_jsr_w => {
+			let target = match get_branch_target(code, pc, read_s4(code, pc + 1)) {
+				Err(s) => return Err(s),
+				Ok(t) => t
+			};
+			if op == OpCode_goto_w { DecodedOpcode_goto(target) } else { DecodedOpcode_jsr(target) }
+		},
+		OpCode_ret => DecodedOpcode_ret(u1()),
+
+		OpCode_tableswitch => {
+			let base = pc + 1 + get_switch_padding(pc);
+			let default = match get_branch_target(code, pc, read_s4(code, base)) {
+				Err(s) => return Err(s),
+				Ok(t) => t
+			};
+			let low = read_s4(code, base + 4);
+			let high = read_s4(code, base + 8);
+			let mut targets : ~[uint] = ~[];
+			for i in range(0, (high as i64 - low as i64 + 1) as uint) {
+				match get_branch_target(code, pc, read_s4(code, base + 12 + 4 * i)) {
+					Err(s) => return Err(s),
+					Ok(t) => targets.push(t)
+				}
+			}
+			DecodedOpcode_tableswitch(default, low, targets)
+		},
+		OpCode_lookupswitch => {
+			let base = pc + 1 + get_switch_padding(pc);
+			let default = match get_branch_target(code, pc, read_s4(code, base)) {
+				Err(s) => return Err(s),
+				Ok(t) => t
+			};
+			let npairs = read_s4(code, base + 4) as uint;
+			let mut pairs : ~[(i32, uint)] = ~[];
+			for i in range(0, npairs) {
+				let key = read_s4(code, base + 8 + 8 * i);
+				// keys must be sorted in increasing order
+				if i > 0 {
+					let (prev_key, _) = pairs[i - 1];
+					if prev_key >= key {
+						return Err(format!("lookupswitch at offset {} has unsorted keys", pc));
+					}
+				}
+				match get_branch_target(code, pc, read_s4(code, base + 12 + 8 * i)) {
+					Err(s) => return Err(s),
+					Ok(t) => pairs.push((key, t))
+				}
+			}
+			DecodedOpcode_lookupswitch(default, pairs)
+		},
+
+		OpCode_ireturn => DecodedOpcode_ireturn,
+		OpCode_lreturn => DecodedOpcode_lreturn,
+		OpCode_freturn => DecodedOpcode_freturn,
+		OpCode_dreturn => DecodedOpcode_dreturn,
+		OpCode_areturn => DecodedOpcode_areturn,
+		OpCode_return => DecodedOpcode_return,
+
+		OpCode_getstatic => DecodedOpcode_getstatic(u2() as u16),
+		OpCode_putstatic => DecodedOpcode_putstatic(u2() as u16),
+		OpCode_getfield => DecodedOpcode_getfield(u2() as u16),
+		OpCode_putfield => DecodedOpcode_putfield(u2() as u16),
+		OpCode_invokevirtual => DecodedOpcode_invokevirtual(u2() as u16),
+		OpCode_invokespecial => DecodedOpcode_invokespecial(u2() as u16),
+		OpCode_invokestatic => DecodedOpcode_invokestatic(u2() as u16),
+		OpCode_invokeinterface => {
+			let count = read_u1(code, pc + 3);
+			if count == 0 || read_u1(code, pc + 4) != 0 {
+				return Err(format!("malformed invokeinterface at offset {}", pc));
+			}
+			DecodedOpcode_invokeinterface(u2() as u16, count)
+		},
+		OpCode_invokedynamic => {
+			if read_u2(code, pc + 3) != 0 {
+				return Err(format!("malformed invokedynamic at offset {}", pc));
+			}
+			DecodedOpcode_invokedynamic(u2() as u16)
+		},
+		OpCode_new => DecodedOpcode_new(u2() as u16),
+		OpCode_newarray => {
+			// T_BOOLEAN (4) to T_LONG (11)
+			let atype = u1();
+			if atype < 4 || atype > 11 {
+				return Err(format!("invalid newarray type {} at offset {}", atype, pc));
+			}
+			DecodedOpcode_newarray(atype as u8)
+		},
+		OpCode_anewarray => DecodedOpcode_anewarray(u2() as u16),
+		OpCode_arraylength => DecodedOpcode_arraylength,
+		OpCode_athrow => DecodedOpcode_athrow,
+		OpCode_checkcast => DecodedOpcode_checkcast(u2() as u16),
+		OpCode_instanceof => DecodedOpcode_instanceof(u2() as u16),
+		OpCode_monitorenter => DecodedOpcode_monitorenter,
+		OpCode_monitorexit => DecodedOpcode_monitorexit,
+		OpCode_multianewarray => {
+			let dims = read_u1(code, pc + 3);
+			if dims == 0 {
+				return Err(format!("multianewarray with zero dimensions at offset {}", pc));
+			}
+			DecodedOpcode_multianewarray(u2() as u16, dims)
+		},
+
+		OpCode_wide => {
+			let index = read_u2(code, pc + 2);
+			let inner : Option<Opcode> = FromPrimitive::from_u8(code[pc + 1]);
+			match inner {
+				Some(OpCode_iload) => DecodedOpcode_iload(index),
+				Some(OpCode_lload) => DecodedOpcode_lload(index),
+				Some(OpCode_fload) => DecodedOpcode_fload(index),
+				Some(OpCode_dload) => DecodedOpcode_dload(index),
+				Some(OpCode_aload) => DecodedOpcode_aload(index),
+				Some(OpCode_istore) => DecodedOpcode_istore(index),
+				Some(OpCode_lstore) => DecodedOpcode_lstore(index),
+				Some(OpCode_fstore) => DecodedOpcode_fstore(index),
+				Some(OpCode_dstore) => DecodedOpcode_dstore(index),
+				Some(OpCode_astore) => DecodedOpcode_astore(index),
+				Some(OpCode_ret) => DecodedOpcode_ret(index),
+				Some(OpCode_iinc) => DecodedOpcode_iinc(index, read_s2(code, pc + 4)),
+				_ => return Err(format!("invalid instruction {} following wide at offset {}", 
+					code[pc + 1], pc))
+			}
+		},
+
+		// rejected by get_instruction_length()
+		OpCode_breakpoint | OpCode_impdep1 | OpCode_impdep2 => fail!("logic error, reserved opcode"),
+	};
+	Ok(decoded)
+}
+
+
+// ----------------------------------------------
+// Apply a mapping to all branch targets of an instruction. Returns
+// the first target for which the mapping yields None.
+fn map_branch_targets(op : &mut DecodedOpcode, map : |uint| -> Option<uint>) -> Option<uint> {
+	match *op {
+		DecodedOpcode_ifeq(ref mut t) | DecodedOpcode_ifne(ref mut t) | 
+		DecodedOpcode_iflt(ref mut t) | DecodedOpcode_ifge(ref mut t) | 
+		DecodedOpcode_ifgt(ref mut t) | DecodedOpcode_ifle(ref mut t) |
+		DecodedOpcode_if_icmpeq(ref mut t) | DecodedOpcode_if_icmpne(ref mut t) | 
+		DecodedOpcode_if_icmplt(ref mut t) | DecodedOpcode_if_icmpge(ref mut t) | 
+		DecodedOpcode_if_icmpgt(ref mut t) | DecodedOpcode_if_icmple(ref mut t) | 
+		DecodedOpcode_if_acmpeq(ref mut t) | DecodedOpcode_if_acmpne(ref mut t) |
+		DecodedOpcode_goto(ref mut t) | DecodedOpcode_jsr(ref mut t) | 
+		DecodedOpcode_ifnull(ref mut t) | DecodedOpcode_ifnonnull(ref mut t) => {
+			match map(*t) {
+				None => return Some(*t),
+				Some(idx) => *t = idx
+			}
+		},
+		DecodedOpcode_tableswitch(ref mut default, _, ref mut targets) => {
+			for t in targets.mut_iter() {
+				match map(*t) {
+					None => return Some(*t),
+					Some(idx) => *t = idx
+				}
+			}
+			match map(*default) {
+				None => return Some(*default),
+				Some(idx) => *default = idx
+			}
+		},
+		DecodedOpcode_lookupswitch(ref mut default, ref mut pairs) => {
+			for i in range(0, pairs.len()) {
+				let (key, t) = pairs[i];
+				match map(t) {
+					None => return Some(t),
+					Some(idx) => pairs[i] = (key, idx)
+				}
+			}
+			match map(*default) {
+				None => return Some(*default),
+				Some(idx) => *default = idx
+			}
+		},
+		_ => ()
+	}
+	None
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use code::*;
+	use opcode::*;
+
+	#[test]
+	fn test_decode_opcodes() {
+		// iconst_1, bipush -2, wide iload 300, istore_2, iinc 2 -1
+		let (ops, pcs) = CodeBlock::decode_opcodes([0x04, 0x10, 0xfe, 0xc4, 0x15, 0x01, 0x2c, 
+			0x3d, 0x84, 0x02, 0xff]).unwrap();
+		assert_eq!(ops, ~[DecodedOpcode_iconst(1), DecodedOpcode_iconst(-2), DecodedOpcode_iload(300),
+			DecodedOpcode_istore(2), DecodedOpcode_iinc(2, -1)]);
+		assert_eq!(pcs, ~[0, 1, 3, 7, 8]);
+
+		// branch targets become instruction indices: 
+		// 0: iload_0, 1: ifeq +7 (to 8), 4: iconst_0, 5: goto -5 (to 0), 8: return
+		let (ops, _) = CodeBlock::decode_opcodes([0x1a, 0x99, 0x00, 0x07, 0x03, 0xa7, 0xff, 0xfb, 
+			0xb1]).unwrap();
+		assert_eq!(ops, ~[DecodedOpcode_iload(0), DecodedOpcode_ifeq(4), DecodedOpcode_iconst(0),
+			DecodedOpcode_goto(0), DecodedOpcode_return]);
+
+		// 0: iload_0, 1: tableswitch (2 bytes padding) default +26, 
+		// low 1, high 2, targets +24 +25; 24: nop, 25: nop, 26: nop, 27: return
+		let (ops, _) = CodeBlock::decode_opcodes([0x1a, 0xaa, 0x00, 0x00, 
+			0x00, 0x00, 0x00, 0x1a, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02,
+			0x00, 0x00, 0x00, 0x18, 0x00, 0x00, 0x00, 0x19, 
+			0x00, 0x00, 0x00, 0xb1]).unwrap();
+		assert_eq!(ops[1], DecodedOpcode_tableswitch(5, 1, ~[3, 4]));
+	}
+
+
+	#[test]
+	fn test_decode_opcodes_fail() {
+		// truncated sipush
+		assert!(CodeBlock::decode_opcodes([0x11, 0x00]).is_err());
+
+		// goto into the operand of sipush
+		assert!(CodeBlock::decode_opcodes([0x11, 0x00, 0x01, 0xa7, 0xff, 0xfe]).is_err());
+
+		// goto beyond the end of the code
+		assert!(CodeBlock::decode_opcodes([0xa7, 0x00, 0x03]).is_err());
+
+		// reserved and unassigned opcodes
+		assert!(CodeBlock::decode_opcodes([0xca]).is_err());
+		assert!(CodeBlock::decode_opcodes([0xcb]).is_err());
+
+		// wide can not be applied to iadd
+		assert!(CodeBlock::decode_opcodes([0xc4, 0x60, 0x00, 0x00]).is_err());
+
+		// code must not be empty
+		assert!(CodeBlock::new(0, 0, ~[], ~[], ~[]).is_err());
+
+		// tableswitch with low > high
+		assert!(CodeBlock::decode_opcodes([0xaa, 0x00, 0x00, 0x00, 
+			0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x01]).is_err());
+	}
+}
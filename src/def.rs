@@ -31,6 +31,7 @@ pub static ACC_PROTECTED : uint = 0x4;
 pub static ACC_STATIC : uint = 0x8;
 pub static ACC_FINAL : uint = 0x10;
 pub static ACC_SYNCHRONIZED : uint = 0x20;
+pub static ACC_SUPER : uint = 0x20;
 pub static ACC_VOLATILE : uint = 0x40;
 pub static ACC_TRANSIENT: uint = 0x80;
 pub static ACC_NATIVE : uint = 0x100;
@@ -76,6 +77,11 @@ pub enum Constant {
 	CONSTANT_utf8_info(~str),
 	CONSTANT_methodhandle_info(u8, u16),
 	CONSTANT_methodtype_info(u16),
-	CONSTANT_invokedynamic_info(u16, u16)
+	CONSTANT_invokedynamic_info(u16, u16),
+
+	// long and double constants take up two entries in the
+	// constant pool, the second of which is not usable. This
+	// placeholder keeps indices into the pool consistent.
+	CONSTANT_unusable_info
 }
 
@@ -0,0 +1,379 @@
+// rustyVM - Java VM written in pure Rust
+// Copyright (c) 2013 Alexander Gessler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+
+
+
+// Virtual and interface dispatch tables of a class. They map the 
+// methods that can be invoked on instances of the class to the methods
+// selected for them as per JVMS 5.4.6, and are built when the class is
+// linked so that invocations need no method lookup.
+//
+// The vtable of a class starts with the vtable of its super class, so
+// a method has the same vtable index in a class and all of its 
+// subclasses. Methods that override an inherited method take over its
+// slot, all other instance methods get a new slot. Methods declared by
+// interfaces are dispatched through itables instead, one for every
+// interface the class implements, which are indexed like the methods
+// of the interface.
+
+use std::hashmap::HashMap;
+
+use class::{JavaClassRef};
+use method::{JavaMethod};
+use def::{ACC_INTERFACE, ACC_PUBLIC, ACC_PROTECTED, ACC_PRIVATE};
+
+
+// Method selected for a vtable or itable slot
+#[deriving(Clone)]
+pub enum DispatchTarget {
+	// method given by its declaring class, None for the class owning 
+	// the table, and the index of the method in that class
+	DT_Method(Option<JavaClassRef>, uint),
+
+	// there is no method to invoke (AbstractMethodError)
+	DT_Abstract,
+
+	// several maximally-specific default methods qualify and none of 
+	// them overrides the others (IncompatibleClassChangeError)
+	DT_Conflict,
+}
+
+
+pub struct DispatchTables {
+	priv vtable : ~[DispatchTarget],
+
+	// vtable index of every method declared by the class, None for
+	// methods that are not dispatched virtually
+	priv method_slots : ~[Option<uint>],
+
+	// itables of all interfaces implemented by the class, directly
+	// or indirectly, keyed by interface name
+	priv itables : ~[(~str, ~[DispatchTarget])],
+}
+
+
+impl DispatchTables {
+
+	// ----------------------------------------------
+	// Build the dispatch tables of a class, given the parameters that
+	// were passed to JavaClass::new(). Interfaces have no instances and
+	// get empty tables.
+	pub fn new(name : &str, access : uint, parents : &[JavaClassRef], 
+		methods : &[JavaMethod]) -> DispatchTables 
+	{
+		let mut tables = DispatchTables {
+			vtable : ~[],
+			method_slots : ~[],
+			itables : ~[],
+		};
+		tables.method_slots.grow(methods.len(), &None);
+		if (access & ACC_INTERFACE) != 0 {
+			return tables;
+		}
+
+		let superclass = if parents.len() > 0 { Some(&parents[0]) } else { None };
+		tables.build_vtable(name, superclass, methods);
+
+		let mut interfaces = ~[];
+		collect_interfaces(parents, &mut interfaces);
+		for iface in interfaces.iter() {
+			let itable = iface.get().get_methods().iter().map(|m| {
+				if m.is_static() || (m.get_access() & ACC_PRIVATE) != 0 || m.get_name().starts_with("<") {
+					DT_Abstract
+				}
+				else {
+					select_interface_method(superclass, methods, interfaces, m)
+				}
+			}).collect();
+			tables.itables.push((iface.get().get_name().clone(), itable));
+		}
+		tables
+	}
+
+
+	// ----------------------------------------------
+	// Get the vtable index of a method declared by the class, given
+	// its index. This is None for static and private methods, instance
+	// initializers and all methods of interfaces.
+	#[inline]
+	pub fn get_vtable_index(&self, method : uint) -> Option<uint> {
+		self.method_slots[method]
+	}
+
+
+	// ----------------------------------------------
+	#[inline]
+	pub fn get_vtable_entry<'a>(&'a self, slot : uint) -> Option<&'a DispatchTarget> {
+		if slot < self.vtable.len() { Some(&self.vtable[slot]) } else { None }
+	}
+
+
+	// ----------------------------------------------
+	// Get the method selected for a method of an interface, given the
+	// name of the interface and the index of the method in it. Returns
+	// None if the class does not implement the interface.
+	pub fn get_itable_entry<'a>(&'a self, iface : &str, method : uint) -> Option<&'a DispatchTarget> {
+		for &(ref name, ref itable) in self.itables.iter() {
+			if name.as_slice() == iface {
+				return if method < itable.len() { Some(&itable[method]) } else { None };
+			}
+		}
+		None
+	}
+
+
+	// ----------------------------------------------
+	pub fn get_vtable_size(&self) -> uint {
+		self.vtable.len()
+	}
+
+
+	// IMPL
+
+
+	// ----------------------------------------------
+	fn build_vtable(&mut self, name : &str, superclass : Option<&JavaClassRef>, methods : &[JavaMethod]) {
+		// vtable slot for every name and descriptor. Package-private
+		// methods that do not override each other may share name and
+		// descriptor, in which case the last slot is the one to override.
+		let mut slot_index = HashMap::new();
+		match superclass {
+			Some(c) => {
+				let sup = c.get().get_dispatch_tables();
+				for (slot, target) in sup.vtable.iter().enumerate() {
+					let target = match *target {
+						DT_Method(None, idx) => DT_Method(Some(c.clone()), idx),
+						ref t => t.clone()
+					};
+					match target {
+						DT_Method(Some(ref decl), idx) => {
+							let m = decl.get().get_method_by_index(idx);
+							slot_index.insert((m.get_name().clone(), m.get_desc().clone()), slot);
+						},
+						_ => ()
+					}
+					self.vtable.push(target);
+				}
+			},
+			None => ()
+		}
+
+		for (i, m) in methods.iter().enumerate() {
+			if m.is_static() || (m.get_access() & ACC_PRIVATE) != 0 || m.get_name().starts_with("<") {
+				continue;
+			}
+			let key = (m.get_name().clone(), m.get_desc().clone());
+			let slot = match slot_index.find(&key) {
+				Some(&slot) if self.overrides_slot(name, slot) => slot,
+				_ => {
+					self.vtable.push(DT_Abstract);
+					self.vtable.len() - 1
+				}
+			};
+			self.vtable[slot] = DT_Method(None, i);
+			self.method_slots[i] = Some(slot);
+			slot_index.insert(key, slot);
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Check if a method with the same name and descriptor declared by 
+	// the class with the given name overrides the method in an
+	// inherited vtable slot (JVMS 5.4.5)
+	fn overrides_slot(&self, name : &str, slot : uint) -> bool {
+		match self.vtable[slot] {
+			DT_Method(Some(ref decl), idx) => {
+				let access = decl.get().get_method_by_index(idx).get_access();
+				(access & (ACC_PUBLIC | ACC_PROTECTED)) != 0 || 
+					get_package_name(name) == get_package_name(decl.get().get_name().as_slice())
+			},
+			_ => false
+		}
+	}
+}
+
+
+// ----------------------------------------------
+// Collect all interfaces implemented by classes or interfaces, directly
+// or through their super classes and super interfaces, without 
+// duplicates.
+fn collect_interfaces(classes : &[JavaClassRef], out : &mut ~[JavaClassRef]) {
+	for c in classes.iter() {
+		let jclass = c.get();
+		if jclass.is_interface() {
+			if out.iter().any(|i| i.get().get_name() == jclass.get_name()) {
+				continue;
+			}
+			out.push(c.clone());
+			collect_interfaces(jclass.get_interfaces(), out);
+		}
+		else {
+			match jclass.get_superclass() {
+				Some(s) => collect_interfaces([s.clone()], out),
+				None => ()
+			}
+			collect_interfaces(jclass.get_interfaces(), out);
+		}
+	}
+}
+
+
+// ----------------------------------------------
+// Select the method to invoke for an interface method on instances of
+// a class as per JVMS 5.4.6: instance methods of the class and its super
+// classes come first, then the maximally-specific default methods of 
+// the interfaces it implements.
+fn select_interface_method(superclass : Option<&JavaClassRef>, methods : &[JavaMethod], 
+	interfaces : &[JavaClassRef], m : &JavaMethod) -> DispatchTarget 
+{
+	let name = m.get_name().as_slice();
+	let desc = m.get_desc().as_slice();
+	match methods.iter().position(|own| !own.is_static() && own.get_name().as_slice() == name && 
+		own.get_desc().as_slice() == desc) {
+		Some(idx) => return DT_Method(None, idx),
+		None => ()
+	}
+
+	let mut cur = superclass.map(|c| c.clone());
+	loop {
+		let next = match cur {
+			None => break,
+			Some(ref c) => {
+				match c.get().find_method_index(name, desc) {
+					Some(idx) if !c.get().get_method_by_index(idx).is_static() => 
+						return DT_Method(Some(c.clone()), idx),
+					_ => ()
+				}
+				c.get().get_superclass().map(|s| s.clone())
+			}
+		};
+		cur = next;
+	}
+
+	// candidates are the non-private instance methods of all super 
+	// interfaces. Of these, the maximally-specific ones are those not
+	// overridden by a candidate in a subinterface.
+	let mut candidates : ~[(&JavaClassRef, uint)] = ~[];
+	for iface in interfaces.iter() {
+		match iface.get().find_method_index(name, desc) {
+			Some(idx) => {
+				let cm = iface.get().get_method_by_index(idx);
+				if !cm.is_static() && (cm.get_access() & ACC_PRIVATE) == 0 {
+					candidates.push((iface, idx));
+				}
+			},
+			None => ()
+		}
+	}
+
+	let mut selected = DT_Abstract;
+	for &(iface, idx) in candidates.iter() {
+		let iname = iface.get().get_name();
+		let overridden = candidates.iter().any(|&(other, _)| {
+			other.get().get_name() != iname && other.get().implements_interface(iname.as_slice())
+		});
+		if overridden || iface.get().get_method_by_index(idx).is_abstract() {
+			continue;
+		}
+		selected = match selected {
+			DT_Abstract => DT_Method(Some(iface.clone()), idx),
+			_ => DT_Conflict
+		};
+	}
+	selected
+}
+
+
+// ----------------------------------------------
+fn get_package_name<'a>(name : &'a str) -> &'a str {
+	match name.rfind('.') {
+		Some(pos) => name.slice_to(pos),
+		None => ""
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use dispatch::*;
+	use classloader::tests::{test_get_real_classloader};
+	use util::{assert_no_err};
+
+	#[test]
+	fn test_dispatch_tables() {
+		let mut cl = test_get_real_classloader();
+		let v = cl.add_from_classfile("InvokeDerived").await();
+		assert_no_err(&v);
+		let derived = v.unwrap();
+		let base = derived.get().get_superclass().unwrap().clone();
+
+		// overrides take over the vtable slot of the overridden method
+		let d = derived.get();
+		let b = base.get();
+		let base_value = b.get_dispatch_tables().get_vtable_index(b.find_method_index("value", "()I").unwrap());
+		let derived_value = d.get_dispatch_tables().get_vtable_index(d.find_method_index("value", "()I").unwrap());
+		assert!(base_value.is_some());
+		assert_eq!(base_value, derived_value);
+		assert!(d.get_dispatch_tables().get_vtable_size() > b.get_dispatch_tables().get_vtable_size());
+
+		// private and static methods are not dispatched virtually
+		assert!(d.get_dispatch_tables().get_vtable_index(d.find_method_index("secret", "()I").unwrap()).is_none());
+		assert!(d.get_dispatch_tables().get_vtable_index(d.find_method_index("fib", "(J)J").unwrap()).is_none());
+
+		// inherited slots refer to the super class
+		match *d.get_dispatch_tables().get_vtable_entry(
+			b.get_dispatch_tables().get_vtable_index(b.find_method_index("callSecret", "()I").unwrap()).unwrap()).unwrap() {
+			DT_Method(Some(ref c), _) => assert_eq!(*c.get().get_name(), ~"InvokeBase"),
+			_ => fail!("expected InvokeBase.callSecret")
+		}
+
+		match *d.get_dispatch_tables().get_itable_entry("InvokeIface", 0).unwrap() {
+			DT_Method(None, idx) => assert_eq!(idx, d.find_method_index("area", "()I").unwrap()),
+			_ => fail!("expected InvokeDerived.area")
+		}
+		assert!(b.get_dispatch_tables().get_itable_entry("InvokeIface", 0).is_none());
+	}
+
+
+	#[test]
+	fn test_dispatch_default_methods() {
+		let mut cl = test_get_real_classloader();
+		let v = cl.add_from_classfile("DefaultImpl").await();
+		assert_no_err(&v);
+		let c = v.unwrap();
+		let iface = cl.add_from_classfile("DefaultA").await().unwrap();
+		let which = iface.get().find_method_index("which", "()I").unwrap();
+
+		// DefaultB.which is more specific than DefaultA.which
+		match *c.get().get_dispatch_tables().get_itable_entry("DefaultA", which).unwrap() {
+			DT_Method(Some(ref decl), _) => assert_eq!(*decl.get().get_name(), ~"DefaultB"),
+			_ => fail!("expected DefaultB.which")
+		}
+
+		let v = cl.add_from_classfile("DefaultConflict").await();
+		assert_no_err(&v);
+		match *v.unwrap().get().get_dispatch_tables().get_itable_entry("DefaultA", which).unwrap() {
+			DT_Conflict => (),
+			_ => fail!("expected conflicting default methods")
+		}
+	}
+}
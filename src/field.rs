@@ -24,6 +24,8 @@ extern mod extra;
 
 use extra::arc::{MutexArc};
 
+use def::{ACC_STATIC};
+use attribute::{Attribute};
 use classloader::*;
 use class::{JavaClassFutureRef};
 
@@ -31,7 +33,7 @@ use class::{JavaClassFutureRef};
 // FieldDescriptor is modelled after the official grammar for Java field descriptors from 
 // http://docs.oracle.com/javase/specs/jvms/se7/html/jvms-4.html#jvms-4.3.2
 
-
+#[deriving(Clone, Eq)]
 pub enum FieldDescriptor {
 	// primitive data type
 	FD_BaseType(BaseType),
@@ -50,7 +52,7 @@ pub enum FieldDescriptor {
 
 #[deriving(FromPrimitive)]
 #[deriving(ToStr)]
-#[deriving(Eq)]
+#[deriving(Clone, Eq)]
 pub enum BaseType {
 	BT_B_byte,     // = 'B',
 	BT_C_char,     // = 'C',
@@ -63,12 +65,231 @@ pub enum BaseType {
 }
 
 
+impl BaseType {
+
+	// ----------------------------------------------
+	// Get the character used to denote the type in descriptors
+	pub fn to_desc_char(&self) -> char {
+		match *self {
+			BT_B_byte => 'B',
+			BT_C_char => 'C',
+			BT_D_double => 'D',
+			BT_F_float => 'F',
+			BT_I_int => 'I',
+			BT_J_long => 'J',
+			BT_S_short => 'S',
+			BT_Z_boolean => 'Z',
+		}
+	}
+}
+
+
+impl FieldDescriptor {
+
+	// ----------------------------------------------
+	// Get the number of local variable or operand stack slots 
+	// a value of this type occupies. This is 2 for long and
+	// double and 1 for everything else.
+	pub fn get_slot_count(&self) -> uint {
+		match *self {
+			FD_BaseType(BT_J_long) | FD_BaseType(BT_D_double) => 2,
+			_ => 1
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Get the name under which the class denoted by a reference
+	// type descriptor is known to the class loader, i.e.
+	// java.lang.String or [Ljava.lang.String; - None for primitives.
+	pub fn get_class_name(&self) -> Option<~str> {
+		match *self {
+			FD_BaseType(_) => None,
+			FD_ObjectType(ref name) => Some(name.clone()),
+			FD_ArrayType(_) => Some(self.to_str().replace("/", ".")),
+		}
+	}
+}
+
+
+// Renders the canonical descriptor string, i.e. [Ljava/lang/String;
+impl ToStr for FieldDescriptor {
+	fn to_str(&self) -> ~str {
+		match *self {
+			FD_BaseType(bt) => std::str::from_char(bt.to_desc_char()),
+			FD_ObjectType(ref name) => "L" + name.replace(".", "/") + ";",
+			FD_ArrayType(ref inner) => "[" + inner.to_str(),
+		}
+	}
+}
+
+
+// MethodDescriptor is modelled after the official grammar for Java method descriptors from
+// http://docs.oracle.com/javase/specs/jvms/se7/html/jvms-4.html#jvms-4.3.3
+
+#[deriving(Clone, Eq)]
+pub struct MethodDescriptor {
+	priv params : ~[FieldDescriptor],
+
+	// return type of the method, None for void
+	priv ret : Option<FieldDescriptor>,
+}
+
+
+// a method may not take up more than 255 slots worth of parameters,
+// including `this` for instance methods. Descriptors do not know whether
+// the method is static, so they are only checked for their parameters,
+// JavaMethod::new() checks the limit including `this`.
+pub static MAX_METHOD_ARG_SLOTS : uint = 255;
+
+
+impl MethodDescriptor {
+
+	// ----------------------------------------------
+	// Parse a method descriptor such as (I[Ljava/lang/String;J)V
+	// Parameter types are parsed as field descriptors.
+	pub fn new_from_string(method_desc : &str) -> 
+		Result<MethodDescriptor, ~str>
+	{
+		let len = method_desc.len();
+		if len == 0 || (method_desc[0] as char) != '(' {
+			return Err(~"method descriptor must start with (");
+		}
+
+		let mut params : ~[FieldDescriptor] = ~[];
+		let mut i = 1;
+		loop {
+			if i >= len {
+				return Err(~"method descriptor is missing )");
+			}
+			if (method_desc[i] as char) == ')' {
+				i += 1;
+				break;
+			}
+
+			// find the end of the parameter, which is either
+			// one character or a L...; sequence, both of which
+			// may be preceded by array dimensions.
+			let start = i;
+			while i < len && (method_desc[i] as char) == '[' {
+				i += 1;
+			}
+			if i >= len {
+				return Err(~"method descriptor is missing )");
+			}
+			if (method_desc[i] as char) == 'L' {
+				match method_desc.slice_from(i).find(';') {
+					None => return Err(~"class name must end with ;"),
+					Some(end) => i += end + 1
+				}
+			}
+			else {
+				i += 1;
+			}
+
+			match JavaField::resolve_field_desc(method_desc.slice(start, i)) {
+				Ok(fd) => params.push(fd),
+				Err(s) => return Err(s)
+			}
+		}
+
+		let rest = method_desc.slice_from(i);
+		let ret = if rest == "V" { 
+			None 
+		}
+		else {
+			match JavaField::resolve_field_desc(rest) {
+				Ok(fd) => Some(fd),
+				Err(s) => return Err("invalid return type: " + s)
+			}
+		};
+
+		let md = MethodDescriptor {
+			params : params,
+			ret : ret
+		};
+
+		if md.get_arg_slots() > MAX_METHOD_ARG_SLOTS {
+			return Err(~"method descriptor exceeds 255 parameter slots");
+		}
+		Ok(md)
+	}
+
+
+	// ----------------------------------------------
+	pub fn get_params<'a>(&'a self) -> &'a ~[FieldDescriptor] {
+		&self.params
+	}
+
+
+	// ----------------------------------------------
+	// Get the return type of the method, None for void
+	pub fn get_return_type<'a>(&'a self) -> &'a Option<FieldDescriptor> {
+		&self.ret
+	}
+
+
+	// ----------------------------------------------
+	// Get the number of local variable slots occupied by the
+	// method's parameters. This does not include the implicit
+	// `this` parameter of instance methods.
+	pub fn get_arg_slots(&self) -> uint {
+		self.params.iter().fold(0, |acc, p| acc + p.get_slot_count())
+	}
+
+
+	// ----------------------------------------------
+	// Get the number of operand stack slots occupied by the 
+	// return value, 0 for void methods.
+	pub fn get_return_slots(&self) -> uint {
+		match self.ret {
+			None => 0,
+			Some(ref fd) => fd.get_slot_count()
+		}
+	}
+}
+
+
+// Renders the canonical descriptor string, i.e. (IJ)V
+impl ToStr for MethodDescriptor {
+	fn to_str(&self) -> ~str {
+		let mut s = ~"(";
+		for p in self.params.iter() {
+			s.push_str(p.to_str());
+		}
+		s.push_char(')');
+		match self.ret {
+			None => s.push_char('V'),
+			Some(ref fd) => s.push_str(fd.to_str())
+		}
+		s
+	}
+}
+
+
+
+// Value of the ConstantValue attribute of a static field, see
+// http://docs.oracle.com/javase/specs/jvms/se7/html/jvms-4.html#jvms-4.7.2
+#[deriving(Clone)]
+pub enum ConstantValue {
+	CV_Integer(i32),
+	CV_Float(f32),
+	CV_Long(i64),
+	CV_Double(f64),
+	CV_String(~str),
+}
+
 
 pub struct JavaField {
 	priv name : ~str,
-	priv jtype : FieldDescriptor
-	//
-	//priv constant_value : ~str,
+	priv access : uint,
+	priv jtype : FieldDescriptor,
+
+	// only ever set for static fields
+	priv constant_value : Option<ConstantValue>,
+
+	// all other attributes of the field
+	priv attributes : ~[Attribute],
 }
 
 
@@ -78,12 +299,91 @@ impl JavaField {
 	pub fn new_from_string( name : &str, field_desc : &str) -> 
 		Result<JavaField, ~str>
 	{
-		match JavaField::resolve_field_desc(field_desc) {
-			Ok(t) => Ok(JavaField {
-				name : name.into_owned(),
-				jtype : t
-			}),
-			Err(s) => Err(s)
+		JavaField::new(name, 0, field_desc, None, ~[])
+	}
+
+
+	// ----------------------------------------------
+	// Construct a field from the data found in a field_info structure.
+	// The constant value, if given, must be compatible with the field's
+	// type. Following the spec, it is silently dropped for instance fields.
+	pub fn new(name : &str, access : uint, field_desc : &str, 
+		constant_value : Option<ConstantValue>, attributes : ~[Attribute]) -> 
+		Result<JavaField, ~str>
+	{
+		let jtype = match JavaField::resolve_field_desc(field_desc) {
+			Ok(t) => t,
+			Err(s) => return Err(s)
+		};
+
+		let cv = if (access & ACC_STATIC) == 0 { None } else { constant_value };
+		match cv {
+			Some(ref v) if !JavaField::is_constant_value_compatible(&jtype, v) => 
+				return Err(format!("ConstantValue does not match field type {}", field_desc)),
+			_ => ()
+		}
+
+		Ok(JavaField {
+			name : name.into_owned(),
+			access : access,
+			jtype : jtype,
+			constant_value : cv,
+			attributes : attributes
+		})
+	}
+
+
+	// ----------------------------------------------
+	pub fn get_name<'a>(&'a self) -> &'a ~str {
+		&self.name
+	}
+
+
+	// ----------------------------------------------
+	pub fn get_access(&self) -> uint {
+		self.access
+	}
+
+
+	// ----------------------------------------------
+	pub fn get_type<'a>(&'a self) -> &'a FieldDescriptor {
+		&self.jtype
+	}
+
+
+	// ----------------------------------------------
+	pub fn get_constant_value<'a>(&'a self) -> &'a Option<ConstantValue> {
+		&self.constant_value
+	}
+
+
+	// ----------------------------------------------
+	pub fn get_attributes<'a>(&'a self) -> &'a ~[Attribute] {
+		&self.attributes
+	}
+
+
+	// ----------------------------------------------
+	#[inline]
+	pub fn is_static(&self) -> bool {
+		(self.access & ACC_STATIC) != 0
+	}
+
+
+	// ----------------------------------------------
+	// Check whether a constant value can be used to initialize a field
+	// of the given type. int, short, char, byte and boolean fields all
+	// take CONSTANT_Integer values.
+	pub fn is_constant_value_compatible(jtype : &FieldDescriptor, cv : &ConstantValue) -> bool
+	{
+		match (jtype, cv) {
+			(&FD_BaseType(BT_J_long), &CV_Long(_)) => true,
+			(&FD_BaseType(BT_F_float), &CV_Float(_)) => true,
+			(&FD_BaseType(BT_D_double), &CV_Double(_)) => true,
+			(&FD_BaseType(bt), &CV_Integer(_)) => 
+				bt != BT_J_long && bt != BT_F_float && bt != BT_D_double,
+			(&FD_ObjectType(ref name), &CV_String(_)) => *name == ~"java.lang.String",
+			_ => false
 		}
 	}
 
@@ -144,6 +444,7 @@ impl JavaField {
 #[cfg(test)]
 mod tests {
 	use field::*;
+	use def::{ACC_STATIC};
 	use util::{assert_is_err, assert_no_err};
 
 	#[test]
@@ -184,4 +485,71 @@ mod tests {
 		assert_is_err(&JavaField::resolve_field_desc(&"b"));
 		assert_is_err(&JavaField::resolve_field_desc(&"["));
 	}
+
+
+	#[test]
+	fn test_field_desc_to_str() {
+		for d in (~["I", "[[J", "Ljava/lang/Object;", "[Ljava/lang/String;"]).iter() {
+			assert_eq!(JavaField::resolve_field_desc(*d).unwrap().to_str(), d.to_owned());
+		}
+	}
+
+
+	#[test]
+	fn test_method_desc_parsing() {
+		let md = MethodDescriptor::new_from_string("(I[Ljava/lang/String;J)V").unwrap();
+		assert_eq!(md.get_params().len(), 3);
+		assert!(md.get_return_type().is_none());
+		assert_eq!(md.get_arg_slots(), 4);
+		assert_eq!(md.get_return_slots(), 0);
+		match md.get_params()[1] {
+			FD_ArrayType(~FD_ObjectType(ref c)) => assert_eq!(*c, ~"java.lang.String"),
+			_ => assert!(false)
+		}
+		assert_eq!(md.to_str(), ~"(I[Ljava/lang/String;J)V");
+
+		let md2 = MethodDescriptor::new_from_string("()[[D").unwrap();
+		assert_eq!(md2.get_params().len(), 0);
+		assert_eq!(md2.get_return_slots(), 1);
+		assert_eq!(md2.to_str(), ~"()[[D");
+
+		let md3 = MethodDescriptor::new_from_string("(DLFoo;)D").unwrap();
+		assert_eq!(md3.get_arg_slots(), 3);
+		assert_eq!(md3.get_return_slots(), 2);
+	}
+
+
+	#[test]
+	fn test_method_desc_parsing_fail() {
+		assert_is_err(&MethodDescriptor::new_from_string(""));
+		assert_is_err(&MethodDescriptor::new_from_string("V"));
+		assert_is_err(&MethodDescriptor::new_from_string("(I"));
+		assert_is_err(&MethodDescriptor::new_from_string("(I)"));
+		assert_is_err(&MethodDescriptor::new_from_string("(Ljava/lang/Object)V"));
+		assert_is_err(&MethodDescriptor::new_from_string("(V)V"));
+		assert_is_err(&MethodDescriptor::new_from_string("([)V"));
+		assert_is_err(&MethodDescriptor::new_from_string("(I)VV"));
+		assert_is_err(&MethodDescriptor::new_from_string("(" + "J".repeat(128) + ")V"));
+	}
+
+
+	#[test]
+	fn test_field_constant_value() {
+		let f = JavaField::new("A", ACC_STATIC, "J", Some(CV_Long(5)), ~[]);
+		assert!(f.is_ok());
+		assert!(f.unwrap().is_static());
+
+		assert!(JavaField::new("B", ACC_STATIC, "Z", Some(CV_Integer(1)), ~[]).is_ok());
+		assert!(JavaField::new("C", ACC_STATIC, "Ljava/lang/String;", 
+			Some(CV_String(~"foo")), ~[]).is_ok());
+
+		assert_is_err(&JavaField::new("D", ACC_STATIC, "I", Some(CV_Long(5)), ~[]));
+		assert_is_err(&JavaField::new("E", ACC_STATIC, "Ljava/lang/Object;", 
+			Some(CV_String(~"foo")), ~[]));
+
+		// instance fields ignore ConstantValue, even if mismatching
+		let g = JavaField::new("G", 0, "I", Some(CV_Double(1.0)), ~[]);
+		assert!(g.is_ok());
+		assert!(g.unwrap().get_constant_value().is_none());
+	}
 }
\ No newline at end of file
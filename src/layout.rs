@@ -0,0 +1,307 @@
+// rustyVM - Java VM written in pure Rust
+// Copyright (c) 2013 Alexander Gessler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+
+
+// Runtime layout of the fields of a class. Field values are stored
+// in a flat array of 32 bit words, and the layout assigns every field
+// an offset into that array.
+//
+// The instance layout of a class starts with the instance layout of
+// its super class, so the offset of an inherited field is the same
+// for a class and all of its subclasses. Static fields are not
+// inherited and are laid out separately per class.
+
+use field::{JavaField, FieldDescriptor, FD_BaseType, BT_J_long, BT_D_double, BT_F_float};
+
+
+// Kind of value stored in a slot. All integral types narrower than
+// long (boolean, byte, char, short and int) are stored as int.
+#[deriving(Clone, Eq, ToStr)]
+pub enum SlotKind {
+	SK_Int,
+	SK_Float,
+	SK_Long,
+	SK_Double,
+	SK_Reference
+}
+
+
+impl SlotKind {
+
+	// ----------------------------------------------
+	// Get the kind of slot used to store values of a given type
+	pub fn from_type(jtype : &FieldDescriptor) -> SlotKind {
+		match *jtype {
+			FD_BaseType(BT_J_long) => SK_Long,
+			FD_BaseType(BT_D_double) => SK_Double,
+			FD_BaseType(BT_F_float) => SK_Float,
+			FD_BaseType(_) => SK_Int,
+			_ => SK_Reference
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Get the number of words a slot of this kind occupies. Object 
+	// ids are 64 bit, so references take two words just like long
+	// and double.
+	pub fn get_width(&self) -> uint {
+		match *self {
+			SK_Int | SK_Float => 1,
+			SK_Long | SK_Double | SK_Reference => 2
+		}
+	}
+}
+
+
+#[deriving(Clone)]
+pub struct FieldSlot {
+	priv name : ~str,
+
+	// fully qualified name of the class declaring the field 
+	priv declaring_class : ~str,
+	priv jtype : FieldDescriptor,
+	priv kind : SlotKind,
+
+	// offset and width, in words
+	priv offset : uint,
+	priv width : uint,
+}
+
+
+impl FieldSlot {
+
+	// ----------------------------------------------
+	pub fn get_name<'a>(&'a self) -> &'a ~str {
+		&self.name
+	}
+
+
+	// ----------------------------------------------
+	pub fn get_declaring_class<'a>(&'a self) -> &'a ~str {
+		&self.declaring_class
+	}
+
+
+	// ----------------------------------------------
+	pub fn get_type<'a>(&'a self) -> &'a FieldDescriptor {
+		&self.jtype
+	}
+
+
+	// ----------------------------------------------
+	#[inline]
+	pub fn get_kind(&self) -> SlotKind {
+		self.kind
+	}
+
+
+	// ----------------------------------------------
+	#[inline]
+	pub fn get_offset(&self) -> uint {
+		self.offset
+	}
+
+
+	// ----------------------------------------------
+	#[inline]
+	pub fn get_width(&self) -> uint {
+		self.width
+	}
+}
+
+
+#[deriving(Clone)]
+pub struct FieldLayout {
+	priv slots : ~[FieldSlot],
+
+	// total size, in words
+	priv size : uint,
+}
+
+
+impl FieldLayout {
+
+	// ----------------------------------------------
+	// Construct an empty layout
+	pub fn new_empty() -> FieldLayout {
+		FieldLayout {
+			slots : ~[],
+			size : 0
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Construct the layout for the instance fields of a class, given
+	// the layout of its super class (if any) and the instance fields
+	// the class itself declares.
+	pub fn new_instance_layout(class_name : &str, parent : Option<&FieldLayout>, 
+		fields : &[JavaField]) -> FieldLayout 
+	{
+		let mut layout = match parent {
+			Some(p) => p.clone(),
+			None => FieldLayout::new_empty()
+		};
+		layout.append(class_name, fields);
+		layout
+	}
+
+
+	// ----------------------------------------------
+	// Construct the layout for the static fields of a class
+	pub fn new_static_layout(class_name : &str, fields : &[JavaField]) -> FieldLayout {
+		let mut layout = FieldLayout::new_empty();
+		layout.append(class_name, fields);
+		layout
+	}
+
+
+	// ----------------------------------------------
+	// Get the number of words needed to store all fields
+	#[inline]
+	pub fn get_size(&self) -> uint {
+		self.size
+	}
+
+
+	// ----------------------------------------------
+	// Get all slots, ordered by offset 
+	pub fn get_slots<'a>(&'a self) -> &'a ~[FieldSlot] {
+		&self.slots
+	}
+
+
+	// ----------------------------------------------
+	// Lookup the slot for a field given its name and descriptor. A
+	// class may declare several fields of the same name as long as 
+	// their descriptors differ. If a subclass declares a field with
+	// the same name and descriptor as a field of a super class, the 
+	// subclass field hides the other field.
+	pub fn find<'a>(&'a self, name : &str, desc : &str) -> Option<&'a FieldSlot> {
+		self.slots.rev_iter().find(|s| s.name.as_slice() == name && 
+			s.jtype.to_str().as_slice() == desc)
+	}
+
+
+	// ----------------------------------------------
+	// Lookup the slot for a field given its name, its descriptor and 
+	// the class which declares it.
+	pub fn find_declared<'a>(&'a self, declaring_class : &str, name : &str, desc : &str) 
+		-> Option<&'a FieldSlot> 
+	{
+		self.slots.iter().find(|s| s.name.as_slice() == name && 
+			s.jtype.to_str().as_slice() == desc &&
+			s.declaring_class.as_slice() == declaring_class)
+	}
+
+
+	// ----------------------------------------------
+	// Lookup the slot for a field given its name only, the last one
+	// declared winning. This is meant for the fields of JDK classes 
+	// the VM accesses itself (i.e. String.value), whose names are 
+	// unique. Fields referenced by bytecode are looked up by find().
+	pub fn find_by_name<'a>(&'a self, name : &str) -> Option<&'a FieldSlot> {
+		self.slots.rev_iter().find(|s| s.name.as_slice() == name)
+	}
+
+
+	// ----------------------------------------------
+	// Lookup the slot that starts at the given offset
+	pub fn find_by_offset<'a>(&'a self, offset : uint) -> Option<&'a FieldSlot> {
+		self.slots.iter().find(|s| s.offset == offset)
+	}
+
+
+	// IMPL
+
+
+	// ----------------------------------------------
+	fn append(&mut self, class_name : &str, fields : &[JavaField]) {
+		for f in fields.iter() {
+			let kind = SlotKind::from_type(f.get_type());
+			let width = kind.get_width();
+			self.slots.push(FieldSlot {
+				name : f.get_name().clone(),
+				declaring_class : class_name.into_owned(),
+				jtype : f.get_type().clone(),
+				kind : kind,
+				offset : self.size,
+				width : width
+			});
+			self.size += width;
+		}
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use layout::*;
+	use field::{JavaField};
+	use def::{ACC_PUBLIC, ACC_STATIC};
+
+	fn test_get_field(name : &str, desc : &str) -> JavaField {
+		JavaField::new(name, ACC_PUBLIC, desc, None, ~[]).unwrap()
+	}
+
+	#[test]
+	fn test_field_layout() {
+		let base = FieldLayout::new_instance_layout("Base", None, 
+			[test_get_field("a", "I"), test_get_field("b", "J")]);
+		assert_eq!(base.get_size(), 3);
+		assert_eq!(base.find("a", "I").unwrap().get_offset(), 0);
+		assert_eq!(base.find("b", "J").unwrap().get_offset(), 1);
+		assert_eq!(base.find("b", "J").unwrap().get_width(), 2);
+		assert!(base.find("b", "I").is_none());
+
+		// super class fields come first, hidden fields remain accessible
+		let derived = FieldLayout::new_instance_layout("Derived", Some(&base), 
+			[test_get_field("c", "Ljava/lang/Object;"), test_get_field("a", "D")]);
+		assert_eq!(derived.get_size(), 7);
+		assert_eq!(derived.find("b", "J").unwrap().get_offset(), 1);
+		assert_eq!(derived.find("c", "Ljava/lang/Object;").unwrap().get_offset(), 3);
+		assert_eq!(derived.find("a", "D").unwrap().get_offset(), 5);
+		assert_eq!(*derived.find("a", "D").unwrap().get_declaring_class(), ~"Derived");
+		assert_eq!(derived.find("a", "I").unwrap().get_offset(), 0);
+		assert_eq!(derived.find_declared("Base", "a", "I").unwrap().get_offset(), 0);
+		assert!(derived.find_declared("Derived", "a", "I").is_none());
+		assert_eq!(derived.find_by_name("a").unwrap().get_offset(), 5);
+		assert!(derived.find("d", "I").is_none());
+
+		assert_eq!(derived.find_by_offset(3).unwrap().get_kind(), SK_Reference);
+		assert_eq!(derived.find_by_offset(5).unwrap().get_kind(), SK_Double);
+		assert!(derived.find_by_offset(4).is_none());
+
+		let statics = FieldLayout::new_static_layout("Derived", 
+			[JavaField::new("s", ACC_PUBLIC | ACC_STATIC, "[I", None, ~[]).unwrap()]);
+		assert_eq!(statics.get_size(), 2);
+		assert_eq!(statics.find("s", "[I").unwrap().get_offset(), 0);
+
+		// fields of the same name, but different types
+		let same_name = FieldLayout::new_instance_layout("SameName", None, 
+			[test_get_field("x", "J"), test_get_field("x", "Ljava/lang/String;")]);
+		assert_eq!(same_name.find("x", "J").unwrap().get_kind(), SK_Long);
+		assert_eq!(same_name.find("x", "J").unwrap().get_offset(), 0);
+		assert_eq!(same_name.find("x", "Ljava/lang/String;").unwrap().get_kind(), SK_Reference);
+		assert_eq!(same_name.find("x", "Ljava/lang/String;").unwrap().get_offset(), 2);
+	}
+}
@@ -59,7 +59,8 @@ pub struct LocalHeap {
 }
 
 static LH_INITIAL_OBJ_HASHMAP_CAPACITY : uint = 1024;
-static mut ObjectIdCounter : JavaObjectId = 0;
+// starts at 1 as JAVA_NULL_OID (0) is never assigned to an object
+static mut ObjectIdCounter : JavaObjectId = 1;
 
 impl LocalHeap  {
 
@@ -98,29 +99,29 @@ impl LocalHeap  {
 
 	// ----------------------------------------------
 	pub fn new_object(&mut self, jclass : JavaClassRef) -> JavaObjectId {
-		// generate an unique object id
-		let id = unsafe {
-			atomic_add(&mut ObjectIdCounter, 1, AcqRel)
-		};
-
-		// this id must be unique - if not, we ran out of
-		// 64bit indices ("impossible - our shields cannot be 
-		// broken") or there is a logic flaw somewhere.
-		assert!(!self.owned_objects.contains_key(&id));
+		let id = self.new_oid();
+		self.owned_objects.insert(id, ~JavaObject::new(jclass, id));
+		id
+	}
 
-		// tell the object broker to ensure other threads
-		// can request the object by its oid
-		let op = OB_REMOTE_OBJECT_OP(self.tid, id,REMOTE_ADD_REF);
 
-		self.get_thread_mut().send_message(op);
-		self.owned_objects.insert(id, ~JavaObject::new(jclass, id));
+	// ----------------------------------------------
+	// Create the object that holds the static fields of a class. This
+	// is done once per class, by the thread initializing the class.
+	pub fn new_class_statics(&mut self, jclass : JavaClassRef) -> JavaObjectId {
+		let id = self.new_oid();
+		self.owned_objects.insert(id, ~JavaObject::new_class_statics(jclass, id));
 		id
 	}
 
 
 	// ----------------------------------------------
-	pub fn new_array_object() {
-		// TODO
+	// Create an array of the given array class with `length` 
+	// zero-initialized elements.
+	pub fn new_array_object(&mut self, jclass : JavaClassRef, length : uint) -> JavaObjectId {
+		let id = self.new_oid();
+		self.owned_objects.insert(id, ~JavaObject::new_array(jclass, id, length));
+		id
 	}
 
 
@@ -190,29 +191,39 @@ impl LocalHeap  {
 	// The closure passed in is called exactly once with a borrowed ref to
 	// the object, to which it gets full access but cannot dispose of
 	pub fn access_object(&mut self, access : RequestObjectAccessType, 
-		oid : JavaObjectId, wrap : |&JavaObject| -> ()) 
+		oid : JavaObjectId, wrap : |&mut JavaObject| -> ()) 
 	{
 		let mut done = false;
+		let mut queued = false;
 		let mut send_to_thread : Option<uint> = None;
 		match self.owned_objects.find_mut(&oid) {
-			Some(ref obj) => {
+			Some(obj) => {
 
 				match access {
 
 					OBJECT_ACCESS_Normal => {
-						wrap(**obj);
+						wrap(&mut **obj);
 						done = true;
 					},
 					OBJECT_ACCESS_Monitor | OBJECT_ACCESS_MonitorPriority 
 						// even if we own the object, somebody else could
 						// have the monitor lock.
 						if obj.monitor().can_be_locked_by_thread(self.tid) => {
-							wrap(**obj);
+							wrap(&mut **obj);
 							done = true;
 					},
 
-					// fallthru
-					_ => ()
+					// somebody else has the monitor lock. They need the object 
+					// to unlock it and hand it back to us afterwards, so all 
+					// we do is queue up for the monitor. wait()ing threads, 
+					// which request priority access, are queued already.
+					OBJECT_ACCESS_Monitor => {
+						obj.monitor_mut().push_thread(self.tid, false);
+						queued = true;
+					},
+					OBJECT_ACCESS_MonitorPriority => {
+						queued = true;
+					}
 				}
 
 				if done {
@@ -239,8 +250,10 @@ impl LocalHeap  {
 		} 
 
 		// request to own the object
-		let op = OB_REMOTE_OBJECT_OP(self.tid, oid, REMOTE_OWN(access));
-		self.get_thread().send_message(op);
+		if !queued {
+			let op = OB_REMOTE_OBJECT_OP(self.tid, oid, REMOTE_OWN(access));
+			self.get_thread().send_message(op);
+		}
 
 		// and block until we can get it
 		if self.get_thread_mut().handle_messages_until(|msg : &ObjectBrokerMessage| {
@@ -267,6 +280,52 @@ impl LocalHeap  {
 	}
 
 
+	// ----------------------------------------------
+	// Enter the monitor of an object, blocking until it is available.
+	// This implements monitorenter and synchronized methods.
+	pub fn lock_monitor(&mut self, oid : JavaObjectId) {
+		let tid = self.tid;
+		self.access_object(OBJECT_ACCESS_Monitor, oid, |obj| {
+			obj.monitor_mut().lock(tid);
+		});
+	}
+
+
+	// ----------------------------------------------
+	// Leave the monitor of an object. If this releases the monitor, 
+	// the object is handed to the next thread waiting for it. Returns
+	// false if the current thread does not hold the monitor.
+	pub fn unlock_monitor(&mut self, oid : JavaObjectId) -> bool {
+		let tid = self.tid;
+		let mut unlocked = false;
+		self.access_object(OBJECT_ACCESS_Normal, oid, |obj| {
+			unlocked = obj.monitor_mut().unlock(tid);
+		});
+		unlocked
+	}
+
+
+	// ----------------------------------------------
+	// Generate an unique object id for a new object owned by this
+	// thread and make it known to the object broker.
+	fn new_oid(&mut self) -> JavaObjectId {
+		let id = unsafe {
+			atomic_add(&mut ObjectIdCounter, 1, AcqRel)
+		};
+
+		// this id must be unique - if not, we ran out of
+		// 64bit indices ("impossible - our shields cannot be 
+		// broken") or there is a logic flaw somewhere.
+		assert!(!self.owned_objects.contains_key(&id));
+
+		// tell the object broker to ensure other threads
+		// can request the object by its oid
+		let op = OB_REMOTE_OBJECT_OP(self.tid, id,REMOTE_ADD_REF);
+		self.get_thread_mut().send_message(op);
+		id
+	}
+
+
 	// ----------------------------------------------
 	// Transfer ownership of an object to a particular thread
 	pub fn send_to_thread(&mut self, oid : JavaObjectId, tid : uint) {
@@ -296,8 +355,18 @@ impl LocalHeap  {
 	// Handle any of the remote object messages 
 	// a is the source thread id, and b is the object in question.
 	pub fn handle_message(&mut self, a : uint, b : JavaObjectId, op : RemoteObjectOpMessage) {
-		// TODO: owns() is not necessarily satisfied if we send back objects without being asked for
-		assert!(self.owns(b));
+		// we may have handed the object to a thread waiting for its monitor
+		// before the broker got to know, in which case the broker forwarded
+		// the request to us in error. Let it route the request again on
+		// behalf of the original sender.
+		let misrouted = match op {
+			REMOTE_DISOWN(_, _) => false,
+			_ => !self.owns(b)
+		};
+		if misrouted {
+			self.get_thread().send_message(OB_REMOTE_OBJECT_OP(a, b, op));
+			return;
+		}
 		match op {
 			REMOTE_WHO_OWNS => fail!("logic error, WHO_OWNS is not handled by threads"),
 			REMOTE_ADD_REF => self.add_ref(b),
@@ -369,6 +438,13 @@ impl JavaStrongObjectRef {
 			heapref : unsafe { heap }
 		}
 	}
+
+
+	// ----------------------------------------------
+	#[inline]
+	pub fn get_oid(&self) -> JavaObjectId {
+		self.jid
+	}
 }
 
 
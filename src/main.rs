@@ -31,15 +31,25 @@ use std::io::{println, File};
 
 mod def;
 mod util;
+mod mutf8;
 mod field;
+mod layout;
 mod method;
+mod attribute;
 mod class;
+mod dispatch;
 mod classpath;
+mod classloaderror;
 mod classloader;
+mod rtpool;
+mod opcode;
 mod code;
 mod monitor;
 mod object;
 mod threadmanager;
+mod classinit;
+mod stringintern;
+mod natives;
 mod objectbroker;
 mod localheap;
 mod thread;
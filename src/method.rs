@@ -20,20 +20,154 @@
 //
 
 
+use def::{ACC_STATIC, ACC_NATIVE, ACC_ABSTRACT, ACC_SYNCHRONIZED};
 use code::{CodeBlock};
+use field::{MethodDescriptor, MAX_METHOD_ARG_SLOTS};
+use attribute::{Attribute, ATTR_Exceptions};
 
 
 pub struct JavaMethod {
-	name : ~str,
+	priv name : ~str,
+	priv desc : ~str,
+	priv jtype : MethodDescriptor,
+	priv access : uint,
+
+	// bytecode of the method. This is None iff the
+	// method is abstract or native.
+	priv code : Option<CodeBlock>,
+
+	// all other attributes of the method
+	priv attributes : ~[Attribute],
 }
 
 
 impl JavaMethod {
 
-	pub fn new(name : ~str, desc : ~str, code : CodeBlock) -> JavaMethod {
-		JavaMethod {
-			name : name
+	// ----------------------------------------------
+	pub fn new(name : &str, desc : &str, access : uint, code : Option<CodeBlock>,
+		attributes : ~[Attribute]) -> 
+		Result<JavaMethod, ~str> 
+	{
+		let m = match MethodDescriptor::new_from_string(desc) {
+			Ok(t) => JavaMethod {
+				name : name.into_owned(),
+				desc : desc.into_owned(),
+				jtype : t,
+				access : access,
+				code : code,
+				attributes : attributes,
+			},
+			Err(s) => return Err(s)
+		};
+
+		// the descriptor itself does not account for `this`
+		if m.get_arg_slots() > MAX_METHOD_ARG_SLOTS {
+			return Err(~"method exceeds 255 parameter slots including this");
+		}
+		Ok(m)
+	}
+
+
+	// ----------------------------------------------
+	pub fn get_name<'a>(&'a self) -> &'a ~str {
+		&self.name
+	}
+
+
+	// ----------------------------------------------
+	// Get the raw descriptor string of the method, i.e. (I)V
+	pub fn get_desc<'a>(&'a self) -> &'a ~str {
+		&self.desc
+	}
+
+
+	// ----------------------------------------------
+	// Get the parsed descriptor of the method
+	pub fn get_type<'a>(&'a self) -> &'a MethodDescriptor {
+		&self.jtype
+	}
+
+
+	// ----------------------------------------------
+	// Get the number of local variable slots taken by the method's 
+	// arguments, including the implicit `this` for instance methods.
+	pub fn get_arg_slots(&self) -> uint {
+		self.jtype.get_arg_slots() + if self.is_static() { 0 } else { 1 }
+	}
+
+
+	// ----------------------------------------------
+	pub fn get_access(&self) -> uint {
+		self.access
+	}
+
+
+	// ----------------------------------------------
+	pub fn get_code<'a>(&'a self) -> Option<&'a CodeBlock> {
+		self.code.as_ref()
+	}
+
+
+	// ----------------------------------------------
+	pub fn get_attributes<'a>(&'a self) -> &'a ~[Attribute] {
+		&self.attributes
+	}
+
+
+	// ----------------------------------------------
+	// Get the names of the checked exceptions the method declares
+	// to throw, as given by the [Exceptions] attribute.
+	pub fn get_declared_exceptions<'a>(&'a self) -> &'a [~str] {
+		for a in self.attributes.iter() {
+			match *a {
+				ATTR_Exceptions(ref v) => return v.as_slice(),
+				_ => ()
+			}
 		}
+		&[]
+	}
+
+
+	// ----------------------------------------------
+	#[inline]
+	pub fn is_static(&self) -> bool {
+		(self.access & ACC_STATIC) != 0
+	}
+
+
+	// ----------------------------------------------
+	#[inline]
+	pub fn is_native(&self) -> bool {
+		(self.access & ACC_NATIVE) != 0
+	}
+
+
+	// ----------------------------------------------
+	#[inline]
+	pub fn is_abstract(&self) -> bool {
+		(self.access & ACC_ABSTRACT) != 0
+	}
+
+
+	// ----------------------------------------------
+	#[inline]
+	pub fn is_synchronized(&self) -> bool {
+		(self.access & ACC_SYNCHRONIZED) != 0
 	}
 }
 
+
+#[cfg(test)]
+mod tests {
+	use method::*;
+	use def::{ACC_STATIC};
+	use util::{assert_is_err};
+
+	#[test]
+	fn test_method_arg_slot_limit() {
+		// 255 slots are fine for static methods, but not if `this` is added
+		let desc = "(" + "J".repeat(127) + "I)V";
+		assert!(JavaMethod::new("f", desc.as_slice(), ACC_STATIC, None, ~[]).is_ok());
+		assert_is_err(&JavaMethod::new("f", desc.as_slice(), 0, None, ~[]));
+	}
+}
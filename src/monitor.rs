@@ -19,10 +19,6 @@
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 //
 
-use objectbroker::{ObjectBroker};
-use thread::{ThreadContext};
-use localheap::{LocalHeap};
-
 
 // Implementation of a basic Java monitor object. Monitors as
 // mandated by Java generally have weaker properties than the 
@@ -140,11 +136,15 @@ impl JavaMonitor {
 		}
 
 		// check if there is any wait()ing thread that has been
-		// notify()ed and is therefore ready to run again.
+		// notify()ed and is therefore ready to run again. It gets
+		// the monitor handed over locked, with the lock count it
+		// had when calling wait().
 		if self.waiters_prio.len() > 0 {
 			let (notified, tid, lock_count) = self.waiters_prio[0];
 			if notified {
 				self.waiters_prio.shift();
+				self.owner = Some(tid);
+				self.lock_count = lock_count;
 				return Some(tid);
 			}
 		}
@@ -187,32 +187,48 @@ impl JavaMonitor {
 	// lock the monitor, call one of the notify_{one, all} APIs
 	// and unlock the monitor again.
 	//
-	// The monitor must be locked by the current thread.
+	// The monitor must be locked by the given thread.
 	#[inline]
-	pub fn wait_noblock(&mut self, thread : &mut ThreadContext) {
+	pub fn wait_noblock(&mut self, tid : uint) {
 		// assure we hold the monitor
-		assert!(self.is_locked_by_thread(thread.get_tid()));
-		let tid = thread.get_tid();
+		assert!(self.is_locked_by_thread(tid));
 
 		// append the given thread to the end of the list, i.e.
 		// this thread gets served last.
 		self.push_thread(tid, true);
 		self.lock_count = 0;
+		self.owner = None;
 	} 
 
 
+	// ----------------------------------------------
+	// Remove a thread from the wait()ing threads, i.e. because
+	// its wait() timed out or it was interrupted. Returns the lock
+	// count to restore once the thread locks the monitor again, or
+	// None if the thread is not waiting (anymore).
+	pub fn remove_waiter(&mut self, tid : uint) -> Option<uint> {
+		match self.waiters_prio.iter().position(|&(_, t, _)| t == tid) {
+			Some(i) => {
+				let (_, _, lock_count) = self.waiters_prio.remove(i);
+				Some(lock_count)
+			},
+			None => None
+		}
+	}
+
+
 	// ----------------------------------------------
 	// Notify one wait()ing thread, if any. The corresponding
 	// thread is unblocked and resumes operation. It automatically
 	// locks the mutex again.
 	//
-	// The monitor must be locked by the current thread.
-	pub fn notify_one(&mut self, thread : &ThreadContext) {
+	// The monitor must be locked by the given thread.
+	pub fn notify_one(&mut self, tid : uint) {
 		// assure we hold the monitor
-		assert!(self.is_locked_by_thread(thread.get_tid()));
+		assert!(self.is_locked_by_thread(tid));
 		
 		let mut i = 0;
-		let len = self.waiters.len();
+		let len = self.waiters_prio.len();
 
 		while i < len {
 			match self.waiters_prio[i] {
@@ -232,13 +248,13 @@ impl JavaMonitor {
 	// Unlike notify_one(), this marks all wait()ing threads as
 	// ready to run again.
 	//
-	// The monitor must be locked by the current thread.
-	pub fn notify_all(&mut self, thread : &ThreadContext) {
+	// The monitor must be locked by the given thread.
+	pub fn notify_all(&mut self, tid : uint) {
 		// assure we hold the monitor
-		assert!(self.is_locked_by_thread(thread.get_tid()));
+		assert!(self.is_locked_by_thread(tid));
 
 		let mut i = 0;
-		let len = self.waiters.len();
+		let len = self.waiters_prio.len();
 		
 		while i < len {
 			match self.waiters_prio[i] {
@@ -262,9 +278,8 @@ impl JavaMonitor {
 	//
 	// Recursive calls to lock()/unlock() are supported.
 	#[inline]
-	pub fn lock(&mut self, thread : &ThreadContext) {
-		let tid = thread.get_tid();
-		if !self.is_locked_by_thread(tid){
+	pub fn lock(&mut self, tid : uint) {
+		if !self.can_be_locked_by_thread(tid) {
 			fail!("cannot lock object");
 		}
 		self.inc_lock();
@@ -272,14 +287,35 @@ impl JavaMonitor {
 	}
 
 
+	// ----------------------------------------------
+	// Lock the monitor for a thread that stopped wait()ing without
+	// being notified, restoring the lock count it had when calling
+	// wait(). The monitor must not be locked by another thread.
+	pub fn lock_restore(&mut self, tid : uint, lock_count : uint) {
+		if !self.can_be_locked_by_thread(tid) || lock_count == 0 {
+			fail!("cannot lock object");
+		}
+		self.lock_count = lock_count;
+		self.owner = Some(tid);
+	}
+
+
 	// ----------------------------------------------
 	// Leave the monitor again and thus make it available to 
 	// other threads. Every call to lock() must be matched with a
-	// call to unlock().
+	// call to unlock(). Returns false and leaves the monitor
+	// untouched if the given thread does not hold the monitor.
+	//
+	// Once the monitor is released, the thread owning the object
+	// hands it on to the next thread returned by pop_ready_thread().
+	// LocalHeap::access_object does so after every object access.
 	#[inline]
-	pub fn unlock(&mut self, oid : uint, heap : &mut LocalHeap) {
+	pub fn unlock(&mut self, tid : uint) -> bool {
+		if !self.is_locked_by_thread(tid) {
+			return false;
+		}
 		self.dec_lock();
-		// TODO: append wait()ers from mutex to the object waiter list
+		true
 	}
 
 
@@ -321,6 +357,84 @@ impl JavaMonitor {
 	fn dec_lock(&mut self) {
 		assert!(self.lock_count > 0);
 		self.lock_count -= 1;
+		if self.lock_count == 0 {
+			self.owner = None;
+		}
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use monitor::*;
+
+	#[test]
+	fn test_monitor_lock_unlock() {
+		let mut m = JavaMonitor::new();
+		assert!(!m.is_locked());
+		assert!(!m.unlock(1));
+
+		// recursive locking
+		m.lock(1);
+		m.lock(1);
+		assert!(m.is_locked_by_thread(1));
+		assert!(!m.can_be_locked_by_thread(2));
+		assert!(!m.unlock(2));
+
+		// threads waiting for the monitor cannot run before
+		// it is released entirely.
+		m.push_thread(2, false);
+		m.push_thread(3, false);
+		assert!(m.unlock(1));
+		assert_eq!(m.pop_ready_thread(), None);
+		assert!(m.unlock(1));
+		assert!(!m.is_locked());
+
+		assert_eq!(m.pop_ready_thread(), Some(2));
+		m.lock(2);
+		assert_eq!(m.pop_ready_thread(), None);
+		assert!(m.unlock(2));
+		assert_eq!(m.pop_ready_thread(), Some(3));
+		assert_eq!(m.pop_ready_thread(), None);
+	}
+
+
+	#[test]
+	fn test_monitor_wait_notify() {
+		let mut m = JavaMonitor::new();
+		m.lock(1);
+		m.lock(1);
+		m.wait_noblock(1);
+		assert!(!m.is_locked());
+
+		m.lock(2);
+		m.wait_noblock(2);
+
+		// waiters that have not been notified cannot run
+		m.lock(3);
+		assert!(m.unlock(3));
+		assert_eq!(m.pop_ready_thread(), None);
+
+		// notify_one wakes the longest waiting thread, which gets the
+		// monitor back with its recursive lock count.
+		m.lock(3);
+		m.notify_one(3);
+		assert!(m.unlock(3));
+		assert_eq!(m.pop_ready_thread(), Some(1));
+		assert!(m.is_locked_by_thread(1));
+		assert!(m.unlock(1));
+		assert!(m.unlock(1));
+		assert!(!m.is_locked());
+
+		// thread 2 times out instead of being notified
+		m.lock(3);
+		m.notify_all(3);
+		assert_eq!(m.remove_waiter(2), Some(1));
+		assert_eq!(m.remove_waiter(2), None);
+		assert!(m.unlock(3));
+		assert_eq!(m.pop_ready_thread(), None);
+		m.lock_restore(2, 1);
+		assert!(m.is_locked_by_thread(2));
 	}
 }
 
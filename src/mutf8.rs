@@ -0,0 +1,221 @@
+// rustyVM - Java VM written in pure Rust
+// Copyright (c) 2013 Alexander Gessler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+
+// Conversion between Rust strings and the "modified UTF-8" encoding
+// used by CONSTANT_Utf8 entries in .class files, see
+// http://docs.oracle.com/javase/specs/jvms/se7/html/jvms-4.html#jvms-4.4.7
+//
+// Modified UTF-8 differs from standard UTF-8 in that
+//  - the NUL character is encoded using two bytes (0xC0 0x80), so
+//    encoded strings never contain a zero byte.
+//  - characters outside the BMP are first split into an UTF-16
+//    surrogate pair and each surrogate is then encoded separately
+//    using three bytes (this is also known as CESU-8). Four byte
+//    sequences are therefore never used.
+//
+// Java strings may contain unpaired surrogates, which Rust strings cannot
+// represent. The decoder replaces them by U+FFFD.
+
+use std::char;
+use std::str;
+use std::vec;
+
+
+static REPLACEMENT_CHAR : u32 = 0xFFFD;
+
+
+// ----------------------------------------------
+// Decode a modified UTF-8 byte sequence. Returns None if the
+// input is not well-formed.
+pub fn decode(bytes : &[u8]) -> Option<~str> {
+	let len = bytes.len();
+	let mut s = str::with_capacity(len);
+
+	// high surrogate for which we did not see the low surrogate yet
+	let mut high : Option<u32> = None;
+
+	let mut i = 0;
+	while i < len {
+		let b0 = bytes[i] as u32;
+		let unit = if (b0 & 0x80) == 0 {
+			// zero bytes must not appear, NUL is a two byte sequence
+			if b0 == 0 {
+				return None;
+			}
+			i += 1;
+			b0
+		}
+		else if (b0 & 0xE0) == 0xC0 {
+			if i + 1 >= len {
+				return None;
+			}
+			let b1 = bytes[i + 1] as u32;
+			if (b1 & 0xC0) != 0x80 {
+				return None;
+			}
+			i += 2;
+			((b0 & 0x1F) << 6) | (b1 & 0x3F)
+		}
+		else if (b0 & 0xF0) == 0xE0 {
+			if i + 2 >= len {
+				return None;
+			}
+			let b1 = bytes[i + 1] as u32;
+			let b2 = bytes[i + 2] as u32;
+			if (b1 & 0xC0) != 0x80 || (b2 & 0xC0) != 0x80 {
+				return None;
+			}
+			i += 3;
+			((b0 & 0x0F) << 12) | ((b1 & 0x3F) << 6) | (b2 & 0x3F)
+		}
+		else {
+			// stray continuation bytes and 0xF0..0xFF
+			return None;
+		};
+
+		// combine UTF-16 surrogate pairs into one code point
+		if unit >= 0xDC00 && unit <= 0xDFFF {
+			match high.take() {
+				Some(h) => push_code_point(&mut s, 0x10000 + ((h - 0xD800) << 10) + (unit - 0xDC00)),
+				None => push_code_point(&mut s, REPLACEMENT_CHAR)
+			}
+			continue;
+		}
+
+		if high.take().is_some() {
+			push_code_point(&mut s, REPLACEMENT_CHAR);
+		}
+
+		if unit >= 0xD800 && unit <= 0xDBFF {
+			high = Some(unit);
+		}
+		else {
+			push_code_point(&mut s, unit);
+		}
+	}
+
+	if high.is_some() {
+		push_code_point(&mut s, REPLACEMENT_CHAR);
+	}
+	Some(s)
+}
+
+
+// ----------------------------------------------
+// Encode a string as modified UTF-8. The caller is responsible
+// for checking that the result fits the u2 length field of a
+// CONSTANT_Utf8 entry.
+pub fn encode(s : &str) -> ~[u8] {
+	let mut out = vec::with_capacity(s.len());
+	for c in s.chars() {
+		let cp = c as u32;
+		if cp >= 0x10000 {
+			let v = cp - 0x10000;
+			push_unit(&mut out, 0xD800 + (v >> 10));
+			push_unit(&mut out, 0xDC00 + (v & 0x3FF));
+		}
+		else {
+			push_unit(&mut out, cp);
+		}
+	}
+	out
+}
+
+
+// IMPL
+
+
+// ----------------------------------------------
+#[inline]
+fn push_code_point(s : &mut ~str, cp : u32) {
+	s.push_char(char::from_u32(cp).unwrap());
+}
+
+
+// ----------------------------------------------
+// Encode one UTF-16 code unit using one, two or three bytes
+#[inline]
+fn push_unit(out : &mut ~[u8], unit : u32) {
+	if unit != 0 && unit < 0x80 {
+		out.push(unit as u8);
+	}
+	else if unit < 0x800 {
+		out.push((0xC0 | (unit >> 6)) as u8);
+		out.push((0x80 | (unit & 0x3F)) as u8);
+	}
+	else {
+		out.push((0xE0 | (unit >> 12)) as u8);
+		out.push((0x80 | ((unit >> 6) & 0x3F)) as u8);
+		out.push((0x80 | (unit & 0x3F)) as u8);
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use mutf8::*;
+
+	#[test]
+	fn test_mutf8_decode() {
+		assert_eq!(decode([0x61, 0x62]), Some(~"ab"));
+		assert_eq!(decode([]), Some(~""));
+
+		// NUL is encoded using two bytes
+		assert_eq!(decode([0x61, 0xC0, 0x80, 0x62]), Some(~"a\x00b"));
+
+		// two and three byte sequences are as in UTF-8
+		assert_eq!(decode([0xC3, 0xA9]), Some(~"é"));
+		assert_eq!(decode([0xE4, 0xB8, 0xAD]), Some(~"中"));
+
+		// supplementary characters come as surrogate pairs
+		assert_eq!(decode([0xED, 0xA0, 0xBD, 0xED, 0xB8, 0x80]), Some(~"\U0001f600"));
+		assert_eq!(decode([0xED, 0xA1, 0x80, 0xED, 0xB0, 0x80]), Some(~"\U00020000"));
+
+		// unpaired surrogates are replaced
+		assert_eq!(decode([0xED, 0xA0, 0xBD, 0x61]), Some(~"�a"));
+		assert_eq!(decode([0xED, 0xB8, 0x80]), Some(~"�"));
+	}
+
+
+	#[test]
+	fn test_mutf8_decode_fail() {
+		assert!(decode([0x00]).is_none());
+		assert!(decode([0x80]).is_none());
+		assert!(decode([0xC3]).is_none());
+		assert!(decode([0xE4, 0xB8]).is_none());
+		assert!(decode([0xE4, 0x38, 0xAD]).is_none());
+
+		// standard UTF-8 four byte sequences are not allowed
+		assert!(decode([0xF0, 0x9F, 0x98, 0x80]).is_none());
+	}
+
+
+	#[test]
+	fn test_mutf8_encode() {
+		assert_eq!(encode("ab"), ~[0x61, 0x62]);
+		assert_eq!(encode("\x00"), ~[0xC0, 0x80]);
+		assert_eq!(encode("\U0001f600"), ~[0xED, 0xA0, 0xBD, 0xED, 0xB8, 0x80]);
+
+		for s in (~["", "rusty\x00vm", "é中\U0001f600\U00020000"]).iter() {
+			assert_eq!(decode(encode(*s)), Some(s.to_owned()));
+		}
+	}
+}
@@ -0,0 +1,354 @@
+// rustyVM - Java VM written in pure Rust
+// Copyright (c) 2013 Alexander Gessler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+
+
+// Registry of native methods, i.e. methods declared ACC_NATIVE whose
+// implementation is provided by Rust code, see 
+// http://docs.oracle.com/javase/specs/jvms/se7/html/jvms-2.html#jvms-2.11.10
+//
+// Natives are looked up by the name of their declaring class, their name
+// and their descriptor. Every thread has its own registry, which comes
+// with the natives the VM itself implements for the JDK. Host 
+// integrations add theirs using ThreadContext::register_native().
+//
+// A native receives the thread calling it, the local heap of that 
+// thread and its arguments, with the receiver first for instance methods.
+// It may allocate objects on the heap and call back into Java using 
+// ThreadContext::call_method(). To throw an exception, it raises it on
+// the thread using ThreadContext::throw_exception() or rethrow(), in
+// which case its return value is ignored.
+
+use std::hashmap::{HashMap};
+use std::cast;
+
+use thread::{ThreadContext};
+use localheap::{LocalHeap};
+use object::{JavaObjectId, JAVA_NULL_OID};
+use objectbroker::{OBJECT_ACCESS_Normal};
+use layout::{SlotKind, SK_Int, SK_Float, SK_Long, SK_Double, SK_Reference};
+
+
+// Typed value of an argument or return value of a native method.
+// boolean, byte, char and short values are passed as int, just as
+// they are on the operand stack.
+#[deriving(Clone, Eq, ToStr)]
+pub enum JavaValue {
+	JV_Int(i32),
+	JV_Float(f32),
+	JV_Long(i64),
+	JV_Double(f64),
+	JV_Reference(JavaObjectId)
+}
+
+
+impl JavaValue {
+
+	// ----------------------------------------------
+	// Convert a value from the raw representation used for fields
+	// and on the operand stack
+	pub fn from_raw(kind : SlotKind, v : u64) -> JavaValue {
+		match kind {
+			SK_Int => JV_Int(v as u32 as i32),
+			SK_Float => JV_Float(unsafe { cast::transmute(v as u32) }),
+			SK_Long => JV_Long(v as i64),
+			SK_Double => JV_Double(unsafe { cast::transmute(v) }),
+			SK_Reference => JV_Reference(v)
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Get the raw representation of the value, see from_raw()
+	pub fn to_raw(&self) -> u64 {
+		match *self {
+			JV_Int(v) => v as u32 as u64,
+			JV_Float(v) => unsafe { cast::transmute::<f32, u32>(v) } as u64,
+			JV_Long(v) => v as u64,
+			JV_Double(v) => unsafe { cast::transmute(v) },
+			JV_Reference(v) => v
+		}
+	}
+
+
+	// ----------------------------------------------
+	pub fn get_kind(&self) -> SlotKind {
+		match *self {
+			JV_Int(_) => SK_Int,
+			JV_Float(_) => SK_Float,
+			JV_Long(_) => SK_Long,
+			JV_Double(_) => SK_Double,
+			JV_Reference(_) => SK_Reference
+		}
+	}
+}
+
+
+// A native method. Returns the return value of the method, which is
+// None for void methods.
+pub type NativeMethod = fn(t : &mut ThreadContext, heap : &mut LocalHeap, 
+	args : &[JavaValue]) -> Option<JavaValue>;
+
+
+pub struct NativeRegistry {
+	// keyed by class name, method name and descriptor
+	priv methods : HashMap<(~str, ~str, ~str), NativeMethod>,
+}
+
+
+impl NativeRegistry {
+
+	// ----------------------------------------------
+	// Create an empty registry
+	pub fn new() -> NativeRegistry {
+		NativeRegistry {
+			methods : HashMap::new(),
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Create a registry holding the natives implemented by the VM
+	pub fn new_with_builtins() -> NativeRegistry {
+		let mut r = NativeRegistry::new();
+		for &(class, name, desc, f) in BUILTIN_NATIVES.iter() {
+			r.register(class, name, desc, f);
+		}
+		r
+	}
+
+
+	// ----------------------------------------------
+	// Register a native method, replacing any previous implementation 
+	// of the same method.
+	pub fn register(&mut self, class : &str, name : &str, desc : &str, f : NativeMethod) {
+		self.methods.insert((class.into_owned(), name.into_owned(), desc.into_owned()), f);
+	}
+
+
+	// ----------------------------------------------
+	// Find the implementation of a native method
+	pub fn find(&self, class : &str, name : &str, desc : &str) -> Option<NativeMethod> {
+		let key = (class.into_owned(), name.into_owned(), desc.into_owned());
+		self.methods.find(&key).map(|f| *f)
+	}
+}
+
+
+static BUILTIN_NATIVES : &'static [(&'static str, &'static str, &'static str, NativeMethod)] = &[
+	("java.lang.Object", "registerNatives", "()V", native_noop),
+	("java.lang.Class", "registerNatives", "()V", native_noop),
+	("java.lang.System", "registerNatives", "()V", native_noop),
+	("java.lang.Thread", "registerNatives", "()V", native_noop),
+
+	("java.lang.Class", "desiredAssertionStatus0", "(Ljava/lang/Class;)Z", native_class_desired_assertion_status0),
+	("java.lang.Class", "getClassLoader0", "()Ljava/lang/ClassLoader;", native_class_get_class_loader0),
+
+	("java.lang.Object", "wait", "(J)V", native_object_wait),
+	("java.lang.Object", "wait", "(JI)V", native_object_wait),
+	("java.lang.Object", "notify", "()V", native_object_notify),
+	("java.lang.Object", "notifyAll", "()V", native_object_notify_all),
+
+	("java.lang.String", "intern", "()Ljava/lang/String;", native_string_intern),
+
+	("java.lang.Thread", "currentThread", "()Ljava/lang/Thread;", native_thread_current_thread),
+	("java.lang.Thread", "interrupt0", "()V", native_thread_interrupt0),
+	("java.lang.Thread", "isInterrupted", "(Z)Z", native_thread_is_interrupted),
+
+	("java.lang.Shutdown", "beforeHalt", "()V", native_noop),
+	("java.lang.Shutdown", "runAllFinalizers", "()V", native_noop),
+	("java.lang.Shutdown", "halt0", "(I)V", native_shutdown_halt0),
+];
+
+
+// IMPL
+
+// ----------------------------------------------
+#[inline]
+fn get_int(v : &JavaValue) -> i32 {
+	match *v {
+		JV_Int(i) => i,
+		_ => fail!("expected int argument")
+	}
+}
+
+#[inline]
+fn get_long(v : &JavaValue) -> i64 {
+	match *v {
+		JV_Long(l) => l,
+		_ => fail!("expected long argument")
+	}
+}
+
+#[inline]
+fn get_ref(v : &JavaValue) -> JavaObjectId {
+	match *v {
+		JV_Reference(oid) => oid,
+		_ => fail!("expected reference argument")
+	}
+}
+
+
+// ----------------------------------------------
+// registerNatives() binds the natives of JDK classes in HotSpot,
+// here they are found in the registry without further ado.
+fn native_noop(_ : &mut ThreadContext, _ : &mut LocalHeap, _ : &[JavaValue]) -> Option<JavaValue> {
+	None
+}
+
+
+// ----------------------------------------------
+// Class.desiredAssertionStatus0(Class), which is asked for classes 
+// loaded by the bootstrap loader. Assertions are always disabled as
+// there is no -ea switch yet.
+fn native_class_desired_assertion_status0(_ : &mut ThreadContext, _ : &mut LocalHeap, _ : &[JavaValue]) -> Option<JavaValue> {
+	Some(JV_Int(0))
+}
+
+
+// ----------------------------------------------
+// Class.getClassLoader0(). All classes are loaded by the VM's own 
+// loader, which is the bootstrap loader and therefore null in Java.
+fn native_class_get_class_loader0(_ : &mut ThreadContext, _ : &mut LocalHeap, _ : &[JavaValue]) -> Option<JavaValue> {
+	Some(JV_Reference(JAVA_NULL_OID))
+}
+
+
+// ----------------------------------------------
+// Object.wait(long) and Object.wait(long, int)
+fn native_object_wait(t : &mut ThreadContext, _ : &mut LocalHeap, args : &[JavaValue]) -> Option<JavaValue> {
+	let nanos = if args.len() > 2 { get_int(&args[2]) } else { 0 };
+	t.monitor_wait(get_ref(&args[0]), get_long(&args[1]), nanos);
+	None
+}
+
+
+// ----------------------------------------------
+fn native_object_notify(t : &mut ThreadContext, _ : &mut LocalHeap, args : &[JavaValue]) -> Option<JavaValue> {
+	t.monitor_notify(get_ref(&args[0]), false);
+	None
+}
+
+
+// ----------------------------------------------
+fn native_object_notify_all(t : &mut ThreadContext, _ : &mut LocalHeap, args : &[JavaValue]) -> Option<JavaValue> {
+	t.monitor_notify(get_ref(&args[0]), true);
+	None
+}
+
+
+// ----------------------------------------------
+fn native_string_intern(t : &mut ThreadContext, _ : &mut LocalHeap, args : &[JavaValue]) -> Option<JavaValue> {
+	let oid = get_ref(&args[0]);
+	let chars = t.get_string_chars(oid);
+	match t.intern_string(chars, Some(oid)) {
+		Ok(interned) => Some(JV_Reference(interned)),
+		Err(name) => {
+			t.throw_exception(name, None);
+			None
+		}
+	}
+}
+
+
+// ----------------------------------------------
+fn native_thread_current_thread(t : &mut ThreadContext, _ : &mut LocalHeap, _ : &[JavaValue]) -> Option<JavaValue> {
+	Some(JV_Reference(t.get_thread_object()))
+}
+
+
+// ----------------------------------------------
+// Thread.interrupt0(), which interrupts the thread whose tid is in the
+// `eetop` field of the Thread object (see ThreadContext::set_thread_object)
+fn native_thread_interrupt0(t : &mut ThreadContext, heap : &mut LocalHeap, args : &[JavaValue]) -> Option<JavaValue> {
+	let mut tid = 0;
+	heap.access_object(OBJECT_ACCESS_Normal, get_ref(&args[0]), |obj| {
+		let jclass = obj.get_class();
+		match jclass.get().get_instance_layout().find_by_name("eetop") {
+			Some(slot) => tid = obj.get_long(slot.get_offset()) as uint,
+			None => ()
+		}
+	});
+	// threads that have not been started cannot be interrupted
+	if tid != 0 {
+		t.interrupt_thread(tid);
+	}
+	None
+}
+
+
+// ----------------------------------------------
+// Thread.isInterrupted(boolean ClearInterrupted)
+fn native_thread_is_interrupted(t : &mut ThreadContext, _ : &mut LocalHeap, args : &[JavaValue]) -> Option<JavaValue> {
+	// TODO: the interrupt status of other threads is not
+	// known to this thread and reported as false.
+	let interrupted = get_ref(&args[0]) == t.get_thread_object() && 
+		t.is_interrupted(get_int(&args[1]) != 0);
+	Some(JV_Int(interrupted as i32))
+}
+
+
+// ----------------------------------------------
+// Shutdown.halt0(int), the end of System.exit() and Runtime.halt()
+fn native_shutdown_halt0(t : &mut ThreadContext, _ : &mut LocalHeap, args : &[JavaValue]) -> Option<JavaValue> {
+	t.halt(get_int(&args[0]) as int);
+	None
+}
+
+
+#[cfg(test)]
+mod tests {
+	use natives::*;
+	use thread::{ThreadContext};
+	use localheap::{LocalHeap};
+	use layout::{SK_Int, SK_Float, SK_Long, SK_Double, SK_Reference};
+
+	fn native_answer(_ : &mut ThreadContext, _ : &mut LocalHeap, _ : &[JavaValue]) -> Option<JavaValue> {
+		Some(JV_Int(42))
+	}
+
+	#[test]
+	fn test_native_registry() {
+		let mut r = NativeRegistry::new();
+		assert!(r.find("Foo", "answer", "()I").is_none());
+
+		r.register("Foo", "answer", "()I", native_answer);
+		assert!(r.find("Foo", "answer", "()I").is_some());
+		assert!(r.find("Foo", "answer", "()J").is_none());
+		assert!(r.find("Bar", "answer", "()I").is_none());
+
+		let r = NativeRegistry::new_with_builtins();
+		assert!(r.find("java.lang.Object", "notifyAll", "()V").is_some());
+		assert!(r.find("java.lang.String", "intern", "()Ljava/lang/String;").is_some());
+	}
+
+
+	#[test]
+	fn test_java_value_raw() {
+		let values = [JV_Int(-5), JV_Float(-1.5), JV_Long(-1234567890123), 
+			JV_Double(0.25), JV_Reference(77)];
+		let kinds = [SK_Int, SK_Float, SK_Long, SK_Double, SK_Reference];
+		for (v, kind) in values.iter().zip(kinds.iter()) {
+			assert_eq!(v.get_kind(), *kind);
+			assert_eq!(JavaValue::from_raw(*kind, v.to_raw()), *v);
+		}
+		assert_eq!(JV_Int(-1).to_raw(), 0xffffffff);
+	}
+}
@@ -19,14 +19,22 @@
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 //
 
-use std::ops::{Index};
+use std::vec;
+use std::cast;
 use class::{JavaClassRef};
 use monitor::{JavaMonitor};
+use layout::{SlotKind, SK_Int, SK_Float, SK_Long, SK_Double, SK_Reference};
+use field::{FieldDescriptor, FD_BaseType, BT_B_byte, BT_C_char, BT_D_double, 
+	BT_F_float, BT_I_int, BT_J_long, BT_S_short, BT_Z_boolean};
 
 // Type used for referencing objects. A 64 bit integer is used
 // to ensure that we never run out of ids.
 pub type JavaObjectId = u64;
 
+// The oid that represents the null reference. No object is ever
+// assigned this oid, so zero-initialized reference fields are null.
+pub static JAVA_NULL_OID : JavaObjectId = 0;
+
 
 // A JavaObject instance represents an alive Java object. Instances
 // of Java objects are reference counted. At every time, an object
@@ -40,20 +48,47 @@ pub struct JavaObject {
 
 	priv ref_count : uint,
 	priv jclass : JavaClassRef,
+
+	// field values, laid out according to the class' layout table.
+	// Values wider than 32 bits span two consecutive words, with the
+	// high word first. Use the typed get_XXX/set_XXX accessors.
 	priv fields : ~[u32],
 
+	// whether the object holds the static fields of jclass rather
+	// than being an instance of it.
+	priv is_class_statics : bool,
+
 	// The monitor object that guards synchronized object access
 	priv monitor : JavaMonitor,
+
+	// element storage if the object is an array, `fields`
+	// is empty in this case.
+	priv array : Option<ArrayData>,
+}
+
+
+// Element storage for Java arrays. Elements are kept at their
+// natural width so a byte[] takes one byte per element.
+pub enum ArrayData {
+	AD_Boolean(~[u8]),
+	AD_Byte(~[i8]),
+	AD_Char(~[u16]),
+	AD_Short(~[i16]),
+	AD_Int(~[i32]),
+	AD_Long(~[i64]),
+	AD_Float(~[f32]),
+	AD_Double(~[f64]),
+	AD_Reference(~[JavaObjectId]),
 }
 
 
 impl JavaObject {
 
 	// ----------------------------------------------
-	// Construct a JavaObject and provide constant-field
-	// initialization according to the runtime-layout
-	// table of that class. No constructor code is 
-	// executed.
+	// Construct a JavaObject with storage for all instance
+	// fields according to the runtime-layout table of that 
+	// class. All fields are zero-initialized, no constructor
+	// code is executed.
 	//
 	// Do not invoke this method directly, instead use
 	// LocalHeap::new_XXX.
@@ -61,14 +96,34 @@ impl JavaObject {
 	// The intial refcount for objects is 1.
 	pub fn new(jclass : JavaClassRef, oid : JavaObjectId) -> JavaObject
 	{
-		JavaObject {
-			oid : oid,
-			ref_count : 1,
-			jclass : jclass,
-			fields : ~[],
-			monitor : JavaMonitor::new()
-		}
-		// TODO: field initialization
+		let size = jclass.get().get_instance_layout().get_size();
+		JavaObject::new_with_size(jclass, oid, size, false)
+	}
+
+
+	// ----------------------------------------------
+	// Construct a JavaObject that holds the static fields of
+	// the given class, laid out according to the class' static 
+	// layout table. All fields are zero-initialized.
+	pub fn new_class_statics(jclass : JavaClassRef, oid : JavaObjectId) -> JavaObject
+	{
+		let size = jclass.get().get_static_layout().get_size();
+		JavaObject::new_with_size(jclass, oid, size, true)
+	}
+
+	// ----------------------------------------------
+	// Construct a Java array of the given array class with `length`
+	// zero-initialized elements. As with new(), do not invoke this
+	// directly but use LocalHeap::new_array_object.
+	pub fn new_array(jclass : JavaClassRef, oid : JavaObjectId, length : uint) -> JavaObject
+	{
+		let data = match *jclass.get().get_component_type() {
+			Some(ref component) => ArrayData::new(component, length),
+			None => fail!("{} is not an array class", *jclass.get().get_name())
+		};
+		let mut obj = JavaObject::new_with_size(jclass, oid, 0, false);
+		obj.array = Some(data);
+		obj
 	}
 
 	// ----------------------------------------------
@@ -115,15 +170,336 @@ impl JavaObject {
 	pub fn monitor_mut<'t>(&'t mut self) -> &'t mut JavaMonitor {
 		&mut self.monitor
 	}
+
+
+	// ----------------------------------------------
+	// Check whether the object is a Java array
+	#[inline]
+	pub fn is_array(&self) -> bool {
+		self.array.is_some()
+	}
+
+	// ----------------------------------------------
+	// Access the elements of an array object. Fails if the 
+	// object is not an array.
+	#[inline]
+	pub fn array<'t>(&'t self) -> &'t ArrayData {
+		match self.array {
+			Some(ref data) => data,
+			None => fail!("object is not an array")
+		}
+	}
+
+	#[inline]
+	pub fn array_mut<'t>(&'t mut self) -> &'t mut ArrayData {
+		match self.array {
+			Some(ref mut data) => data,
+			None => fail!("object is not an array")
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Typed field access. `offset` is the offset of a field as given by
+	// the layout table of the object's class. boolean, byte, char and
+	// short fields are accessed as int. In debug builds, the kind of
+	// the field at the given offset is checked.
+	#[inline]
+	pub fn get_int(&self, offset : uint) -> i32 {
+		self.check_kind(offset, SK_Int);
+		self.fields[offset] as i32
+	}
+
+	#[inline]
+	pub fn set_int(&mut self, offset : uint, val : i32) {
+		self.check_kind(offset, SK_Int);
+		self.fields[offset] = val as u32;
+	}
+
+	#[inline]
+	pub fn get_float(&self, offset : uint) -> f32 {
+		self.check_kind(offset, SK_Float);
+		unsafe { cast::transmute(self.fields[offset]) }
+	}
+
+	#[inline]
+	pub fn set_float(&mut self, offset : uint, val : f32) {
+		self.check_kind(offset, SK_Float);
+		self.fields[offset] = unsafe { cast::transmute(val) };
+	}
+
+	#[inline]
+	pub fn get_long(&self, offset : uint) -> i64 {
+		self.check_kind(offset, SK_Long);
+		self.get_wide(offset) as i64
+	}
+
+	#[inline]
+	pub fn set_long(&mut self, offset : uint, val : i64) {
+		self.check_kind(offset, SK_Long);
+		self.set_wide(offset, val as u64);
+	}
+
+	#[inline]
+	pub fn get_double(&self, offset : uint) -> f64 {
+		self.check_kind(offset, SK_Double);
+		unsafe { cast::transmute(self.get_wide(offset)) }
+	}
+
+	#[inline]
+	pub fn set_double(&mut self, offset : uint, val : f64) {
+		self.check_kind(offset, SK_Double);
+		self.set_wide(offset, unsafe { cast::transmute(val) });
+	}
+
+	// References are returned as plain oids, JAVA_NULL_OID being
+	// the null reference. The caller is responsible for add_ref()ing
+	// and release()ing referenced objects as needed.
+	#[inline]
+	pub fn get_ref(&self, offset : uint) -> JavaObjectId {
+		self.check_kind(offset, SK_Reference);
+		self.get_wide(offset) as JavaObjectId
+	}
+
+	#[inline]
+	pub fn set_ref(&mut self, offset : uint, val : JavaObjectId) {
+		self.check_kind(offset, SK_Reference);
+		self.set_wide(offset, val as u64);
+	}
+
+
+	// IMPL
+
+
+	// ----------------------------------------------
+	fn new_with_size(jclass : JavaClassRef, oid : JavaObjectId, size : uint, 
+		is_class_statics : bool) -> JavaObject
+	{
+		JavaObject {
+			oid : oid,
+			ref_count : 1,
+			jclass : jclass,
+			fields : vec::from_elem(size, 0u32),
+			is_class_statics : is_class_statics,
+			monitor : JavaMonitor::new(),
+			array : None
+		}
+	}
+
+
+	// ----------------------------------------------
+	#[inline]
+	fn get_wide(&self, offset : uint) -> u64 {
+		((self.fields[offset] as u64) << 32) | (self.fields[offset + 1] as u64)
+	}
+
+	#[inline]
+	fn set_wide(&mut self, offset : uint, val : u64) {
+		self.fields[offset] = (val >> 32) as u32;
+		self.fields[offset + 1] = val as u32;
+	}
+
+
+	// ----------------------------------------------
+	#[cfg(debug)]
+	#[cfg(test)]
+	fn check_kind(&self, offset : uint, kind : SlotKind) {
+		let jclass = self.jclass.get();
+		let layout = if self.is_class_statics { 
+			jclass.get_static_layout() 
+		} 
+		else { 
+			jclass.get_instance_layout() 
+		};
+		match layout.find_by_offset(offset) {
+			Some(slot) if slot.get_kind() == kind => (),
+			Some(slot) => fail!("field {} of {} is of kind {}, not {}", *slot.get_name(), 
+				*jclass.get_name(), slot.get_kind().to_str(), kind.to_str()),
+			None => fail!("no field at offset {} in {}", offset, *jclass.get_name())
+		}
+	}
+
+	#[cfg(release)]
+	#[inline]
+	fn check_kind(&self, offset : uint, kind : SlotKind) {
+	}
 }
 
 
-impl Index<uint, u32> for JavaObject {
-    fn index(&self, idx: &uint) -> u32 {
-    	assert!(*idx < self.fields.len());
-    	self.fields[*idx]
-    }
+
+
+
+impl ArrayData {
+
+	// ----------------------------------------------
+	// Allocate storage for `length` zero-initialized elements 
+	// of the given component type. 
+	pub fn new(component : &FieldDescriptor, length : uint) -> ArrayData {
+		match *component {
+			FD_BaseType(BT_Z_boolean) => AD_Boolean(vec::from_elem(length, 0u8)),
+			FD_BaseType(BT_B_byte) => AD_Byte(vec::from_elem(length, 0i8)),
+			FD_BaseType(BT_C_char) => AD_Char(vec::from_elem(length, 0u16)),
+			FD_BaseType(BT_S_short) => AD_Short(vec::from_elem(length, 0i16)),
+			FD_BaseType(BT_I_int) => AD_Int(vec::from_elem(length, 0i32)),
+			FD_BaseType(BT_J_long) => AD_Long(vec::from_elem(length, 0i64)),
+			FD_BaseType(BT_F_float) => AD_Float(vec::from_elem(length, 0f32)),
+			FD_BaseType(BT_D_double) => AD_Double(vec::from_elem(length, 0f64)),
+			_ => AD_Reference(vec::from_elem(length, JAVA_NULL_OID)),
+		}
+	}
+
+
+	// ----------------------------------------------
+	pub fn len(&self) -> uint {
+		match *self {
+			AD_Boolean(ref v) => v.len(),
+			AD_Byte(ref v) => v.len(),
+			AD_Char(ref v) => v.len(),
+			AD_Short(ref v) => v.len(),
+			AD_Int(ref v) => v.len(),
+			AD_Long(ref v) => v.len(),
+			AD_Float(ref v) => v.len(),
+			AD_Double(ref v) => v.len(),
+			AD_Reference(ref v) => v.len(),
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Get the kind of value elements are loaded as. boolean, byte,
+	// char and short elements are loaded as int.
+	pub fn get_kind(&self) -> SlotKind {
+		match *self {
+			AD_Long(_) => SK_Long,
+			AD_Float(_) => SK_Float,
+			AD_Double(_) => SK_Double,
+			AD_Reference(_) => SK_Reference,
+			_ => SK_Int
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Get an element in its raw operand stack representation. byte
+	// and short are sign-extended to int, boolean and char are 
+	// zero-extended. `index` must be within bounds.
+	pub fn get(&self, index : uint) -> u64 {
+		match *self {
+			AD_Boolean(ref v) => v[index] as u64,
+			AD_Byte(ref v) => v[index] as i32 as u32 as u64,
+			AD_Char(ref v) => v[index] as u64,
+			AD_Short(ref v) => v[index] as i32 as u32 as u64,
+			AD_Int(ref v) => v[index] as u32 as u64,
+			AD_Long(ref v) => v[index] as u64,
+			AD_Float(ref v) => unsafe { cast::transmute::<f32, u32>(v[index]) } as u64,
+			AD_Double(ref v) => unsafe { cast::transmute(v[index]) },
+			AD_Reference(ref v) => v[index],
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Set an element from its raw operand stack representation. int
+	// values are truncated to the width of the element type, for 
+	// boolean arrays only the lowest bit is kept (JVMS bastore).
+	// `index` must be within bounds.
+	pub fn set(&mut self, index : uint, val : u64) {
+		match *self {
+			AD_Boolean(ref mut v) => v[index] = (val & 1) as u8,
+			AD_Byte(ref mut v) => v[index] = val as i8,
+			AD_Char(ref mut v) => v[index] = val as u16,
+			AD_Short(ref mut v) => v[index] = val as i16,
+			AD_Int(ref mut v) => v[index] = val as u32 as i32,
+			AD_Long(ref mut v) => v[index] = val as i64,
+			AD_Float(ref mut v) => v[index] = unsafe { cast::transmute(val as u32) },
+			AD_Double(ref mut v) => v[index] = unsafe { cast::transmute(val) },
+			AD_Reference(ref mut v) => v[index] = val,
+		}
+	}
 }
 
 
 
+#[cfg(test)]
+mod tests {
+	use object::*;
+	use classloader::tests::{test_get_real_classloader};
+	use layout::{SK_Int};
+	use std::i64;
+
+	#[test]
+	fn test_object_typed_fields() {
+		let mut cl = test_get_real_classloader();
+		let c = cl.add_from_classfile("LayoutDerived").await().unwrap();
+		let layout = c.get().get_instance_layout().clone();
+
+		let mut obj = JavaObject::new(c.clone(), 1);
+		let base_a = layout.find_declared("LayoutBase", "a", "I").unwrap().get_offset();
+		let b = layout.find("b", "J").unwrap().get_offset();
+		let c_ref = layout.find("c", "Ljava/lang/Object;").unwrap().get_offset();
+		let a = layout.find("a", "D").unwrap().get_offset();
+
+		// fields are zero-initialized, references are null
+		assert_eq!(obj.get_ref(c_ref), JAVA_NULL_OID);
+		assert_eq!(obj.get_long(b), 0);
+
+		obj.set_int(base_a, -7);
+		obj.set_long(b, i64::min_value);
+		obj.set_ref(c_ref, 0xFFFFFFFF00000001);
+		obj.set_double(a, -1.5e300);
+
+		assert_eq!(obj.get_int(base_a), -7);
+		assert_eq!(obj.get_long(b), i64::min_value);
+		assert_eq!(obj.get_ref(c_ref), 0xFFFFFFFF00000001);
+		assert_eq!(obj.get_double(a), -1.5e300);
+
+		let mut statics = JavaObject::new_class_statics(c.clone(), 2);
+		let counter = c.get().get_static_layout().find("counter", "I").unwrap().get_offset();
+		statics.set_int(counter, 42);
+		assert_eq!(statics.get_int(counter), 42);
+	}
+
+
+	#[test]
+	#[should_fail]
+	fn test_object_typed_fields_kind_mismatch() {
+		let mut cl = test_get_real_classloader();
+		let c = cl.add_from_classfile("LayoutDerived").await().unwrap();
+		let obj = JavaObject::new(c.clone(), 1);
+
+		// reading a reference field as int
+		obj.get_int(c.get().get_instance_layout().find("c", "Ljava/lang/Object;").unwrap().get_offset());
+	}
+
+
+	#[test]
+	fn test_object_array_elements() {
+		let mut cl = test_get_real_classloader();
+		let c = cl.add_from_classfile("[B").await().unwrap();
+
+		let mut arr = JavaObject::new_array(c.clone(), 1, 3);
+		assert!(arr.is_array());
+		assert_eq!(arr.array().len(), 3);
+		assert_eq!(arr.array().get_kind(), SK_Int);
+
+		// byte elements are stored in one byte each and truncated
+		// on store, loads sign-extend them to int.
+		match *arr.array() {
+			AD_Byte(ref v) => assert_eq!(v.len(), 3),
+			_ => fail!("expected byte array storage")
+		}
+		arr.array_mut().set(1, 0x1FF);
+		assert_eq!(arr.array().get(1) as u32 as i32, -1);
+		assert_eq!(arr.array().get(0), 0);
+
+		let bools = cl.add_from_classfile("[Z").await().unwrap();
+		let mut arr = JavaObject::new_array(bools, 2, 1);
+		arr.array_mut().set(0, 2);
+		assert_eq!(arr.array().get(0), 0);
+
+		let chars = cl.add_from_classfile("[C").await().unwrap();
+		let mut arr = JavaObject::new_array(chars, 3, 1);
+		arr.array_mut().set(0, -1i32 as u32 as u64);
+		assert_eq!(arr.array().get(0), 0xFFFF);
+	}
+}
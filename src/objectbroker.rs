@@ -28,8 +28,10 @@ use extra::comm::{DuplexStream};
 
 
 use object::{JavaObject, JavaObjectId};
-use threadmanager::{ThreadManager, RemoteThreadOpMessage};
+use threadmanager::{ThreadManager, RemoteThreadOpMessage, THREAD_INTERRUPT, THREAD_WAKEUP};
 use threadmanager;
+use classinit::{ClassInitManager, ClassOpMessage};
+use stringintern::{StringInternManager, StringOpMessage};
 use vm;
 
 // Enumerates all possible types of accessing objects.
@@ -127,6 +129,20 @@ pub enum ObjectBrokerMessage {
 	OB_THREAD_REMOTE_OP(uint, uint, RemoteThreadOpMessage),
 
 
+	// ## Class initialization ##
+	// thread a sends a message pertaining to the initialization of
+	// the class with name b, or the broker responds to such a message 
+	// (in which case a is 0).
+	OB_CLASS_OP(uint, ~str, ClassOpMessage),
+
+
+	// ## String interning ##
+	// thread a sends a message pertaining to the interned String with
+	// the UTF-16 contents b, or the broker responds to such a message
+	// (in which case a is 0).
+	OB_STRING_OP(uint, ~[u16], StringOpMessage),
+
+
 	// ## VM management ##
 	// Connection to VM 
 	OB_VM_TO_BROKER(vm::VMToBrokerControlMessage),
@@ -167,6 +183,8 @@ pub struct ObjectBroker {
 	priv vm_chan : Chan< vm::BrokerToVMControlMessage>,
 
 	priv threads : ThreadManager,
+	priv classes : ClassInitManager,
+	priv strings : StringInternManager,
 
 	priv objects_with_owners: HashMap<JavaObjectId, uint>,
 	priv objects_owned : ObjectSet,
@@ -208,6 +226,8 @@ impl ObjectBroker {
 		ObjectBroker {
 			vm_chan : cstream,
 			threads : ThreadManager::new(),
+			classes : ClassInitManager::new(),
+			strings : StringInternManager::new(),
 
 			// maps object-ids (oid) to their other thread-ids (tid) or to 0
 			// if the broker owns them (i.e. they are in objects_owned).
@@ -262,7 +282,29 @@ impl ObjectBroker {
 			},
 
 			OB_THREAD_REMOTE_OP(a, b, remote_op) => {
-				self.threads.process_message(a, b, remote_op)
+				match remote_op {
+					// interrupts and wakeups are delivered to the target
+					// thread, unless it has died in the meantime.
+					THREAD_INTERRUPT | THREAD_WAKEUP(_) => {
+						match self.thread_chans.find(&b) {
+							Some(t) => t.send(OB_THREAD_REMOTE_OP(a, b, remote_op)),
+							None => ()
+						}
+					},
+					_ => self.threads.process_message(a, b, remote_op)
+				}
+			},
+
+			OB_CLASS_OP(a, name, op) => {
+				let responses = self.classes.process_message(a, name, op);
+				for (tid, msg) in responses.move_iter() {
+					self.thread_chans.get(&tid).send(OB_CLASS_OP(0, name.clone(), msg));
+				}
+			},
+
+			OB_STRING_OP(a, value, op) => {
+				let response = self.strings.process_message(value, op);
+				self.thread_chans.get(&a).send(OB_STRING_OP(0, value, response));
 			},
 
 
@@ -313,6 +355,11 @@ impl ObjectBroker {
 
 				self.verify_thread_owns_no_objects(a);
 
+				// fail classes that the thread did not finish initializing
+				for (tid, name, msg) in self.classes.remove_thread(a).move_iter() {
+					self.thread_chans.get(&tid).send(OB_CLASS_OP(0, name, msg));
+				}
+
 				debug!("object broker unregistered with thread {}", a);
 
 				// unregister the thread from threadmanager and check if this
@@ -396,8 +443,13 @@ impl ObjectBroker {
 				}
 
 				// cleanup shelf, sending the messages all in the right order,
-				// but not more than one OWN message
-				let mut sh = self.waiting_shelf.pop(&b).unwrap();
+				// but not more than one OWN message. There is no shelf if
+				// the object was handed on without being asked for, i.e. to
+				// the next thread waiting for the object's monitor.
+				let mut sh = match self.waiting_shelf.pop(&b) {
+					Some(sh) => sh,
+					None => return
+				};
 				while sh.len() > 0 {
 					match sh.shift().unwrap() {
 						OB_REMOTE_OBJECT_OP(a, b, op) => self.handle_object_op(a, b, op),
@@ -474,10 +526,13 @@ impl ObjectBroker {
 				// OB_RQ_ADD_REF() is sound.
 
 				let owner = *objects.get(&b);
-				// cannot request object oned by oneself
-				// bookkeeping of own owned objects is consistent,
-				// so failure to hold this would be a logic error.
-				assert!(owner != a);
+				// if the requesting thread is already the owner, the
+				// object has been handed on to it without being asked
+				// for (i.e. because it was notified while waiting on
+				// the object's monitor) and is on its way to it.
+				if owner == a {
+					return;
+				}
 
 				// if the broker owns this object, send it immediately
 				if owner == 0 {
@@ -22,25 +22,390 @@
 // Maps from the instruction code found in the .class files
 // to symbolic Opcode identifiers. This mapping is auto-generated using
 // the compiler-generated FromPrimitive()-traits 
+//
+// See http://docs.oracle.com/javase/specs/jvms/se7/html/jvms-6.html
 
 #[deriving(FromPrimitive)]
 #[deriving(ToStr)]
+#[deriving(Eq)]
 pub enum Opcode {
-	
 	OpCode_nop = 0,
+	OpCode_aconst_null = 1,
+	OpCode_iconst_m1 = 2,
+	OpCode_iconst_0 = 3,
+	OpCode_iconst_1 = 4,
+	OpCode_iconst_2 = 5,
+	OpCode_iconst_3 = 6,
+	OpCode_iconst_4 = 7,
+	OpCode_iconst_5 = 8,
+	OpCode_lconst_0 = 9,
+	OpCode_lconst_1 = 10,
+	OpCode_fconst_0 = 11,
+	OpCode_fconst_1 = 12,
+	OpCode_fconst_2 = 13,
+	OpCode_dconst_0 = 14,
+	OpCode_dconst_1 = 15,
+	OpCode_bipush = 16,
+	OpCode_sipush = 17,
+	OpCode_ldc = 18,
+	OpCode_ldc_w = 19,
+	OpCode_ldc2_w = 20,
+	OpCode_iload = 21,
+	OpCode_lload = 22,
+	OpCode_fload = 23,
+	OpCode_dload = 24,
+	OpCode_aload = 25,
+	OpCode_iload_0 = 26,
+	OpCode_iload_1 = 27,
+	OpCode_iload_2 = 28,
+	OpCode_iload_3 = 29,
+	OpCode_lload_0 = 30,
+	OpCode_lload_1 = 31,
+	OpCode_lload_2 = 32,
+	OpCode_lload_3 = 33,
+	OpCode_fload_0 = 34,
+	OpCode_fload_1 = 35,
+	OpCode_fload_2 = 36,
+	OpCode_fload_3 = 37,
+	OpCode_dload_0 = 38,
+	OpCode_dload_1 = 39,
+	OpCode_dload_2 = 40,
+	OpCode_dload_3 = 41,
+	OpCode_aload_0 = 42,
+	OpCode_aload_1 = 43,
+	OpCode_aload_2 = 44,
+	OpCode_aload_3 = 45,
+	OpCode_iaload = 46,
+	OpCode_laload = 47,
+	OpCode_faload = 48,
+	OpCode_daload = 49,
+	OpCode_aaload = 50,
+	OpCode_baload = 51,
+	OpCode_caload = 52,
+	OpCode_saload = 53,
+	OpCode_istore = 54,
+	OpCode_lstore = 55,
+	OpCode_fstore = 56,
+	OpCode_dstore = 57,
+	OpCode_astore = 58,
+	OpCode_istore_0 = 59,
+	OpCode_istore_1 = 60,
+	OpCode_istore_2 = 61,
+	OpCode_istore_3 = 62,
+	OpCode_lstore_0 = 63,
+	OpCode_lstore_1 = 64,
+	OpCode_lstore_2 = 65,
+	OpCode_lstore_3 = 66,
+	OpCode_fstore_0 = 67,
+	OpCode_fstore_1 = 68,
+	OpCode_fstore_2 = 69,
+	OpCode_fstore_3 = 70,
+	OpCode_dstore_0 = 71,
+	OpCode_dstore_1 = 72,
+	OpCode_dstore_2 = 73,
+	OpCode_dstore_3 = 74,
+	OpCode_astore_0 = 75,
+	OpCode_astore_1 = 76,
+	OpCode_astore_2 = 77,
+	OpCode_astore_3 = 78,
+	OpCode_iastore = 79,
+	OpCode_lastore = 80,
+	OpCode_fastore = 81,
+	OpCode_dastore = 82,
+	OpCode_aastore = 83,
+	OpCode_bastore = 84,
+	OpCode_castore = 85,
+	OpCode_sastore = 86,
+	OpCode_pop = 87,
+	OpCode_pop2 = 88,
+	OpCode_dup = 89,
+	OpCode_dup_x1 = 90,
+	OpCode_dup_x2 = 91,
+	OpCode_dup2 = 92,
+	OpCode_dup2_x1 = 93,
+	OpCode_dup2_x2 = 94,
+	OpCode_swap = 95,
+	OpCode_iadd = 96,
+	OpCode_ladd = 97,
+	OpCode_fadd = 98,
+	OpCode_dadd = 99,
+	OpCode_isub = 100,
+	OpCode_lsub = 101,
+	OpCode_fsub = 102,
+	OpCode_dsub = 103,
+	OpCode_imul = 104,
+	OpCode_lmul = 105,
+	OpCode_fmul = 106,
+	OpCode_dmul = 107,
+	OpCode_idiv = 108,
+	OpCode_ldiv = 109,
+	OpCode_fdiv = 110,
+	OpCode_ddiv = 111,
+	OpCode_irem = 112,
+	OpCode_lrem = 113,
+	OpCode_frem = 114,
+	OpCode_drem = 115,
+	OpCode_ineg = 116,
+	OpCode_lneg = 117,
+	OpCode_fneg = 118,
+	OpCode_dneg = 119,
+	OpCode_ishl = 120,
+	OpCode_lshl = 121,
+	OpCode_ishr = 122,
+	OpCode_lshr = 123,
+	OpCode_iushr = 124,
+	OpCode_lushr = 125,
+	OpCode_iand = 126,
+	OpCode_land = 127,
+	OpCode_ior = 128,
+	OpCode_lor = 129,
+	OpCode_ixor = 130,
+	OpCode_lxor = 131,
+	OpCode_iinc = 132,
+	OpCode_i2l = 133,
+	OpCode_i2f = 134,
+	OpCode_i2d = 135,
+	OpCode_l2i = 136,
+	OpCode_l2f = 137,
+	OpCode_l2d = 138,
+	OpCode_f2i = 139,
+	OpCode_f2l = 140,
+	OpCode_f2d = 141,
+	OpCode_d2i = 142,
+	OpCode_d2l = 143,
+	OpCode_d2f = 144,
+	OpCode_i2b = 145,
+	OpCode_i2c = 146,
+	OpCode_i2s = 147,
+	OpCode_lcmp = 148,
+	OpCode_fcmpl = 149,
+	OpCode_fcmpg = 150,
+	OpCode_dcmpl = 151,
+	OpCode_dcmpg = 152,
+	OpCode_ifeq = 153,
+	OpCode_ifne = 154,
+	OpCode_iflt = 155,
+	OpCode_ifge = 156,
+	OpCode_ifgt = 157,
+	OpCode_ifle = 158,
+	OpCode_if_icmpeq = 159,
+	OpCode_if_icmpne = 160,
+	OpCode_if_icmplt = 161,
+	OpCode_if_icmpge = 162,
+	OpCode_if_icmpgt = 163,
+	OpCode_if_icmple = 164,
+	OpCode_if_acmpeq = 165,
+	OpCode_if_acmpne = 166,
+	OpCode_goto = 167,
+	OpCode_jsr = 168,
+	OpCode_ret = 169,
+	OpCode_tableswitch = 170,
+	OpCode_lookupswitch = 171,
+	OpCode_ireturn = 172,
+	OpCode_lreturn = 173,
+	OpCode_freturn = 174,
+	OpCode_dreturn = 175,
+	OpCode_areturn = 176,
+	OpCode_return = 177,
+	OpCode_getstatic = 178,
+	OpCode_putstatic = 179,
+	OpCode_getfield = 180,
+	OpCode_putfield = 181,
+	OpCode_invokevirtual = 182,
+	OpCode_invokespecial = 183,
+	OpCode_invokestatic = 184,
+	OpCode_invokeinterface = 185,
+	OpCode_invokedynamic = 186,
+	OpCode_new = 187,
+	OpCode_newarray = 188,
+	OpCode_anewarray = 189,
+	OpCode_arraylength = 190,
+	OpCode_athrow = 191,
+	OpCode_checkcast = 192,
+	OpCode_instanceof = 193,
+	OpCode_monitorenter = 194,
+	OpCode_monitorexit = 195,
+	OpCode_wide = 196,
+	OpCode_multianewarray = 197,
+	OpCode_ifnull = 198,
+	OpCode_ifnonnull = 199,
+	OpCode_goto_w = 200,
+	OpCode_jsr_w = 201,
+	OpCode_breakpoint = 202,
+	OpCode_impdep1 = 254,
+	OpCode_impdep2 = 255,
 }
 
 
 // Enum variant containing the decoded and linked forms of the opcodes.
 // In this format, all extra bytes are attached to the opcode itself
 // in the most natural representation possible. References to fields,
-// classes, methods or any other Java symbol are resolved at this stage
-// to alleviate further error checking during execution.
+// classes, methods or any other Java symbol are kept as constant pool
+// indices. They are resolved when first executed and the outcome is
+// cached by the runtime constant pool of the class, see rtpool.rs.
+//
+// Instructions that only differ in the encoding of their operands are
+// represented by the same variant, i.e. iload_0 is decoded as iload(0),
+// bipush and sipush are decoded as iconst(value) and goto_w as goto.
+// Operands of wide instructions are folded into the respective
+// variant as well.
+//
+// Branch targets are given as indices into the list of decoded 
+// instructions of the method, not as bytecode offsets.
+#[deriving(Clone, Eq)]
 pub enum DecodedOpcode {
-
-	DecodedOpcode_nop = 0,
-
+	DecodedOpcode_nop,
+	DecodedOpcode_aconst_null,
+	// iconst_<i>, bipush and sipush
+	DecodedOpcode_iconst(i32),
+	DecodedOpcode_lconst(i64),
+	DecodedOpcode_fconst(f32),
+	DecodedOpcode_dconst(f64),
+	// constant pool index, for both ldc and ldc_w
+	DecodedOpcode_ldc(u16),
+	DecodedOpcode_ldc2_w(u16),
+	DecodedOpcode_iload(uint),
+	DecodedOpcode_lload(uint),
+	DecodedOpcode_fload(uint),
+	DecodedOpcode_dload(uint),
+	DecodedOpcode_aload(uint),
+	DecodedOpcode_iaload,
+	DecodedOpcode_laload,
+	DecodedOpcode_faload,
+	DecodedOpcode_daload,
+	DecodedOpcode_aaload,
+	DecodedOpcode_baload,
+	DecodedOpcode_caload,
+	DecodedOpcode_saload,
+	DecodedOpcode_istore(uint),
+	DecodedOpcode_lstore(uint),
+	DecodedOpcode_fstore(uint),
+	DecodedOpcode_dstore(uint),
+	DecodedOpcode_astore(uint),
+	DecodedOpcode_iastore,
+	DecodedOpcode_lastore,
+	DecodedOpcode_fastore,
+	DecodedOpcode_dastore,
+	DecodedOpcode_aastore,
+	DecodedOpcode_bastore,
+	DecodedOpcode_castore,
+	DecodedOpcode_sastore,
+	DecodedOpcode_pop,
+	DecodedOpcode_pop2,
+	DecodedOpcode_dup,
+	DecodedOpcode_dup_x1,
+	DecodedOpcode_dup_x2,
+	DecodedOpcode_dup2,
+	DecodedOpcode_dup2_x1,
+	DecodedOpcode_dup2_x2,
+	DecodedOpcode_swap,
+	DecodedOpcode_iadd,
+	DecodedOpcode_ladd,
+	DecodedOpcode_fadd,
+	DecodedOpcode_dadd,
+	DecodedOpcode_isub,
+	DecodedOpcode_lsub,
+	DecodedOpcode_fsub,
+	DecodedOpcode_dsub,
+	DecodedOpcode_imul,
+	DecodedOpcode_lmul,
+	DecodedOpcode_fmul,
+	DecodedOpcode_dmul,
+	DecodedOpcode_idiv,
+	DecodedOpcode_ldiv,
+	DecodedOpcode_fdiv,
+	DecodedOpcode_ddiv,
+	DecodedOpcode_irem,
+	DecodedOpcode_lrem,
+	DecodedOpcode_frem,
+	DecodedOpcode_drem,
+	DecodedOpcode_ineg,
+	DecodedOpcode_lneg,
+	DecodedOpcode_fneg,
+	DecodedOpcode_dneg,
+	DecodedOpcode_ishl,
+	DecodedOpcode_lshl,
+	DecodedOpcode_ishr,
+	DecodedOpcode_lshr,
+	DecodedOpcode_iushr,
+	DecodedOpcode_lushr,
+	DecodedOpcode_iand,
+	DecodedOpcode_land,
+	DecodedOpcode_ior,
+	DecodedOpcode_lor,
+	DecodedOpcode_ixor,
+	DecodedOpcode_lxor,
+	// local variable index, increment
+	DecodedOpcode_iinc(uint, i32),
+	DecodedOpcode_i2l,
+	DecodedOpcode_i2f,
+	DecodedOpcode_i2d,
+	DecodedOpcode_l2i,
+	DecodedOpcode_l2f,
+	DecodedOpcode_l2d,
+	DecodedOpcode_f2i,
+	DecodedOpcode_f2l,
+	DecodedOpcode_f2d,
+	DecodedOpcode_d2i,
+	DecodedOpcode_d2l,
+	DecodedOpcode_d2f,
+	DecodedOpcode_i2b,
+	DecodedOpcode_i2c,
+	DecodedOpcode_i2s,
+	DecodedOpcode_lcmp,
+	DecodedOpcode_fcmpl,
+	DecodedOpcode_fcmpg,
+	DecodedOpcode_dcmpl,
+	DecodedOpcode_dcmpg,
+	DecodedOpcode_ifeq(uint),
+	DecodedOpcode_ifne(uint),
+	DecodedOpcode_iflt(uint),
+	DecodedOpcode_ifge(uint),
+	DecodedOpcode_ifgt(uint),
+	DecodedOpcode_ifle(uint),
+	DecodedOpcode_if_icmpeq(uint),
+	DecodedOpcode_if_icmpne(uint),
+	DecodedOpcode_if_icmplt(uint),
+	DecodedOpcode_if_icmpge(uint),
+	DecodedOpcode_if_icmpgt(uint),
+	DecodedOpcode_if_icmple(uint),
+	DecodedOpcode_if_acmpeq(uint),
+	DecodedOpcode_if_acmpne(uint),
+	DecodedOpcode_goto(uint),
+	DecodedOpcode_jsr(uint),
+	DecodedOpcode_ret(uint),
+	// default target, lowest key, targets for all keys from the lowest key onwards
+	DecodedOpcode_tableswitch(uint, i32, ~[uint]),
+	// default target, (key, target) pairs sorted by key
+	DecodedOpcode_lookupswitch(uint, ~[(i32, uint)]),
+	DecodedOpcode_ireturn,
+	DecodedOpcode_lreturn,
+	DecodedOpcode_freturn,
+	DecodedOpcode_dreturn,
+	DecodedOpcode_areturn,
+	DecodedOpcode_return,
+	DecodedOpcode_getstatic(u16),
+	DecodedOpcode_putstatic(u16),
+	DecodedOpcode_getfield(u16),
+	DecodedOpcode_putfield(u16),
+	DecodedOpcode_invokevirtual(u16),
+	DecodedOpcode_invokespecial(u16),
+	DecodedOpcode_invokestatic(u16),
+	// constant pool index, argument count
+	DecodedOpcode_invokeinterface(u16, uint),
+	DecodedOpcode_invokedynamic(u16),
+	DecodedOpcode_new(u16),
+	// primitive array type (T_XXX)
+	DecodedOpcode_newarray(u8),
+	DecodedOpcode_anewarray(u16),
+	DecodedOpcode_arraylength,
+	DecodedOpcode_athrow,
+	DecodedOpcode_checkcast(u16),
+	DecodedOpcode_instanceof(u16),
+	DecodedOpcode_monitorenter,
+	DecodedOpcode_monitorexit,
+	// constant pool index, number of dimensions
+	DecodedOpcode_multianewarray(u16, uint),
+	DecodedOpcode_ifnull(uint),
+	DecodedOpcode_ifnonnull(uint),
 }
-
-
-
@@ -0,0 +1,515 @@
+// rustyVM - Java VM written in pure Rust
+// Copyright (c) 2013 Alexander Gessler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+
+
+// Runtime constant pool of a class, see
+// http://docs.oracle.com/javase/specs/jvms/se7/html/jvms-5.html#jvms-5.1
+//
+// Symbolic references to classes, fields and methods are resolved lazily
+// when an instruction using them is first executed (JVMS 5.4.3). The 
+// outcome of every resolution is cached per constant pool entry, which
+// includes failures: an entry whose resolution failed with a linkage 
+// error keeps failing with the same error.
+//
+// JavaClass instances are shared between threads, whereas resolution
+// results are kept per thread (see ThreadContext) so that looking them
+// up does not require any synchronization.
+
+use std::vec;
+
+use classloader::{AbstractClassLoader, ClassLoader};
+use classloaderror::{ClassLoadError, CLE_IncompatibleClassChangeError, CLE_NoSuchFieldError, 
+	CLE_NoSuchMethodError, CLE_IllegalAccessError};
+use class::{JavaClass, JavaClassRef};
+use method::{JavaMethod};
+use layout::{FieldSlot, SlotKind};
+use def::{Constant, CONSTANT_class_info, CONSTANT_fieldref_info, CONSTANT_methodref_info, 
+	CONSTANT_ifacemethodref_info, ACC_PUBLIC, ACC_PRIVATE, ACC_PROTECTED, ACC_STATIC};
+
+
+// A resolved reference to a field
+#[deriving(Clone)]
+pub struct ResolvedField {
+	// class declaring the field, which is not necessarily the
+	// class named by the symbolic reference.
+	priv declaring_class : JavaClassRef,
+	priv slot : FieldSlot,
+	priv is_static : bool,
+}
+
+
+impl ResolvedField {
+
+	// ----------------------------------------------
+	pub fn get_class<'a>(&'a self) -> &'a JavaClassRef {
+		&self.declaring_class
+	}
+
+
+	// ----------------------------------------------
+	// Get the slot of the field, in the static layout of the declaring
+	// class for static fields and in the instance layout otherwise.
+	pub fn get_slot<'a>(&'a self) -> &'a FieldSlot {
+		&self.slot
+	}
+
+
+	// ----------------------------------------------
+	#[inline]
+	pub fn get_offset(&self) -> uint {
+		self.slot.get_offset()
+	}
+
+
+	// ----------------------------------------------
+	#[inline]
+	pub fn get_kind(&self) -> SlotKind {
+		self.slot.get_kind()
+	}
+
+
+	// ----------------------------------------------
+	#[inline]
+	pub fn is_static(&self) -> bool {
+		self.is_static
+	}
+}
+
+
+// A resolved reference to a method, given as the class declaring
+// the method and the index of the method in that class. The class 
+// named by the reference is kept as well as invokespecial needs it
+// to select the method to invoke.
+#[deriving(Clone)]
+pub struct ResolvedMethod {
+	priv referenced_class : JavaClassRef,
+	priv declaring_class : JavaClassRef,
+	priv index : uint,
+}
+
+
+impl ResolvedMethod {
+
+	// ----------------------------------------------
+	pub fn get_class<'a>(&'a self) -> &'a JavaClassRef {
+		&self.declaring_class
+	}
+
+
+	// ----------------------------------------------
+	// Get the class named by the symbolic reference, which is either
+	// the declaring class or one of its subclasses or subinterfaces.
+	pub fn get_referenced_class<'a>(&'a self) -> &'a JavaClassRef {
+		&self.referenced_class
+	}
+
+
+	// ----------------------------------------------
+	// Get the index of the method, see JavaClass::get_method_by_index()
+	#[inline]
+	pub fn get_index(&self) -> uint {
+		self.index
+	}
+
+
+	// ----------------------------------------------
+	pub fn get_method<'a>(&'a self) -> &'a JavaMethod {
+		self.declaring_class.get().get_method_by_index(self.index)
+	}
+}
+
+
+#[deriving(Clone)]
+enum RuntimeConstant {
+	RC_Unresolved,
+	RC_Class(JavaClassRef),
+	RC_Field(ResolvedField),
+	RC_Method(ResolvedMethod),
+	RC_Error(ClassLoadError),
+}
+
+
+pub struct RuntimeConstantPool {
+	priv jclass : JavaClassRef,
+
+	// one entry per constant pool entry
+	priv entries : ~[RuntimeConstant],
+}
+
+
+impl RuntimeConstantPool {
+
+	// ----------------------------------------------
+	pub fn new(jclass : JavaClassRef) -> RuntimeConstantPool {
+		let len = jclass.get().get_constants().len();
+		RuntimeConstantPool {
+			jclass : jclass,
+			entries : vec::from_elem(len, RC_Unresolved),
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Get the class owning the constant pool
+	pub fn get_class<'a>(&'a self) -> &'a JavaClassRef {
+		&self.jclass
+	}
+
+
+	// ----------------------------------------------
+	// Resolve a CONSTANT_Class entry, given its one-based index, to
+	// the class it refers to (JVMS 5.4.3.1).
+	pub fn resolve_class(&mut self, loader : &mut AbstractClassLoader, oneb_index : uint) -> 
+		Result<JavaClassRef, ClassLoadError> 
+	{
+		match self.check_entry(oneb_index, "CONSTANT_Class", |c| match *c {
+			CONSTANT_class_info(_) => true,
+			_ => false
+		}) {
+			Err(e) => return Err(e),
+			Ok(_) => ()
+		}
+		match self.entries[oneb_index - 1] {
+			RC_Class(ref c) => return Ok(c.clone()),
+			RC_Error(ref e) => return Err(e.clone()),
+			_ => ()
+		}
+
+		let res = self.do_resolve_class(loader, oneb_index);
+		self.entries[oneb_index - 1] = match res {
+			Ok(ref c) => RC_Class(c.clone()),
+			Err(ref e) => RC_Error(e.clone())
+		};
+		res
+	}
+
+
+	// ----------------------------------------------
+	// Resolve a CONSTANT_Fieldref entry, given its one-based index, 
+	// to the field it refers to (JVMS 5.4.3.2).
+	pub fn resolve_field(&mut self, loader : &mut AbstractClassLoader, oneb_index : uint) -> 
+		Result<ResolvedField, ClassLoadError> 
+	{
+		match self.check_entry(oneb_index, "CONSTANT_Fieldref", |c| match *c {
+			CONSTANT_fieldref_info(_, _) => true,
+			_ => false
+		}) {
+			Err(e) => return Err(e),
+			Ok(_) => ()
+		}
+		match self.entries[oneb_index - 1] {
+			RC_Field(ref f) => return Ok(f.clone()),
+			RC_Error(ref e) => return Err(e.clone()),
+			_ => ()
+		}
+
+		let res = self.do_resolve_field(loader, oneb_index);
+		self.entries[oneb_index - 1] = match res {
+			Ok(ref f) => RC_Field(f.clone()),
+			Err(ref e) => RC_Error(e.clone())
+		};
+		res
+	}
+
+
+	// ----------------------------------------------
+	// Resolve a CONSTANT_Methodref or CONSTANT_InterfaceMethodref entry,
+	// given its one-based index, to the method it refers to (JVMS 5.4.3.3
+	// and 5.4.3.4). This does not select the method to be invoked for
+	// virtual calls, which is up to the invoke instructions.
+	pub fn resolve_method(&mut self, loader : &mut AbstractClassLoader, oneb_index : uint) -> 
+		Result<ResolvedMethod, ClassLoadError> 
+	{
+		match self.check_entry(oneb_index, "CONSTANT_Methodref or CONSTANT_InterfaceMethodref", 
+			|c| match *c {
+				CONSTANT_methodref_info(_, _) | CONSTANT_ifacemethodref_info(_, _) => true,
+				_ => false
+		}) {
+			Err(e) => return Err(e),
+			Ok(_) => ()
+		}
+		match self.entries[oneb_index - 1] {
+			RC_Method(ref m) => return Ok(m.clone()),
+			RC_Error(ref e) => return Err(e.clone()),
+			_ => ()
+		}
+
+		let res = self.do_resolve_method(loader, oneb_index);
+		self.entries[oneb_index - 1] = match res {
+			Ok(ref m) => RC_Method(m.clone()),
+			Err(ref e) => RC_Error(e.clone())
+		};
+		res
+	}
+
+
+	// IMPL
+
+
+	// ----------------------------------------------
+	// Check that a one-based index refers to a constant pool entry of 
+	// the expected type. This is not verified when loading the class, 
+	// so malformed bytecode could otherwise trip resolution.
+	fn check_entry(&self, oneb_index : uint, what : &str, pred : |&Constant| -> bool) -> 
+		Result<(), ClassLoadError> 
+	{
+		let constants = self.jclass.get().get_constants();
+		if oneb_index == 0 || oneb_index > constants.len() || !pred(&constants[oneb_index - 1]) {
+			return Err(ClassLoadError::new_format_error(format!(
+				"constant pool entry {} is not a {}", oneb_index, what))
+				.with_class(self.jclass.get().get_name().as_slice()));
+		}
+		Ok(())
+	}
+
+
+	// ----------------------------------------------
+	fn do_resolve_class(&mut self, loader : &mut AbstractClassLoader, oneb_index : uint) -> 
+		Result<JavaClassRef, ClassLoadError> 
+	{
+		let name = match ClassLoader::resolve_class_cpool_entry(
+			self.jclass.get().get_constants().as_slice(), oneb_index) {
+			Err(s) => return Err(ClassLoadError::new_format_error(s)),
+			Ok(name) => name
+		};
+
+		// a class may always refer to itself
+		if name == *self.jclass.get().get_name() {
+			return Ok(self.jclass.clone());
+		}
+
+		let jclass = match loader.load(name.as_slice()).await() {
+			Err(e) => return Err(e),
+			Ok(c) => c
+		};
+		if !self.is_class_accessible(jclass.get()) {
+			return Err(ClassLoadError::new(CLE_IllegalAccessError, format!(
+				"tried to access class {} from class {}", name, *self.jclass.get().get_name())));
+		}
+		Ok(jclass)
+	}
+
+
+	// ----------------------------------------------
+	fn do_resolve_field(&mut self, loader : &mut AbstractClassLoader, oneb_index : uint) -> 
+		Result<ResolvedField, ClassLoadError> 
+	{
+		let (jclass, name, desc) = match self.resolve_member_class(loader, oneb_index) {
+			Err(e) => return Err(e),
+			Ok(res) => res
+		};
+
+		let (decl_name, access) = match jclass.get().lookup_field(name.as_slice(), desc.as_slice()) {
+			None => return Err(ClassLoadError::new(CLE_NoSuchFieldError, 
+				format!("{}.{}", *jclass.get().get_name(), name))),
+			Some((decl, f)) => (decl.get_name().clone(), f.get_access())
+		};
+
+		let decl = JavaClass::find_class_ref(&jclass, decl_name.as_slice()).unwrap();
+		if !self.is_member_accessible(decl.get(), access) {
+			return Err(ClassLoadError::new(CLE_IllegalAccessError, format!(
+				"tried to access field {}.{} from class {}", decl_name, name, 
+				*self.jclass.get().get_name())));
+		}
+
+		let is_static = (access & ACC_STATIC) != 0;
+		let slot = match is_static {
+			true => decl.get().get_static_layout().find(name.as_slice(), desc.as_slice()),
+			false => decl.get().get_instance_layout().find_declared(decl_name.as_slice(), 
+				name.as_slice(), desc.as_slice())
+		}.unwrap().clone();
+
+		Ok(ResolvedField {
+			declaring_class : decl.clone(),
+			slot : slot,
+			is_static : is_static,
+		})
+	}
+
+
+	// ----------------------------------------------
+	fn do_resolve_method(&mut self, loader : &mut AbstractClassLoader, oneb_index : uint) -> 
+		Result<ResolvedMethod, ClassLoadError> 
+	{
+		let is_interface_ref = match self.jclass.get().get_constants()[oneb_index - 1] {
+			CONSTANT_ifacemethodref_info(_, _) => true,
+			_ => false
+		};
+
+		let (jclass, name, desc) = match self.resolve_member_class(loader, oneb_index) {
+			Err(e) => return Err(e),
+			Ok(res) => res
+		};
+
+		if is_interface_ref != jclass.get().is_interface() {
+			return Err(ClassLoadError::new(CLE_IncompatibleClassChangeError, format!(
+				"found {} {}, but {} was expected", 
+				if is_interface_ref { "class" } else { "interface" }, *jclass.get().get_name(),
+				if is_interface_ref { "interface" } else { "class" })));
+		}
+
+		let found = match is_interface_ref {
+			true => jclass.get().lookup_interface_method(name.as_slice(), desc.as_slice()),
+			false => jclass.get().lookup_method(name.as_slice(), desc.as_slice())
+		}.map(|(decl, m)| (decl.get_name().clone(), m.get_access()));
+
+		let (decl, access) = match found {
+			Some((decl_name, access)) => 
+				(JavaClass::find_class_ref(&jclass, decl_name.as_slice()).unwrap(), access),
+			None if is_interface_ref => {
+				// interface method resolution falls back to the public 
+				// instance methods of java.lang.Object
+				let object = match loader.load("java.lang.Object").await() {
+					Err(e) => return Err(e),
+					Ok(c) => c
+				};
+				let access = object.get().find_method(name.as_slice(), desc.as_slice()).map(|m| m.get_access());
+				match access {
+					Some(a) if (a & ACC_PUBLIC) != 0 && (a & ACC_STATIC) == 0 => (object, a),
+					_ => return Err(ClassLoadError::new(CLE_NoSuchMethodError, 
+						format!("{}.{}{}", *jclass.get().get_name(), name, desc)))
+				}
+			},
+			None => return Err(ClassLoadError::new(CLE_NoSuchMethodError, 
+				format!("{}.{}{}", *jclass.get().get_name(), name, desc)))
+		};
+
+		if !self.is_member_accessible(decl.get(), access) {
+			return Err(ClassLoadError::new(CLE_IllegalAccessError, format!(
+				"tried to access method {}.{}{} from class {}", *decl.get().get_name(), name, desc,
+				*self.jclass.get().get_name())));
+		}
+
+		let index = decl.get().find_method_index(name.as_slice(), desc.as_slice()).unwrap();
+		Ok(ResolvedMethod {
+			referenced_class : jclass,
+			declaring_class : decl,
+			index : index,
+		})
+	}
+
+
+	// ----------------------------------------------
+	// Resolve the class named by a Fieldref, Methodref or InterfaceMethodref
+	// entry and get the name and descriptor of the member.
+	fn resolve_member_class(&mut self, loader : &mut AbstractClassLoader, oneb_index : uint) -> 
+		Result<(JavaClassRef, ~str, ~str), ClassLoadError> 
+	{
+		let class_index = match self.jclass.get().get_constants()[oneb_index - 1] {
+			CONSTANT_fieldref_info(c, _) | CONSTANT_methodref_info(c, _) | 
+			CONSTANT_ifacemethodref_info(c, _) => c as uint,
+			_ => fail!("logic error, not a member reference")
+		};
+		let (_, name, desc) = match ClassLoader::resolve_member_cpool_entry(
+			self.jclass.get().get_constants().as_slice(), oneb_index) {
+			Err(s) => return Err(ClassLoadError::new_format_error(s)),
+			Ok(res) => res
+		};
+
+		// failure to resolve the class is cached for the class entry 
+		// as well as for the member reference.
+		match self.resolve_class(loader, class_index) {
+			Err(e) => Err(e),
+			Ok(c) => Ok((c, name, desc))
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Check if the class owning the constant pool may access a class
+	// as per JVMS 5.4.4.
+	fn is_class_accessible(&self, jclass : &JavaClass) -> bool {
+		(jclass.get_access() & ACC_PUBLIC) != 0 || jclass.is_same_package(self.jclass.get())
+	}
+
+
+	// ----------------------------------------------
+	// Check if the class owning the constant pool may access a field or
+	// method with the given access flags declared by `decl` as per
+	// JVMS 5.4.4.
+	fn is_member_accessible(&self, decl : &JavaClass, access : uint) -> bool {
+		let cur = self.jclass.get();
+		if (access & ACC_PUBLIC) != 0 {
+			return true;
+		}
+		if (access & ACC_PRIVATE) != 0 {
+			return decl.get_name() == cur.get_name();
+		}
+		if decl.is_same_package(cur) {
+			return true;
+		}
+		(access & ACC_PROTECTED) != 0 && cur.is_subclass_of(decl.get_name().as_slice())
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use rtpool::*;
+	use classloader::{AbstractClassLoader};
+	use classloader::tests::{test_get_real_classloader};
+	use classloaderror::*;
+	use layout::{SK_Reference, SK_Long, SK_Int};
+	use util::{assert_no_err};
+
+	#[test]
+	fn test_runtime_constant_pool_resolution() {
+		let mut cl = test_get_real_classloader();
+		let v = cl.add_from_classfile("ResolveRefs").await();
+		assert_no_err(&v);
+
+		let mut pool = RuntimeConstantPool::new(v.unwrap());
+		let loader : &mut AbstractClassLoader = &mut cl;
+
+		// see javap -v ResolveRefs.class for the constant pool indices
+		let c = pool.resolve_class(loader, 8).unwrap();
+		assert_eq!(*c.get().get_name(), ~"HierarchyImpl");
+
+		// fields are found in super classes
+		let f = pool.resolve_field(loader, 7).unwrap();
+		assert_eq!(*f.get_class().get().get_name(), ~"LayoutDerived");
+		assert_eq!(f.get_kind(), SK_Reference);
+		assert!(!f.is_static());
+		let f2 = pool.resolve_field(loader, 13).unwrap();
+		assert_eq!(*f2.get_class().get().get_name(), ~"LayoutBase");
+		assert_eq!(f2.get_kind(), SK_Long);
+		let f3 = pool.resolve_field(loader, 17).unwrap();
+		assert_eq!(f3.get_kind(), SK_Int);
+		assert!(f3.is_static());
+
+		// class and interface methods
+		let m = pool.resolve_method(loader, 23).unwrap();
+		assert_eq!(*m.get_method().get_name(), ~"bar");
+		assert_eq!(*m.get_class().get().get_name(), ~"HierarchyImpl");
+		let m2 = pool.resolve_method(loader, 26).unwrap();
+		assert_eq!(*m2.get_class().get().get_name(), ~"TestInterface");
+		assert_eq!(*m2.get_referenced_class().get().get_name(), ~"SubInterface");
+
+		// the entry type is checked
+		assert_eq!(pool.resolve_field(loader, 8).unwrap_err().get_kind(), CLE_ClassFormatError);
+
+		// failures are cached and reported for the class entry as well
+		let e = pool.resolve_method(loader, 32).unwrap_err();
+		assert_eq!(e.get_kind(), CLE_NoClassDefFoundError);
+		assert_eq!(pool.resolve_method(loader, 32).unwrap_err().get_kind(), CLE_NoClassDefFoundError);
+		assert_eq!(pool.resolve_class(loader, 33).unwrap_err().get_kind(), CLE_NoClassDefFoundError);
+	}
+}
@@ -0,0 +1,130 @@
+// rustyVM - Java VM written in pure Rust
+// Copyright (c) 2013 Alexander Gessler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of
+// this software and associated documentation files (the "Software"), to deal in
+// the Software without restriction, including without limitation the rights to
+// use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies of
+// the Software, and to permit persons to whom the Software is furnished to do so,
+// subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS
+// FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER
+// IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+//
+
+
+// Global table of interned java.lang.String instances, see
+// http://docs.oracle.com/javase/specs/jvms/se7/html/jvms-5.html#jvms-5.1
+//
+// String literals with the same contents denote the same String 
+// instance, regardless of the class they appear in or the thread that
+// first loads them. Like class initialization state (see classinit.rs),
+// the table lives in the broker task and threads consult it with
+// STRING_INTERN_REQUEST messages.
+//
+// The table holds only oids, the String objects themselves are subject
+// to the same ownership protocol as all other objects. The thread that
+// creates a String offers it to the table, which keeps the first String
+// offered for a given value and hands it out to all later requesters.
+// The table holds a reference on every String in it, so interned 
+// Strings are never collected.
+
+use std::hashmap::{HashMap};
+
+use object::{JavaObjectId};
+
+
+// OB_STRING_OP() detail messages. Strings are identified by their 
+// UTF-16 contents as Java strings need not be valid unicode.
+pub enum StringOpMessage {
+	// thread a asks for the interned String with contents b, offering
+	// the given String object in case the contents are not interned yet. 
+	// The requesting thread must own a reference to the object, which 
+	// is passed on to the table if it accepts the object.
+	STRING_INTERN_REQUEST(JavaObjectId),
+
+	// sent by the broker in response to STRING_INTERN_REQUEST: the 
+	// interned String with contents b is the given object. If it is not
+	// the object offered, the requester still owns its reference to it.
+	STRING_INTERN_DONE(JavaObjectId),
+}
+
+
+pub struct StringInternManager {
+	priv strings : HashMap<~[u16], JavaObjectId>,
+}
+
+
+impl StringInternManager {
+
+	// ----------------------------------------------
+	pub fn new() -> StringInternManager {
+		StringInternManager {
+			strings : HashMap::new(),
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Lookup the interned String with the given contents
+	pub fn find(&self, value : &[u16]) -> Option<JavaObjectId> {
+		match self.strings.find(&value.to_owned()) {
+			Some(oid) => Some(*oid),
+			None => None
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Process a message pertaining to the String with contents `value`
+	// and return the response to be sent to the requesting thread.
+	pub fn process_message(&mut self, value : &[u16], op : StringOpMessage) -> StringOpMessage {
+		match op {
+			STRING_INTERN_REQUEST(offered) => {
+				let oid = self.strings.find_or_insert(value.to_owned(), offered);
+				STRING_INTERN_DONE(*oid)
+			},
+			STRING_INTERN_DONE(_) => fail!("logic error, message only sent by broker"),
+		}
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use stringintern::*;
+
+	#[test]
+	fn test_string_intern_protocol() {
+		let mut m = StringInternManager::new();
+		let foo = "foo".to_utf16();
+		assert!(m.find(foo).is_none());
+
+		// the first String offered is kept
+		match m.process_message(foo, STRING_INTERN_REQUEST(42)) {
+			STRING_INTERN_DONE(42) => (),
+			_ => fail!("expected DONE(42)")
+		}
+		match m.process_message(foo, STRING_INTERN_REQUEST(43)) {
+			STRING_INTERN_DONE(42) => (),
+			_ => fail!("expected DONE(42)")
+		}
+		assert_eq!(m.find(foo), Some(42));
+
+		// contents are compared as UTF-16 code units, which need
+		// not form valid unicode
+		let bar = ~[0xD800u16];
+		match m.process_message(bar, STRING_INTERN_REQUEST(44)) {
+			STRING_INTERN_DONE(44) => (),
+			_ => fail!("expected DONE(44)")
+		}
+		assert!(m.find(~[0xD801u16]).is_none());
+	}
+}
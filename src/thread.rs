@@ -27,24 +27,101 @@ use std::sync::atomics::{atomic_add, AcqRel};
 
 use std::hashmap::{HashMap};
 
+use std::{cast, vec, io, str, i32, i64};
+
 use std::task::{task};
 
+use std::io::timer;
+
 use objectbroker::*;
 
 use localheap::{LocalHeap, JavaStrongObjectRef};
 
 use classloader::{AbstractClassLoader};
 
-use object::{JavaObjectId};
+use object::{JavaObject, JavaObjectId, JAVA_NULL_OID};
+
+use class::{JavaClass, JavaClassRef};
+
+use classloaderror::{ClassLoadError};
+
+use rtpool::{RuntimeConstantPool, ResolvedField, ResolvedMethod};
+
+use dispatch::{DT_Method, DT_Abstract, DT_Conflict};
+
+use classinit::{CLASS_INIT_REQUEST, CLASS_INIT_PROCEED, CLASS_INIT_DONE, 
+	CLASS_INIT_ERRONEOUS, CLASS_INIT_COMPLETE, CLASS_INIT_FAILED, CLASS_MIRROR_REQUEST, 
+	CLASS_MIRROR_DONE};
+
+use stringintern::{STRING_INTERN_REQUEST, STRING_INTERN_DONE};
 
+use threadmanager::{THREAD_INTERRUPT, THREAD_WAKEUP};
 
+use natives::{NativeRegistry, NativeMethod, JavaValue};
 
-// A FrameInfo represents 
+use field::{CV_Integer, CV_Float, CV_Long, CV_Double, CV_String};
+
+use layout::{SlotKind, SK_Int, SK_Float, SK_Long, SK_Double, SK_Reference};
+
+use def::{ACC_SUPER, ACC_ABSTRACT, ACC_PUBLIC, ACC_PRIVATE};
+
+use def::{CONSTANT_integer_info, CONSTANT_float_info, CONSTANT_long_info, CONSTANT_double_info,
+	CONSTANT_string_info, CONSTANT_utf8_info, CONSTANT_class_info};
+
+use opcode::*;
+
+
+
+// A FrameInfo represents one activation of a method. The local variables
+// and the operand stack of all frames of a thread are kept in two flat
+// arrays, `locals` and `opstack`, each frame owning the part of them 
+// that starts at its base index.
 pub struct FrameInfo {
-	// not necessaryily up-to-date for top of stack
+	// class declaring the method and index of the method in it
+	jclass : JavaClassRef,
+	method : uint,
+
+	// runtime constant pool of the class, an index into constant_pools
+	pool : uint,
+
+	// index of the next instruction to execute
 	pc : uint,
-	pc_opstack : uint,
-	pc_locals : uint
+
+	// first local variable of the frame in `locals` and first
+	// operand stack slot of the frame in `opstack`
+	locals_base : uint,
+	opstack_base : uint,
+
+	// maximum depth of the operand stack and number of local
+	// variables of the frame
+	max_stack : uint,
+	max_locals : uint,
+
+	// for synchronized methods, the object whose monitor the
+	// method holds. It is released when the frame is left.
+	monitor : Option<JavaObjectId>,
+}
+
+
+// An exception that has been thrown but not handled yet. Along with
+// the exception object, this keeps the class and detail message of the
+// exception and the stack trace at the point where it was thrown.
+#[deriving(Clone)]
+pub struct PendingException {
+	class_name : ~str,
+	message : Option<~str>,
+
+	// exception object, or JAVA_NULL_OID if the exception class could
+	// not be loaded. For exceptions raised by the VM, the constructor 
+	// of the object is not run.
+	oid : JavaObjectId,
+	jclass : Option<JavaClassRef>,
+
+	// one Class.method(Source.java:line) entry per frame, innermost first
+	stack_trace : ~[~str],
+
+	// exception that caused this one, if known
+	cause : Option<~PendingException>,
 }
 
 
@@ -64,30 +141,92 @@ pub struct ThreadContext {
 	priv broker_port : Port<ObjectBrokerMessage>,
 	priv broker_chan : SharedChan<ObjectBrokerMessage>,
 
-	priv opstack : ~[u32],
-	priv locals : ~[u32],
+	// local variables and operand stacks of all frames. Every slot holds 
+	// one int, float or reference. long and double values take two slots,
+	// the first of which holds the value while the second is unused.
+	//
+	// References held in slots do not count towards the reference count
+	// of the objects they refer to.
+	priv opstack : ~[u64],
+	priv locals : ~[u64],
 
 	priv frames : ~[FrameInfo],
 
+	// exception thrown by the current instruction
+	priv pending_exception : Option<PendingException>,
+
+	// runtime constant pools of all classes this thread executed code
+	// of, and an index to look them up by class name. Symbolic references
+	// are resolved per thread, so this needs no synchronization.
+	priv constant_pools : ~[RuntimeConstantPool],
+	priv constant_pool_index : HashMap<~str, uint>,
+
+	// classes known to be initialized, mapped to the oid of the object
+	// holding their static fields. This caches the answers of the 
+	// object broker, which keeps the global initialization state.
+	priv initialized_classes : HashMap<~str, JavaObjectId>,
+
+	// classes whose initializer is currently being run by this thread
+	priv initializing_classes : HashMap<~str, JavaObjectId>,
+
+	// java.lang.Class objects known to this thread, keyed by class
+	// name. The object broker keeps the Class objects of all classes, 
+	// see classinit.rs.
+	priv class_objects : HashMap<~str, JavaObjectId>,
+
+	// interned Strings known to this thread, keyed by their UTF-16
+	// contents. The table of all interned Strings is kept by the 
+	// object broker, see stringintern.rs.
+	priv interned_strings : HashMap<~[u16], JavaObjectId>,
+
+	// java.lang.Thread object representing this thread, if any. Like
+	// HotSpot does with its native thread, the `eetop` field of the
+	// object holds the tid of the thread.
+	priv thread_object : JavaObjectId,
+
+	// interrupt status, set by Thread.interrupt()
+	priv interrupted : bool,
+
+	// counts the timed wait()s of this thread, so wakeups for
+	// earlier wait()s can be told apart.
+	priv wait_sequence : uint,
+
+	// implementations of the native methods this thread can call,
+	// see natives.rs
+	priv natives : NativeRegistry,
+
 	// marker variable to indicate that, during processing
 	// of the current bytecode instruction, a message was
 	// received that indicated that the VM is shutting
 	// down.
 	priv vm_was_shutdown : bool,
 
+	// set if the current instruction tried to take more values off 
+	// the operand stack than its frame has, or accessed a local 
+	// variable the frame does not have. Holds the VerifyError message.
+	priv verify_error : Option<&'static str>,
+
 	// startup context for the thread. 
 	priv startup_class : ~str,
 	priv startup_method : ~str,
+	priv startup_desc : ~str,
 	priv startup_object : Option<JavaStrongObjectRef>
 }
 
 	// Thread ids start at 1 as 0 is reserved for the VM
 static mut ThreadContextIdCounter : uint = 1;
 
+	// Maximum number of frames on the stack of a thread before 
+	// StackOverflowError is raised
+static MAX_STACK_DEPTH : uint = 2048;
+
 impl ThreadContext {
 
 	// ----------------------------------------------
-	pub fn new(classloader : ~AbstractClassLoader, broker_chan : SharedChan<ObjectBrokerMessage>) -> ThreadContext 
+	//
+	// The context is boxed as its LocalHeap keeps a pointer to it, so
+	// it must not be moved.
+	pub fn new(classloader : ~AbstractClassLoader, broker_chan : SharedChan<ObjectBrokerMessage>) -> ~ThreadContext 
 	{
 		// generate an unique thread id
 		let id = unsafe {
@@ -98,7 +237,7 @@ impl ThreadContext {
 		let (port, chan) = Chan::new();
 		broker_chan.send(OB_REGISTER(id, chan));
 
-		let mut t = ThreadContext {
+		let mut t = ~ThreadContext {
 			classloader : classloader,
 			tid : id,
 
@@ -109,15 +248,31 @@ impl ThreadContext {
 			opstack : ~[],
 			locals : ~[],
 			frames : ~[],
+			pending_exception : None,
+
+			constant_pools : ~[],
+			constant_pool_index : HashMap::new(),
+
+			initialized_classes : HashMap::new(),
+			initializing_classes : HashMap::new(),
+			class_objects : HashMap::new(),
+			interned_strings : HashMap::new(),
+
+			thread_object : JAVA_NULL_OID,
+			interrupted : false,
+			wait_sequence : 0,
+			natives : NativeRegistry::new_with_builtins(),
 
 			vm_was_shutdown : false,
+			verify_error : None,
 
 			startup_class : ~"",
 			startup_method : ~"",
+			startup_desc : ~"",
 			startup_object : None,
 		};
 
-		t.heap = unsafe { LocalHeap::new_with_owner(&mut t) };
+		t.heap = unsafe { LocalHeap::new_with_owner(&mut *t) };
 		t
 	}
 
@@ -134,10 +289,13 @@ impl ThreadContext {
 	// Set the context in which the java thread executes. This context
 	// is not verified until the thread executes. If an object is
 	// specified, it is required to exist though and its class type must
-	// match the class specified.
-	pub fn set_context(&mut self, class : &str, method : &str, obj : Option<JavaObjectId>) {
+	// match the class specified. The method is identified by its name
+	// and descriptor, i.e. "main" and "([Ljava/lang/String;)V" for the
+	// main method of a program.
+	pub fn set_context(&mut self, class : &str, method : &str, desc : &str, obj : Option<JavaObjectId>) {
 		self.startup_class = class.into_owned();
 		self.startup_method = method.into_owned();
+		self.startup_desc = desc.into_owned();
 
 		self.startup_object = match obj {
 			None => None,
@@ -146,6 +304,65 @@ impl ThreadContext {
 	}
 
 
+	// ----------------------------------------------
+	// Register a native method for this thread, in addition to the 
+	// natives implemented by the VM (see natives.rs). This is how host
+	// integrations provide their natives.
+	pub fn register_native(&mut self, class : &str, name : &str, desc : &str, f : NativeMethod) {
+		self.natives.register(class, name, desc, f);
+	}
+
+
+	// ----------------------------------------------
+	// Get the java.lang.Thread object representing this thread, or
+	// JAVA_NULL_OID if there is none.
+	#[inline]
+	pub fn get_thread_object(&self) -> JavaObjectId {
+		self.thread_object
+	}
+
+
+	// ----------------------------------------------
+	// Set the java.lang.Thread object representing this thread. 
+	// Its `eetop` field is set to the tid of the thread, which
+	// allows other threads to interrupt it.
+	pub fn set_thread_object(&mut self, oid : JavaObjectId) {
+		let tid = self.tid;
+		self.thread_object = oid;
+		self.heap.access_object(OBJECT_ACCESS_Normal, oid, |obj| {
+			let jclass = obj.get_class();
+			match jclass.get().get_instance_layout().find_by_name("eetop") {
+				Some(slot) => obj.set_long(slot.get_offset(), tid as i64),
+				None => ()
+			}
+		});
+	}
+
+
+	// ----------------------------------------------
+	// Interrupt the thread with the given tid, as by Thread.interrupt().
+	// If the thread is blocked in wait(), it is woken and raises
+	// InterruptedException. Otherwise its interrupt status is set.
+	pub fn interrupt_thread(&mut self, tid : uint) {
+		if tid == self.tid {
+			self.interrupted = true;
+			return;
+		}
+		self.send_message(OB_THREAD_REMOTE_OP(self.tid, tid, THREAD_INTERRUPT));
+	}
+
+
+	// ----------------------------------------------
+	// Check the interrupt status of the thread, optionally clearing it
+	pub fn is_interrupted(&mut self, clear : bool) -> bool {
+		let interrupted = self.interrupted;
+		if clear {
+			self.interrupted = false;
+		}
+		interrupted
+	}
+
+
 	// ----------------------------------------------
 	// Handle incoming messages from ObjectBroker until a message
 	// satifies the given predicate. Messages are processed after
@@ -172,6 +389,20 @@ impl ThreadContext {
 	}
 
 
+	// ----------------------------------------------
+	// Handle all messages from ObjectBroker that are currently pending, 
+	// without blocking. This is done between instructions so threads 
+	// get to own objects that this thread owns but does not use.
+	pub fn handle_pending_messages(&mut self) {
+		loop {
+			match self.broker_port.try_recv() {
+				Some(msg) => self.handle_message(msg),
+				None => break
+			}
+		}
+	}
+
+
 	// ----------------------------------------------
 	// Sends a message to another thread via ObjectBroker, does 
 	// not block.
@@ -181,9 +412,196 @@ impl ThreadContext {
 
 
 	// ----------------------------------------------
-	pub fn die_exception(self, exception_type : &str, opt_message : Option<&str>)
+	// Shut down the VM with the given exit code, as System.exit() does. 
+	// The broker stops all threads, including this one. Blocks until 
+	// the shutdown reaches this thread, which then unwinds with a 
+	// ThreadDeath (see run_method()).
+	pub fn halt(&mut self, exit_code : int) {
+		self.send_message(OB_SHUTDOWN(self.tid, exit_code));
+		self.handle_messages_until(|msg : &ObjectBrokerMessage| {
+			match *msg {
+				OB_SHUTDOWN(_, _) => true,
+				_ => false
+			}
+		});
+	}
+
+
+	// ----------------------------------------------
+	// Ensure that a class is initialized, running its static initializer 
+	// if this did not happen yet. This implements the initialization
+	// procedure from JVMS 5.5 and must be called prior to every active 
+	// use of a class, i.e. by the new, getstatic, putstatic and 
+	// invokestatic instructions.
+	//
+	// If another thread is initializing the class, this blocks until it
+	// is done. Recursive requests made while this thread initializes
+	// the class return immediately.
+	//
+	// Returns the oid of the object holding the static fields of the
+	// class, or the exception to raise if the class could not be 
+	// initialized.
+	pub fn initialize_class(&mut self, jclass : &JavaClassRef) -> Result<JavaObjectId, PendingException> {
+		let name = jclass.get().get_name().clone();
+		match self.initialized_classes.find(&name) {
+			Some(oid) => return Ok(*oid),
+			None => ()
+		}
+		match self.initializing_classes.find(&name) {
+			Some(oid) => return Ok(*oid),
+			None => ()
+		}
+
+		self.send_message(OB_CLASS_OP(self.tid, name.clone(), CLASS_INIT_REQUEST));
+
+		// Some(oid) if initialized by another thread, None if erroneous
+		let mut outcome : Option<Option<JavaObjectId>> = None;
+		if !self.handle_messages_until(|msg : &ObjectBrokerMessage| {
+			match *msg {
+				OB_CLASS_OP(_, ref n, CLASS_INIT_PROCEED) if *n == name => true,
+				OB_CLASS_OP(_, ref n, CLASS_INIT_DONE(oid)) if *n == name => {
+					outcome = Some(Some(oid));
+					true
+				},
+				OB_CLASS_OP(_, ref n, CLASS_INIT_ERRONEOUS) if *n == name => {
+					outcome = Some(None);
+					true
+				},
+				_ => false
+			}
+		}) {
+			// VM shutdown, the thread is going to terminate anyway
+			return Err(self.new_exception("java.lang.ThreadDeath", None));
+		}
+
+		match outcome {
+			Some(Some(oid)) => {
+				self.initialized_classes.insert(name, oid);
+				return Ok(oid);
+			},
+			Some(None) => {
+				let msg = format!("Could not initialize class {}", name);
+				return Err(self.new_exception("java.lang.NoClassDefFoundError", Some(msg.as_slice())));
+			},
+			None => ()
+		}
+
+		// we are in charge of initializing the class. Static fields
+		// with a ConstantValue get their values first. 
+		let oid = self.heap.new_class_statics(jclass.clone());
+		self.initializing_classes.insert(name.clone(), oid);
+		let mut res = match self.init_constant_statics(jclass, oid) {
+			Err(name) => Err(self.new_exception(name, None)),
+			Ok(_) => Ok(oid)
+		};
+
+		// the super class is initialized before the class itself
+		let superclass = jclass.get().get_superclass().map(|c| c.clone());
+		if res.is_ok() {
+			res = match superclass {
+				Some(ref c) => self.initialize_class(c).map(|_| oid),
+				None => Ok(oid)
+			};
+		}
+
+		if res.is_ok() {
+			res = self.run_class_initializer(jclass).map(|_| oid);
+		}
+
+		self.initializing_classes.remove(&name);
+		match res {
+			Ok(_) => {
+				self.send_message(OB_CLASS_OP(self.tid, name.clone(), CLASS_INIT_COMPLETE(oid)));
+				self.initialized_classes.insert(name, oid);
+			},
+			Err(_) => {
+				self.send_message(OB_CLASS_OP(self.tid, name.clone(), CLASS_INIT_FAILED));
+				self.heap.release(oid);
+			}
+		}
+		res
+	}
+
+
+	// ----------------------------------------------
+	// Run a method to completion, nesting a new interpreter loop on top 
+	// of the frames that are currently executing. This is used to run
+	// the startup method of the thread and class initializers.
+	//
+	// `args` holds the argument slots, starting with `this` for instance
+	// methods, in the same format as they are kept on the operand stack.
+	// Returns the slots of the return value (none for void methods), or
+	// the exception by which the method completed abruptly.
+	pub fn run_method(&mut self, jclass : &JavaClassRef, method : uint, args : &[u64]) -> 
+		Result<~[u64], PendingException> 
+	{
+		let depth = self.frames.len();
+		let base = self.opstack.len();
+		self.opstack.push_all(args);
+		self.invoke(jclass, method);
+
+		loop {
+			if self.frames.len() > depth && self.pending_exception.is_none() {
+				self.op();
+				self.check_operand_stack();
+				self.handle_pending_messages();
+			}
+
+			if self.vm_was_shutdown {
+				self.throw_exception("java.lang.ThreadDeath", None);
+			}
+
+			// unless the VM is shutting down, exceptions are passed on to
+			// the first handler that catches them in any of the frames of
+			// this invocation.
+			if self.pending_exception.is_some() && !self.vm_was_shutdown && 
+				self.find_exception_handler(depth) {
+				continue;
+			}
+			if self.pending_exception.is_some() {
+				if self.frames.len() > depth {
+					for i in range(depth, self.frames.len()) {
+						self.release_frame_monitor(i);
+					}
+					let locals_base = self.frames[depth].locals_base;
+					self.frames.truncate(depth);
+					self.locals.truncate(locals_base);
+				}
+				self.opstack.truncate(base);
+				return Err(self.pending_exception.take().unwrap());
+			}
+			if self.frames.len() == depth {
+				break;
+			}
+		}
+
+		let ret = self.opstack.slice_from(base).to_owned();
+		self.opstack.truncate(base);
+		Ok(ret)
+	}
+
+
+	// ----------------------------------------------
+	// Terminate the thread because of an exception that is not caught
+	// by Java code, printing a report to stderr.
+	pub fn die_exception(&mut self, exception_type : &str, opt_message : Option<&str>)
+	{
+		self.die_uncaught_exception(&PendingException::new(exception_type, opt_message));
+	}
+
+
+	// ----------------------------------------------
+	pub fn die_uncaught_exception(&mut self, e : &PendingException)
 	{
-		// TODO
+		// Java threads have no names yet, so mimic the name of the 
+		// main thread for threads running a main method.
+		let thread_name = if self.startup_method.as_slice() == "main" {
+			~"main"
+		}
+		else {
+			format!("Thread-{}", self.tid)
+		};
+		io::stderr().write_str(e.get_report(thread_name.as_slice()).as_slice());
 	}
 
 
@@ -191,43 +609,82 @@ impl ThreadContext {
 	// Execute the context concurrently. This transfers ownership
 	// of the context into a separate task and yields a communication
 	// channel for other threads to interrupt.
-	pub fn execute(mut self) {
+	pub fn execute(~self) {
 		// important that task failure does not propagate
 		let mut tt = task();
 		tt.unwatched();
 
 		do tt.spawn {
 			let mut inner = self;
+			inner.run_startup_method();
+			inner.die();
+		}
+	}
+
+
+	// IMPL
+
+
+	// ----------------------------------------------
+	// Resolve the class and method given to set_context() and run the 
+	// method. Method arguments other than `this` are zero-initialized,
+	// command line arguments are not supported yet.
+	fn run_startup_method(&mut self) {
+		let jclass = match self.classloader.load(self.startup_class.as_slice()).await() {
+			Err(e) => {
+				self.die_exception(e.get_java_exception_name(), Some(e.get_detail_message().as_slice()));
+				return;
+			},
+			Ok(jclass) => jclass
+		};
+
+		let method = jclass.get().find_method_index(self.startup_method.as_slice(), 
+			self.startup_desc.as_slice());
+		let method = match method {
+			None => {
+				let name = format!("{}.{}{}", self.startup_class, self.startup_method, self.startup_desc);
+				self.die_exception("java.lang.NoSuchMethodError", Some(name.as_slice()));
+				return;
+			},
+			Some(idx) => idx
+		};
 
-			// first: resolve the class
-			let maybe_class = inner.classloader.load(inner.startup_class).await();
-			match maybe_class {
-				Err(msg) => {
-					inner.die_exception("java.lang.ClassNotFoundException", None);
+		let (is_static, arg_slots) = {
+			let m = jclass.get().get_method_by_index(method);
+			(m.is_static(), m.get_arg_slots())
+		};
+		let mut args = vec::from_elem(arg_slots, 0u64);
+		if is_static {
+			// invoking a static method triggers initialization (JVMS 5.5)
+			match self.initialize_class(&jclass) {
+				Err(ref e) if !self.vm_was_shutdown => {
+					self.die_uncaught_exception(e);
 					return;
 				},
-				Ok(jclass) => {
-					// resolve the method signature
-
-				}
+				Err(_) => return,
+				Ok(_) => ()
 			}
-
-			loop {
-				inner.op();
-				if inner.vm_was_shutdown {
-					break;
+		}
+		else {
+			match self.startup_object {
+				Some(ref obj) => args[0] = obj.get_oid(),
+				None => {
+					self.die_exception("java.lang.NullPointerException", None);
+					return;
 				}
 			}
-			inner.die();
 		}
-	}
-
 
-	// IMPL
+		match self.run_method(&jclass, method, args) {
+			// no report if the thread was stopped by the VM shutting down
+			Err(ref e) if !self.vm_was_shutdown => self.die_uncaught_exception(e),
+			_ => ()
+		}
+	}
 
 
 	// ----------------------------------------------
-	fn die(self) {
+	fn die(~self) {
 		// this thread dies and transfers all of its object to
 		// the ownership of the broker. We do this _even_ in the
 		// scenario that the whole VM is shutdown to enable this
@@ -260,16 +717,2879 @@ impl ThreadContext {
 				self.heap.handle_message(a,b,op),
 
 			OB_THREAD_REMOTE_OP(a, b, remote_op) => {
-				// TODO
-			}
+				match remote_op {
+					THREAD_INTERRUPT => self.interrupted = true,
+
+					// wakeups end timed wait()s, see monitor_wait()
+					THREAD_WAKEUP(_) => (),
+					_ => fail!("thread message not expected here")
+				}
+			},
+
+			// responses to CLASS_INIT_REQUEST are consumed by 
+			// initialize_class(), which blocks until it gets them.
+			OB_CLASS_OP(a, b, op) => (),
+
+			// likewise, responses to STRING_INTERN_REQUEST are
+			// consumed by intern_string()
+			OB_STRING_OP(a, b, op) => ()
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Get the index of the runtime constant pool of a class in 
+	// constant_pools, creating the pool if needed.
+	fn get_constant_pool_index(&mut self, jclass : &JavaClassRef) -> uint {
+		match self.constant_pool_index.find(jclass.get().get_name()) {
+			Some(idx) => return *idx,
+			None => ()
 		}
+		let idx = self.constant_pools.len();
+		self.constant_pools.push(RuntimeConstantPool::new(jclass.clone()));
+		self.constant_pool_index.insert(jclass.get().get_name().clone(), idx);
+		idx
 	}
 
 
 	// ----------------------------------------------
+	// Resolve the class referenced by entry `oneb_index` of the 
+	// runtime constant pool with index `pool`
 	#[inline]
-	fn op(&mut self) {
+	fn resolve_class_ref(&mut self, pool : uint, oneb_index : uint) -> 
+		Result<JavaClassRef, ClassLoadError> {
+		self.constant_pools[pool].resolve_class(&mut *self.classloader, oneb_index)
+	}
+
+
+	// ----------------------------------------------
+	#[inline]
+	fn resolve_field_ref(&mut self, pool : uint, oneb_index : uint) -> 
+		Result<ResolvedField, ClassLoadError> {
+		self.constant_pools[pool].resolve_field(&mut *self.classloader, oneb_index)
+	}
+
 
+	// ----------------------------------------------
+	#[inline]
+	fn resolve_method_ref(&mut self, pool : uint, oneb_index : uint) -> 
+		Result<ResolvedMethod, ClassLoadError> {
+		self.constant_pools[pool].resolve_method(&mut *self.classloader, oneb_index)
+	}
+
+
+	// ----------------------------------------------
+	// Set static fields that have a ConstantValue attribute to their
+	// respective value. Other static fields remain zero-initialized. 
+	// String constants are interned. Returns the name of the exception 
+	// to raise if this fails.
+	fn init_constant_statics(&mut self, jclass : &JavaClassRef, oid : JavaObjectId) -> 
+		Result<(), &'static str> 
+	{
+		let c = jclass.get();
+		let mut strings = ~[];
+		for f in c.get_static_fields().iter() {
+			match *f.get_constant_value() {
+				Some(CV_String(ref s)) => {
+					let offset = c.get_static_layout().find(f.get_name().as_slice(), 
+						f.get_type().to_str().as_slice()).unwrap().get_offset();
+					match self.intern_string(s.to_utf16(), None) {
+						Err(name) => return Err(name),
+						Ok(soid) => strings.push((offset, soid))
+					}
+				},
+				_ => ()
+			}
+		}
+
+		self.heap.access_object(OBJECT_ACCESS_Normal, oid, |obj| {
+			for f in c.get_static_fields().iter() {
+				let offset = c.get_static_layout().find(f.get_name().as_slice(), 
+					f.get_type().to_str().as_slice()).unwrap().get_offset();
+				match *f.get_constant_value() {
+					Some(CV_Integer(v)) => obj.set_int(offset, v),
+					Some(CV_Float(v)) => obj.set_float(offset, v),
+					Some(CV_Long(v)) => obj.set_long(offset, v),
+					Some(CV_Double(v)) => obj.set_double(offset, v),
+					Some(CV_String(_)) | None => ()
+				}
+			}
+			for &(offset, soid) in strings.iter() {
+				obj.set_ref(offset, soid);
+			}
+		});
+		Ok(())
+	}
+
+
+	// ----------------------------------------------
+	// Get the java.lang.Class object of a class, as used by ldc and 
+	// static synchronized methods. There is one such object per class,
+	// which the first thread to ask for it creates and hands to the 
+	// object broker. Like HotSpot's mirrors, Class objects are created 
+	// without running a constructor. Blocks until the broker answers.
+	//
+	// Returns the oid of the Class object, or the name of the exception
+	// to raise if it could not be created.
+	pub fn get_class_object(&mut self, jclass : &JavaClassRef) -> Result<JavaObjectId, &'static str> {
+		let name = jclass.get().get_name().clone();
+		match self.class_objects.find(&name) {
+			Some(oid) => return Ok(*oid),
+			None => ()
+		}
+
+		let class_class = match self.classloader.load("java.lang.Class").await() {
+			Err(_) => return Err("java.lang.NoClassDefFoundError"),
+			Ok(c) => c
+		};
+		let candidate = self.heap.new_object(class_class);
+		self.send_message(OB_CLASS_OP(self.tid, name.clone(), CLASS_MIRROR_REQUEST(candidate)));
+
+		let mut mirror = JAVA_NULL_OID;
+		if !self.handle_messages_until(|msg : &ObjectBrokerMessage| {
+			match *msg {
+				OB_CLASS_OP(_, ref n, CLASS_MIRROR_DONE(oid)) if *n == name => {
+					mirror = oid;
+					true
+				},
+				_ => false
+			}
+		}) {
+			// VM shutdown, the thread is going to terminate anyway
+			return Err("java.lang.ThreadDeath");
+		}
+
+		if mirror != candidate {
+			self.heap.release(candidate);
+		}
+		self.class_objects.insert(name, mirror);
+		Ok(mirror)
+	}
+
+
+	// ----------------------------------------------
+	// Get the interned java.lang.String with the given UTF-16 contents, 
+	// as used for String literals and String.intern() (JVMS 5.1). If
+	// there is none yet, `offered` becomes the interned String, or a new
+	// String is created if it is None. Blocks until the object broker,
+	// which keeps the table of interned Strings, answers.
+	//
+	// Returns the oid of the interned String, or the name of the 
+	// exception to raise if it could not be created.
+	pub fn intern_string(&mut self, chars : &[u16], offered : Option<JavaObjectId>) -> 
+		Result<JavaObjectId, &'static str> 
+	{
+		match self.interned_strings.find(&chars.to_owned()) {
+			Some(oid) => return Ok(*oid),
+			None => ()
+		}
+
+		// the reference we hand over to the table, if it accepts the String
+		let candidate = match offered {
+			Some(oid) => {
+				self.heap.add_ref(oid);
+				oid
+			},
+			None => match self.new_string(chars) {
+				Err(name) => return Err(name),
+				Ok(oid) => oid
+			}
+		};
+		self.send_message(OB_STRING_OP(self.tid, chars.to_owned(), STRING_INTERN_REQUEST(candidate)));
+
+		let mut interned = JAVA_NULL_OID;
+		if !self.handle_messages_until(|msg : &ObjectBrokerMessage| {
+			match *msg {
+				OB_STRING_OP(_, ref s, STRING_INTERN_DONE(oid)) if s.as_slice() == chars => {
+					interned = oid;
+					true
+				},
+				_ => false
+			}
+		}) {
+			// VM shutdown, the thread is going to terminate anyway
+			return Err("java.lang.ThreadDeath");
+		}
+
+		if interned != candidate {
+			self.heap.release(candidate);
+		}
+		self.interned_strings.insert(chars.to_owned(), interned);
+		Ok(interned)
+	}
+
+
+	// ----------------------------------------------
+	// Create a java.lang.String with the given UTF-16 contents. This
+	// supports the String layout of JDK 7 and earlier, which keeps the
+	// characters in a char[] `value` along with an optional `offset` 
+	// and `count`.
+	pub fn new_string(&mut self, chars : &[u16]) -> Result<JavaObjectId, &'static str> {
+		let string_class = match self.classloader.load("java.lang.String").await() {
+			Err(_) => return Err("java.lang.NoClassDefFoundError"),
+			Ok(c) => c
+		};
+		let array_class = match self.classloader.load("[C").await() {
+			Err(_) => return Err("java.lang.NoClassDefFoundError"),
+			Ok(c) => c
+		};
+		match string_class.get().find_instance_field("value") {
+			Some(f) if f.get_type().to_str() == ~"[C" => (),
+			_ => return Err("java.lang.InternalError")
+		}
+		match self.initialize_class(&string_class) {
+			Err(_) => return Err("java.lang.NoClassDefFoundError"),
+			Ok(_) => ()
+		}
+
+		let array = self.heap.new_array_object(array_class, chars.len());
+		self.heap.access_object(OBJECT_ACCESS_Normal, array, |obj| {
+			for (i, c) in chars.iter().enumerate() {
+				obj.array_mut().set(i, *c as u64);
+			}
+		});
+
+		let layout = string_class.get().get_instance_layout();
+		let value = layout.find_by_name("value").unwrap().get_offset();
+		let count = layout.find_by_name("count").map(|slot| slot.get_offset());
+
+		let oid = self.heap.new_object(string_class.clone());
+		self.heap.access_object(OBJECT_ACCESS_Normal, oid, |obj| {
+			obj.set_ref(value, array);
+			match count {
+				Some(offset) => obj.set_int(offset, chars.len() as i32),
+				None => ()
+			}
+		});
+		Ok(oid)
+	}
+
+
+	// ----------------------------------------------
+	// Get the UTF-16 contents of a java.lang.String, see new_string()
+	pub fn get_string_chars(&mut self, oid : JavaObjectId) -> ~[u16] {
+		let mut array = JAVA_NULL_OID;
+		let mut start = 0u;
+		let mut count = None;
+		self.heap.access_object(OBJECT_ACCESS_Normal, oid, |obj| {
+			let jclass = obj.get_class();
+			let layout = jclass.get().get_instance_layout();
+			array = obj.get_ref(layout.find_by_name("value").unwrap().get_offset());
+			match layout.find_by_name("offset") {
+				Some(slot) => start = obj.get_int(slot.get_offset()) as uint,
+				None => ()
+			}
+			match layout.find_by_name("count") {
+				Some(slot) => count = Some(obj.get_int(slot.get_offset()) as uint),
+				None => ()
+			}
+		});
+
+		let mut chars = ~[];
+		self.heap.access_object(OBJECT_ACCESS_Normal, array, |obj| {
+			let end = start + count.unwrap_or(obj.array().len() - start);
+			chars = range(start, end).map(|i| obj.array().get(i) as u16).collect();
+		});
+		chars
+	}
+
+
+	// ----------------------------------------------
+	// Run the <clinit> method of a class, if it has one. Returns false
+	// if the initializer completed abruptly by throwing an exception.
+	fn run_class_initializer(&mut self, jclass : &JavaClassRef) -> Result<(), PendingException> {
+		let idx = match jclass.get().find_method_index("<clinit>", "()V") {
+			None => return Ok(()),
+			Some(idx) => idx
+		};
+		let e = match self.run_method(jclass, idx, []) {
+			Ok(_) => return Ok(()),
+			Err(e) => e
+		};
+
+		// Errors are passed on as they are, other exceptions are wrapped
+		// in an ExceptionInInitializerError (JVMS 5.5, step 10)
+		if is_exception_instance_of(&e, "java.lang.Error") {
+			return Err(e);
+		}
+		let mut wrapper = self.new_exception("java.lang.ExceptionInInitializerError", None);
+		match wrapper.jclass {
+			Some(ref c) => {
+				// the exception is the `exception` field of the wrapper and, 
+				// with newer class libraries, also its Throwable cause.
+				let layout = c.get().get_instance_layout();
+				let fields : ~[uint] = ["exception", "cause"].iter()
+					.filter_map(|f| layout.find_by_name(*f).map(|slot| slot.get_offset())).collect();
+				let cause = e.oid;
+				self.heap.access_object(OBJECT_ACCESS_Normal, wrapper.oid, |obj| {
+					for offset in fields.iter() {
+						obj.set_ref(*offset, cause);
+					}
+				});
+			},
+			None => ()
+		}
+		wrapper.cause = Some(~e);
+		Err(wrapper)
 	}
-}
 
+
+	// ----------------------------------------------
+	// Enter a method, popping its arguments off the operand stack and
+	// making them the first local variables of the new frame.
+	fn push_frame(&mut self, jclass : &JavaClassRef, method : uint) {
+		let (arg_slots, max_locals, max_stack) = {
+			let m = jclass.get().get_method_by_index(method);
+			let code = m.get_code().unwrap();
+			(m.get_arg_slots(), code.get_max_locals(), code.get_max_stack())
+		};
+
+		let opstack_base = self.opstack.len() - arg_slots;
+		let locals_base = self.locals.len();
+		self.locals.push_all(self.opstack.slice_from(opstack_base));
+		self.opstack.truncate(opstack_base);
+		self.locals.grow(max_locals - arg_slots, &0u64);
+		self.opstack.reserve_additional(max_stack);
+
+		let pool = self.get_constant_pool_index(jclass);
+		self.frames.push(FrameInfo {
+			jclass : jclass.clone(),
+			method : method,
+			pool : pool,
+			pc : 0,
+			locals_base : locals_base,
+			opstack_base : opstack_base,
+			max_stack : max_stack,
+			max_locals : max_locals,
+			monitor : None,
+		});
+	}
+
+
+	// ----------------------------------------------
+	// Invoke a method, taking its arguments from the operand stack. For
+	// methods with bytecode, this pushes a new frame that the interpreter
+	// loop continues with, native methods are run right away (see 
+	// natives.rs). Method selection and class initialization are up to
+	// the caller.
+	fn invoke(&mut self, jclass : &JavaClassRef, method : uint) {
+		let (is_abstract, is_native, is_synchronized, is_static, arg_slots, bad_locals) = {
+			let m = jclass.get().get_method_by_index(method);
+			(m.is_abstract(), m.is_native(), m.is_synchronized(), m.is_static(), m.get_arg_slots(),
+				m.get_code().map_default(false, |c| c.get_max_locals() < m.get_arg_slots()))
+		};
+		if !self.check_operand_slots(arg_slots) {
+			return;
+		}
+		let native = if is_native {
+			let m = jclass.get().get_method_by_index(method);
+			self.natives.find(jclass.get().get_name().as_slice(), m.get_name().as_slice(), m.get_desc().as_slice())
+		}
+		else {
+			None
+		};
+		if is_abstract || (is_native && native.is_none()) {
+			let name = if is_abstract { 
+				"java.lang.AbstractMethodError" 
+			} 
+			else { 
+				"java.lang.UnsatisfiedLinkError" 
+			};
+			let message = describe_method(jclass, method);
+			self.throw_exception(name, Some(message.as_slice()));
+			return;
+		}
+		if bad_locals {
+			let message = format!("{}: max_locals too small for arguments", describe_method(jclass, method));
+			self.throw_exception("java.lang.VerifyError", Some(message.as_slice()));
+			return;
+		}
+		if self.frames.len() >= MAX_STACK_DEPTH {
+			self.throw_exception("java.lang.StackOverflowError", None);
+			return;
+		}
+
+		// synchronized methods lock `this`, static ones lock the 
+		// java.lang.Class object of the class.
+		let monitor = if is_synchronized {
+			let oid = if is_static {
+				match self.get_class_object(jclass) {
+					Err(name) => {
+						self.throw_exception(name, None);
+						return;
+					},
+					Ok(oid) => oid
+				}
+			}
+			else {
+				self.opstack[self.opstack.len() - arg_slots]
+			};
+			self.heap.lock_monitor(oid);
+			Some(oid)
+		}
+		else {
+			None
+		};
+
+		match native {
+			Some(f) => {
+				self.call_native(jclass, method, f);
+				match monitor {
+					Some(oid) => { self.heap.unlock_monitor(oid); },
+					None => ()
+				}
+			},
+			None => {
+				self.push_frame(jclass, method);
+				let top = self.frames.len() - 1;
+				self.frames[top].monitor = monitor;
+			}
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Run a native method, taking its arguments from the operand stack 
+	// and pushing its return value unless it raised an exception.
+	fn call_native(&mut self, jclass : &JavaClassRef, method : uint, native : NativeMethod) {
+		let (is_static, params, ret) = {
+			let m = jclass.get().get_method_by_index(method);
+			let params = m.get_type().get_params().iter().map(|p| SlotKind::from_type(p)).collect::<~[SlotKind]>();
+			(m.is_static(), params, m.get_type().get_return_type().as_ref().map(|r| SlotKind::from_type(r)))
+		};
+		let mut args = ~[];
+		for kind in params.iter().invert() {
+			let v = self.pop_value(*kind);
+			args.push(JavaValue::from_raw(*kind, v));
+		}
+		if !is_static {
+			args.push(JavaValue::from_raw(SK_Reference, self.pop_ref()));
+		}
+		args.reverse();
+
+		// natives get to use the heap directly, which is owned by this 
+		// thread just like LocalHeap keeps a pointer to its thread.
+		let heap = unsafe { &mut *(&mut self.heap as *mut LocalHeap) };
+		let result = native(self, heap, args);
+		if self.pending_exception.is_some() {
+			return;
+		}
+		match (ret, result) {
+			(None, None) => (),
+			(Some(kind), Some(v)) if v.get_kind() == kind => self.push_value(kind, v.to_raw()),
+			_ => {
+				let message = format!("{}: native returned a value of the wrong type", 
+					describe_method(jclass, method));
+				self.throw_exception("java.lang.InternalError", Some(message.as_slice()));
+			}
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Call a Java method from Rust code, i.e. from a native method. The
+	// method is looked up in the given class and not selected virtually.
+	// Static methods initialize their class first.
+	//
+	// Returns the return value of the method (None for void methods), or
+	// the exception it threw. Natives pass such exceptions on to their 
+	// caller by handing them to rethrow().
+	pub fn call_method(&mut self, jclass : &JavaClassRef, name : &str, desc : &str, 
+		args : &[JavaValue]) -> Result<Option<JavaValue>, PendingException> 
+	{
+		let method = match jclass.get().find_method_index(name, desc) {
+			Some(idx) => idx,
+			None => {
+				let message = format!("{}.{}{}", *jclass.get().get_name(), name, desc);
+				self.throw_exception("java.lang.NoSuchMethodError", Some(message.as_slice()));
+				return Err(self.pending_exception.take().unwrap());
+			}
+		};
+		let ret = jclass.get().get_method_by_index(method).get_type().get_return_type()
+			.as_ref().map(|r| SlotKind::from_type(r));
+		if jclass.get().get_method_by_index(method).is_static() {
+			match self.initialize_class(jclass) {
+				Err(e) => return Err(e),
+				Ok(_) => ()
+			}
+		}
+
+		let mut slots = ~[];
+		for v in args.iter() {
+			slots.push(v.to_raw());
+			if is_wide_kind(v.get_kind()) {
+				slots.push(0);
+			}
+		}
+		match self.run_method(jclass, method, slots) {
+			Err(e) => Err(e),
+			Ok(result) => Ok(ret.map(|kind| JavaValue::from_raw(kind, result[0])))
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Raise an exception that was thrown by Java code called using
+	// call_method(), passing it on to the caller of a native method.
+	pub fn rethrow(&mut self, e : PendingException) {
+		self.pending_exception = Some(e);
+	}
+
+
+	// ----------------------------------------------
+	// Check that the operand stack of the top frame does not exceed
+	// max_stack and that the last instruction neither underflowed it
+	// nor used a bad local variable index. Class files are not 
+	// verified, so malformed bytecode is only detected while running it.
+	//
+	// The latter make the results of the instruction meaningless, so 
+	// the VerifyError replaces any exception it raised.
+	#[inline]
+	fn check_operand_stack(&mut self) {
+		match self.verify_error.take() {
+			Some(message) => {
+				self.throw_exception("java.lang.VerifyError", Some(message));
+				return;
+			},
+			None => ()
+		}
+		let overflow = match self.frames.last() {
+			Some(frame) => self.opstack.len() > frame.opstack_base + frame.max_stack,
+			None => false
+		};
+		if overflow && self.pending_exception.is_none() {
+			self.throw_exception("java.lang.VerifyError", Some("operand stack overflow"));
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Check that the operand stack of the top frame holds at least
+	// `slots` values, flagging an underflow otherwise.
+	#[inline]
+	fn check_operand_slots(&mut self, slots : uint) -> bool {
+		let base = match self.frames.last() {
+			Some(frame) => frame.opstack_base,
+			None => 0
+		};
+		if self.opstack.len() < base + slots {
+			self.verify_error = Some("operand stack underflow");
+			return false;
+		}
+		true
+	}
+
+
+	// ----------------------------------------------
+	// Get the class of the receiver of an instance method invocation,
+	// which is `arg_slots` deep on the operand stack. Raises 
+	// NullPointerException and returns None if it is null.
+	fn get_receiver_class(&mut self, arg_slots : uint) -> Option<JavaClassRef> {
+		if !self.check_operand_slots(arg_slots) {
+			return None;
+		}
+		let oid = self.opstack[self.opstack.len() - arg_slots];
+		if oid == JAVA_NULL_OID {
+			self.throw_exception("java.lang.NullPointerException", None);
+			return None;
+		}
+		let mut jclass = None;
+		self.heap.access_object(OBJECT_ACCESS_Normal, oid, |obj| {
+			jclass = Some(obj.get_class());
+		});
+		jclass
+	}
+
+
+	// ----------------------------------------------
+	// Resolve a method reference for an invoke instruction, checking
+	// that the method is static or not, as the instruction requires. 
+	// Returns None if an exception was thrown.
+	fn resolve_invoked_method(&mut self, pool : uint, index : uint, want_static : bool) -> 
+		Option<ResolvedMethod> 
+	{
+		let m = match self.resolve_method_ref(pool, index) {
+			Err(e) => {
+				self.throw_linkage_error(&e);
+				return None;
+			},
+			Ok(m) => m
+		};
+		if m.get_method().is_static() != want_static {
+			let message = format!("expected {} method {}", if want_static { "static" } else { "non-static" },
+				describe_method(m.get_class(), m.get_index()));
+			self.throw_exception("java.lang.IncompatibleClassChangeError", Some(message.as_slice()));
+			return None;
+		}
+		Some(m)
+	}
+
+
+	// ----------------------------------------------
+	fn invoke_static(&mut self, pool : uint, index : uint) {
+		let m = match self.resolve_invoked_method(pool, index, true) {
+			None => return,
+			Some(m) => m
+		};
+		match self.initialize_class(m.get_class()) {
+			Err(e) => {
+				self.rethrow(e);
+				return;
+			},
+			Ok(_) => ()
+		}
+		self.invoke(m.get_class(), m.get_index());
+	}
+
+
+	// ----------------------------------------------
+	// invokespecial is used for instance initializers, private methods
+	// and calls to super class methods (JVMS 6.5). The latter select 
+	// the method starting from the direct super class of the current 
+	// class if the class has ACC_SUPER set, bypassing overrides in 
+	// the current class.
+	fn invoke_special(&mut self, pool : uint, index : uint) {
+		let m = match self.resolve_invoked_method(pool, index, false) {
+			None => return,
+			Some(m) => m
+		};
+		if self.get_receiver_class(m.get_method().get_arg_slots()).is_none() {
+			return;
+		}
+
+		let cur = self.frames[self.frames.len() - 1].jclass.clone();
+		let is_super_call = {
+			let c = cur.get();
+			let referenced = m.get_referenced_class().get();
+			m.get_method().get_name().as_slice() != "<init>" && !referenced.is_interface() &&
+				(c.get_access() & ACC_SUPER) != 0 && c.get_name() != referenced.get_name() && 
+				c.is_subclass_of(referenced.get_name().as_slice())
+		};
+		if !is_super_call {
+			self.invoke(m.get_class(), m.get_index());
+			return;
+		}
+
+		let superclass = cur.get().get_superclass().unwrap().clone();
+		let found = {
+			let method = m.get_method();
+			superclass.get().lookup_method(method.get_name().as_slice(), method.get_desc().as_slice())
+				.map(|(decl, _)| decl.get_name().clone())
+		};
+		match found {
+			Some(decl_name) => {
+				let decl = JavaClass::find_class_ref(&superclass, decl_name.as_slice()).unwrap();
+				let idx = {
+					let method = m.get_method();
+					decl.get().find_method_index(method.get_name().as_slice(), 
+						method.get_desc().as_slice()).unwrap()
+				};
+				self.invoke(&decl, idx);
+			},
+			None => {
+				let message = describe_method(m.get_class(), m.get_index());
+				self.throw_exception("java.lang.AbstractMethodError", Some(message.as_slice()));
+			}
+		}
+	}
+
+
+	// ----------------------------------------------
+	fn invoke_virtual(&mut self, pool : uint, index : uint) {
+		let m = match self.resolve_invoked_method(pool, index, false) {
+			None => return,
+			Some(m) => m
+		};
+		let recv = match self.get_receiver_class(m.get_method().get_arg_slots()) {
+			None => return,
+			Some(c) => c
+		};
+
+		// private methods are not overridden and need no selection
+		if (m.get_method().get_access() & ACC_PRIVATE) != 0 {
+			self.invoke(m.get_class(), m.get_index());
+			return;
+		}
+		match select_method(&recv, &m) {
+			Ok((decl, idx)) => self.invoke(&decl, idx),
+			Err(name) => {
+				let message = describe_method(m.get_class(), m.get_index());
+				self.throw_exception(name, Some(message.as_slice()));
+			}
+		}
+	}
+
+
+	// ----------------------------------------------
+	fn invoke_interface(&mut self, pool : uint, index : uint) {
+		let m = match self.resolve_invoked_method(pool, index, false) {
+			None => return,
+			Some(m) => m
+		};
+		if (m.get_method().get_access() & ACC_PRIVATE) != 0 {
+			let message = format!("private interface method {}", 
+				describe_method(m.get_class(), m.get_index()));
+			self.throw_exception("java.lang.IncompatibleClassChangeError", Some(message.as_slice()));
+			return;
+		}
+		let recv = match self.get_receiver_class(m.get_method().get_arg_slots()) {
+			None => return,
+			Some(c) => c
+		};
+
+		let iface = m.get_referenced_class().get().get_name().clone();
+		if !recv.get().implements_interface(iface.as_slice()) {
+			let message = format!("class {} does not implement the requested interface {}", 
+				*recv.get().get_name(), iface);
+			self.throw_exception("java.lang.IncompatibleClassChangeError", Some(message.as_slice()));
+			return;
+		}
+		match select_method(&recv, &m) {
+			Ok((decl, idx)) => {
+				if (decl.get().get_method_by_index(idx).get_access() & ACC_PUBLIC) == 0 {
+					let message = describe_method(&decl, idx);
+					self.throw_exception("java.lang.IllegalAccessError", Some(message.as_slice()));
+					return;
+				}
+				self.invoke(&decl, idx);
+			},
+			Err(name) => {
+				let message = describe_method(m.get_class(), m.get_index());
+				self.throw_exception(name, Some(message.as_slice()));
+			}
+		}
+	}
+
+
+	// ----------------------------------------------
+	fn new_object(&mut self, pool : uint, index : uint) {
+		let jclass = match self.resolve_class_ref(pool, index) {
+			Err(e) => {
+				self.throw_linkage_error(&e);
+				return;
+			},
+			Ok(c) => c
+		};
+		if jclass.get().is_interface() || (jclass.get().get_access() & ACC_ABSTRACT) != 0 {
+			let name = jclass.get().get_name().clone();
+			self.throw_exception("java.lang.InstantiationError", Some(name.as_slice()));
+			return;
+		}
+		match self.initialize_class(&jclass) {
+			Err(e) => {
+				self.rethrow(e);
+				return;
+			},
+			Ok(_) => ()
+		}
+		let oid = self.heap.new_object(jclass);
+		self.push_ref(oid);
+	}
+
+
+	// ----------------------------------------------
+	// Load a class without initializing it, as needed by the array 
+	// creation instructions (array classes have no initializer) and by
+	// native methods. Raises the corresponding linkage error and returns
+	// None if the class cannot be loaded.
+	pub fn load_class(&mut self, name : &str) -> Option<JavaClassRef> {
+		match self.classloader.load(name).await() {
+			Err(e) => {
+				self.throw_linkage_error(&e);
+				None
+			},
+			Ok(c) => Some(c)
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Pop the length of an array to be created, raising a 
+	// NegativeArraySizeException if it is negative.
+	fn pop_array_length(&mut self) -> Option<uint> {
+		let count = self.pop_int();
+		if count < 0 {
+			self.throw_exception("java.lang.NegativeArraySizeException", Some(count.to_str().as_slice()));
+			return None;
+		}
+		Some(count as uint)
+	}
+
+
+	// ----------------------------------------------
+	// newarray
+	fn new_primitive_array(&mut self, atype : u8) {
+		let name = match atype {
+			4 => "[Z",
+			5 => "[C",
+			6 => "[F",
+			7 => "[D",
+			8 => "[B",
+			9 => "[S",
+			10 => "[I",
+			11 => "[J",
+			_ => {
+				self.throw_exception("java.lang.VerifyError", Some("invalid newarray type"));
+				return;
+			}
+		};
+		let count = match self.pop_array_length() {
+			None => return,
+			Some(n) => n
+		};
+		match self.load_class(name) {
+			None => (),
+			Some(jclass) => {
+				let oid = self.heap.new_array_object(jclass, count);
+				self.push_ref(oid);
+			}
+		}
+	}
+
+
+	// ----------------------------------------------
+	// anewarray
+	fn new_reference_array(&mut self, pool : uint, index : uint) {
+		let component = match self.resolve_class_ref(pool, index) {
+			Err(e) => {
+				self.throw_linkage_error(&e);
+				return;
+			},
+			Ok(c) => c
+		};
+		let count = match self.pop_array_length() {
+			None => return,
+			Some(n) => n
+		};
+		match self.load_class(component.get().get_array_class_name()) {
+			None => (),
+			Some(jclass) => {
+				let oid = self.heap.new_array_object(jclass, count);
+				self.push_ref(oid);
+			}
+		}
+	}
+
+
+	// ----------------------------------------------
+	// multianewarray
+	fn new_multi_array(&mut self, pool : uint, index : uint, dims : uint) {
+		let jclass = match self.resolve_class_ref(pool, index) {
+			Err(e) => {
+				self.throw_linkage_error(&e);
+				return;
+			},
+			Ok(c) => c
+		};
+
+		// the count for the outermost dimension is pushed first
+		let mut counts = vec::from_elem(dims, 0i32);
+		for i in range(0, dims).invert() {
+			counts[i] = self.pop_int();
+		}
+		match counts.iter().find(|c| **c < 0) {
+			Some(c) => {
+				self.throw_exception("java.lang.NegativeArraySizeException", Some(c.to_str().as_slice()));
+				return;
+			}
+			None => ()
+		}
+		let counts = counts.map(|c| *c as uint);
+		let oid = self.alloc_multi_array(&jclass, counts);
+		self.push_ref(oid);
+	}
+
+
+	// ----------------------------------------------
+	// Allocate an array with counts[0] elements, each of which is
+	// an array of the remaining dimensions. Dimensions for which no
+	// count is given are left null.
+	fn alloc_multi_array(&mut self, jclass : &JavaClassRef, counts : &[uint]) -> JavaObjectId {
+		let oid = self.heap.new_array_object(jclass.clone(), counts[0]);
+		if counts.len() == 1 || counts[0] == 0 {
+			return oid;
+		}
+
+		// the component class was loaded along with the array class
+		let name = jclass.get().get_component_type().get_ref().get_class_name().unwrap();
+		let component = self.classloader.load(name).await().unwrap();
+		for i in range(0, counts[0]) {
+			let elem = self.alloc_multi_array(&component, counts.slice_from(1));
+			self.heap.access_object(OBJECT_ACCESS_Normal, oid, |obj| {
+				obj.array_mut().set(i, elem);
+			});
+		}
+		oid
+	}
+
+
+	// ----------------------------------------------
+	// arraylength
+	fn array_length(&mut self) {
+		let oid = self.pop_ref();
+		if oid == JAVA_NULL_OID {
+			self.throw_exception("java.lang.NullPointerException", None);
+			return;
+		}
+		let mut len = 0;
+		self.heap.access_object(OBJECT_ACCESS_Normal, oid, |obj| {
+			len = obj.array().len();
+		});
+		self.push_int(len as i32);
+	}
+
+
+	// ----------------------------------------------
+	// Pop an array reference and an index into it for one of the array
+	// load or store instructions. Raises NullPointerException or 
+	// ArrayIndexOutOfBoundsException and returns None if either is invalid.
+	fn pop_array_index(&mut self) -> Option<(JavaObjectId, uint)> {
+		let index = self.pop_int();
+		let oid = self.pop_ref();
+		if oid == JAVA_NULL_OID {
+			self.throw_exception("java.lang.NullPointerException", None);
+			return None;
+		}
+		let mut len = 0;
+		self.heap.access_object(OBJECT_ACCESS_Normal, oid, |obj| {
+			len = obj.array().len();
+		});
+		if index < 0 || index as uint >= len {
+			self.throw_exception("java.lang.ArrayIndexOutOfBoundsException", 
+				Some(index.to_str().as_slice()));
+			return None;
+		}
+		Some((oid, index as uint))
+	}
+
+
+	// ----------------------------------------------
+	// [ilfdabcs]aload. What is pushed is determined by the
+	// element type of the array.
+	fn array_load(&mut self) {
+		let (oid, index) = match self.pop_array_index() {
+			None => return,
+			Some(res) => res
+		};
+		let mut v = 0u64;
+		let mut kind = SK_Int;
+		self.heap.access_object(OBJECT_ACCESS_Normal, oid, |obj| {
+			v = obj.array().get(index);
+			kind = obj.array().get_kind();
+		});
+		self.push_value(kind, v);
+	}
+
+
+	// ----------------------------------------------
+	// [ilfdabcs]astore, `kind` is the kind of value stored by the 
+	// instruction. Values are truncated to the array's element type.
+	fn array_store(&mut self, kind : SlotKind) {
+		let v = self.pop_value(kind);
+		let (oid, index) = match self.pop_array_index() {
+			None => return,
+			Some(res) => res
+		};
+		if kind == SK_Reference && v != JAVA_NULL_OID && !self.check_array_store(oid, v) {
+			return;
+		}
+		self.heap.access_object(OBJECT_ACCESS_Normal, oid, |obj| {
+			obj.array_mut().set(index, v);
+		});
+	}
+
+
+	// ----------------------------------------------
+	// Check whether an object can be stored in an array of references,
+	// which requires it to be assignable to the array's component type
+	// (arrays are covariant). Raises ArrayStoreException if not.
+	fn check_array_store(&mut self, array : JavaObjectId, value : JavaObjectId) -> bool {
+		let mut array_class = None;
+		self.heap.access_object(OBJECT_ACCESS_Normal, array, |obj| {
+			array_class = Some(obj.get_class());
+		});
+		let mut value_class = None;
+		self.heap.access_object(OBJECT_ACCESS_Normal, value, |obj| {
+			value_class = Some(obj.get_class());
+		});
+		let (array_class, value_class) = (array_class.unwrap(), value_class.unwrap());
+
+		let target = array_class.get().get_component_type().get_ref().clone();
+		let source = value_class.get().get_type_descriptor();
+		if JavaClass::is_type_assignable(&target, &source, |name| self.classloader.load(name).await().ok()) {
+			return true;
+		}
+		self.throw_exception("java.lang.ArrayStoreException", Some(value_class.get().get_name().as_slice()));
+		false
+	}
+
+
+	// ----------------------------------------------
+	// Leave the method of the top frame, passing the topmost
+	// `ret_slots` slots of its operand stack on to the caller.
+	// Synchronized methods release their monitor, which raises 
+	// IllegalMonitorStateException if it is no longer held.
+	fn pop_frame(&mut self, ret_slots : uint) {
+		if !self.check_operand_slots(ret_slots) {
+			return;
+		}
+		let top = self.frames.len() - 1;
+		if !self.release_frame_monitor(top) {
+			self.throw_exception("java.lang.IllegalMonitorStateException", None);
+			return;
+		}
+		let frame = self.frames.pop().unwrap();
+		let top = self.opstack.len();
+		for i in range(0, ret_slots) {
+			self.opstack[frame.opstack_base + i] = self.opstack[top - ret_slots + i];
+		}
+		self.opstack.truncate(frame.opstack_base + ret_slots);
+		self.locals.truncate(frame.locals_base);
+	}
+
+
+	// ----------------------------------------------
+	// Release the monitor held by the frame with the given index, if
+	// it belongs to a synchronized method. Returns false if the thread
+	// no longer holds the monitor. Either way, the frame holds no 
+	// monitor afterwards.
+	fn release_frame_monitor(&mut self, index : uint) -> bool {
+		match self.frames[index].monitor.take() {
+			Some(oid) => self.heap.unlock_monitor(oid),
+			None => true
+		}
+	}
+
+
+	// ----------------------------------------------
+	// monitorenter, blocks until the monitor is available
+	fn monitor_enter(&mut self) {
+		let oid = self.pop_ref();
+		if oid == JAVA_NULL_OID {
+			self.throw_exception("java.lang.NullPointerException", None);
+			return;
+		}
+		self.heap.lock_monitor(oid);
+	}
+
+
+	// ----------------------------------------------
+	// monitorexit
+	fn monitor_exit(&mut self) {
+		let oid = self.pop_ref();
+		if oid == JAVA_NULL_OID {
+			self.throw_exception("java.lang.NullPointerException", None);
+			return;
+		}
+		if !self.heap.unlock_monitor(oid) {
+			self.throw_exception("java.lang.IllegalMonitorStateException", None);
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Object.wait(). Releases the monitor of the object and blocks
+	// until the thread is notified, interrupted or the timeout elapsed,
+	// a timeout of 0 meaning no timeout. Afterwards, the thread holds
+	// the monitor again, with the lock count it had before.
+	pub fn monitor_wait(&mut self, oid : JavaObjectId, millis : i64, nanos : i32) {
+		if oid == JAVA_NULL_OID {
+			self.throw_exception("java.lang.NullPointerException", None);
+			return;
+		}
+		if millis < 0 {
+			self.throw_exception("java.lang.IllegalArgumentException", 
+				Some("timeout value is negative"));
+			return;
+		}
+		if nanos < 0 || nanos > 999999 {
+			self.throw_exception("java.lang.IllegalArgumentException", 
+				Some("nanosecond timeout value out of range"));
+			return;
+		}
+		// timers have millisecond resolution, so round up as the JDK does
+		let millis = if nanos > 0 { millis + 1 } else { millis };
+
+		let tid = self.tid;
+		let interrupted = self.interrupted;
+		let mut held = false;
+		self.heap.access_object(OBJECT_ACCESS_Normal, oid, |obj| {
+			held = obj.monitor().is_locked_by_thread(tid);
+			if held && !interrupted {
+				obj.monitor_mut().wait_noblock(tid);
+			}
+		});
+		if !held {
+			self.throw_exception("java.lang.IllegalMonitorStateException", None);
+			return;
+		}
+		if interrupted {
+			self.interrupted = false;
+			self.throw_exception("java.lang.InterruptedException", None);
+			return;
+		}
+
+		self.wait_sequence += 1;
+		let sequence = self.wait_sequence;
+		if millis > 0 {
+			let chan = self.broker_chan.clone();
+			do spawn {
+				timer::sleep(millis as u64);
+				chan.send(OB_THREAD_REMOTE_OP(tid, tid, THREAD_WAKEUP(sequence)));
+			}
+		}
+
+		// once notified, the thread gets the object handed over
+		// by its owner, with the monitor already locked for it.
+		let mut notified = false;
+		if !self.handle_messages_until(|msg| {
+			match *msg {
+				OB_REMOTE_OBJECT_OP(_, b, REMOTE_DISOWN(_, _)) if b == oid => {
+					notified = true;
+					true
+				},
+				OB_THREAD_REMOTE_OP(_, _, THREAD_INTERRUPT) => true,
+				OB_THREAD_REMOTE_OP(_, _, THREAD_WAKEUP(s)) => s == sequence,
+				_ => false
+			}
+		}) {
+			return;
+		}
+		if notified {
+			return;
+		}
+
+		// interrupted or timed out. Unless the thread has been notified 
+		// in the meantime, it stops waiting and locks the monitor again.
+		let mut lock_count = None;
+		self.heap.access_object(OBJECT_ACCESS_Normal, oid, |obj| {
+			lock_count = obj.monitor_mut().remove_waiter(tid);
+		});
+		match lock_count {
+			None => return,
+			Some(count) => {
+				self.heap.access_object(OBJECT_ACCESS_Monitor, oid, |obj| {
+					obj.monitor_mut().lock_restore(tid, count);
+				});
+			}
+		}
+		if self.interrupted {
+			self.interrupted = false;
+			self.throw_exception("java.lang.InterruptedException", None);
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Object.notify() and Object.notifyAll()
+	pub fn monitor_notify(&mut self, oid : JavaObjectId, all : bool) {
+		if oid == JAVA_NULL_OID {
+			self.throw_exception("java.lang.NullPointerException", None);
+			return;
+		}
+		let tid = self.tid;
+		let mut held = false;
+		self.heap.access_object(OBJECT_ACCESS_Normal, oid, |obj| {
+			held = obj.monitor().is_locked_by_thread(tid);
+			if held {
+				if all {
+					obj.monitor_mut().notify_all(tid);
+				}
+				else {
+					obj.monitor_mut().notify_one(tid);
+				}
+			}
+		});
+		if !held {
+			self.throw_exception("java.lang.IllegalMonitorStateException", None);
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Raise an exception from within the VM, i.e. a NullPointerException
+	// upon dereferencing a null reference. The current instruction is
+	// aborted once it returns to the interpreter loop.
+	pub fn throw_exception(&mut self, class_name : &str, message : Option<&str>) {
+		let e = self.new_exception(class_name, message);
+		self.pending_exception = Some(e);
+	}
+
+
+	// ----------------------------------------------
+	// Create an exception as throw_exception() does, without raising it
+	fn new_exception(&mut self, class_name : &str, message : Option<&str>) -> PendingException {
+		let mut e = PendingException::new(class_name, message);
+		match self.classloader.load(class_name).await() {
+			Ok(jclass) => {
+				e.oid = self.heap.new_object(jclass.clone());
+				e.jclass = Some(jclass);
+			},
+			Err(_) => ()
+		}
+
+		// the message is also stored in Throwable.detailMessage, where 
+		// getMessage() finds it. It is left null if no String can be 
+		// created.
+		let offset = match e.jclass {
+			Some(ref c) => c.get().get_instance_layout().find_by_name("detailMessage").map(|slot| slot.get_offset()),
+			None => None
+		};
+		match (message, offset) {
+			(Some(m), Some(offset)) => {
+				match self.new_string(m.to_utf16()) {
+					Ok(soid) => {
+						self.heap.access_object(OBJECT_ACCESS_Normal, e.oid, |obj| {
+							obj.set_ref(offset, soid);
+						});
+					},
+					Err(_) => ()
+				}
+			},
+			_ => ()
+		}
+		e.stack_trace = self.get_stack_trace();
+		e
+	}
+
+
+	// ----------------------------------------------
+	// athrow
+	fn throw_object(&mut self) {
+		let oid = self.pop_ref();
+		if oid == JAVA_NULL_OID {
+			self.throw_exception("java.lang.NullPointerException", None);
+			return;
+		}
+		let mut jclass = None;
+		self.heap.access_object(OBJECT_ACCESS_Normal, oid, |obj| {
+			jclass = Some(obj.get_class());
+		});
+		let jclass = jclass.unwrap();
+
+		// the detail message is kept in Throwable.detailMessage
+		let mut message_oid = JAVA_NULL_OID;
+		match jclass.get().get_instance_layout().find_by_name("detailMessage") {
+			Some(slot) => {
+				let offset = slot.get_offset();
+				self.heap.access_object(OBJECT_ACCESS_Normal, oid, |obj| {
+					message_oid = obj.get_ref(offset);
+				});
+			},
+			None => ()
+		}
+		let message = if message_oid != JAVA_NULL_OID {
+			let chars = self.get_string_chars(message_oid);
+			if str::is_utf16(chars) { Some(str::from_utf16(chars)) } else { None }
+		}
+		else {
+			None
+		};
+
+		let mut e = PendingException::new(jclass.get().get_name().as_slice(), 
+			message.as_ref().map(|m| m.as_slice()));
+		e.oid = oid;
+		e.jclass = Some(jclass);
+		e.stack_trace = self.get_stack_trace();
+		self.pending_exception = Some(e);
+	}
+
+
+	// ----------------------------------------------
+	// Search the frames above `depth` for a handler of the pending 
+	// exception, innermost first, popping all frames that do not have
+	// one. If a handler is found, the exception object is pushed onto
+	// the emptied operand stack of its frame and execution resumes at 
+	// the handler. Returns whether this was the case.
+	fn find_exception_handler(&mut self, depth : uint) -> bool {
+		while self.frames.len() > depth {
+			let top = self.frames.len() - 1;
+			let handler = {
+				let frame = &self.frames[top];
+				let e = self.pending_exception.get_ref();
+				let code = frame.jclass.get().get_method_by_index(frame.method).get_code().unwrap();
+
+				// pc points past the instruction that raised the exception,
+				// or past the invocation in frames of callers
+				let pc = code.get_pc(if frame.pc > 0 { frame.pc - 1 } else { 0 });
+				code.get_exception_handlers().iter().find(|h| {
+					h.start_pc <= pc && pc < h.end_pc && match h.catch_type {
+						None => true,
+						Some(ref name) => is_exception_instance_of(e, name.as_slice())
+					}
+				}).map(|h| code.get_index_for_pc(h.handler_pc).unwrap())
+			};
+
+			match handler {
+				Some(index) => {
+					let e = self.pending_exception.take().unwrap();
+					let base = self.frames[top].opstack_base;
+					self.opstack.truncate(base);
+					self.push_ref(e.oid);
+					self.jump(index);
+					return true;
+				},
+				None => {
+					// synchronized methods that complete abruptly 
+					// release their monitor as well
+					self.release_frame_monitor(top);
+					let frame = self.frames.pop().unwrap();
+					self.locals.truncate(frame.locals_base);
+					self.opstack.truncate(frame.opstack_base);
+				}
+			}
+		}
+		false
+	}
+
+
+	// ----------------------------------------------
+	// Get the current stack trace, innermost frame first
+	fn get_stack_trace(&self) -> ~[~str] {
+		self.frames.rev_iter().map(|frame| {
+			let jclass = frame.jclass.get();
+			let m = jclass.get_method_by_index(frame.method);
+			let code = m.get_code().unwrap();
+			let pc = code.get_pc(if frame.pc > 0 { frame.pc - 1 } else { 0 });
+			let location = match (jclass.get_source_file(), code.get_line_number(pc)) {
+				(Some(file), Some(line)) => format!("{}:{}", *file, line),
+				(Some(file), None) => file.clone(),
+				(None, _) => ~"Unknown Source"
+			};
+			format!("{}.{}({})", *jclass.get_name(), *m.get_name(), location)
+		}).collect()
+	}
+
+
+	// ----------------------------------------------
+	// Raise the LinkageError corresponding to a failed resolution
+	fn throw_linkage_error(&mut self, e : &ClassLoadError) {
+		self.throw_exception(e.get_java_exception_name(), Some(e.get_detail_message().as_slice()));
+	}
+
+
+	// ----------------------------------------------
+	#[inline]
+	fn jump(&mut self, target : uint) {
+		let top = self.frames.len() - 1;
+		self.frames[top].pc = target;
+	}
+
+
+	// ----------------------------------------------
+	// Typed access to the top of the operand stack
+	#[inline]
+	fn pop_slot(&mut self) -> u64 {
+		if !self.check_operand_slots(1) {
+			return 0;
+		}
+		self.opstack.pop().unwrap()
+	}
+
+	#[inline]
+	fn push_int(&mut self, v : i32) {
+		self.opstack.push(v as u32 as u64);
+	}
+
+	#[inline]
+	fn pop_int(&mut self) -> i32 {
+		self.pop_slot() as u32 as i32
+	}
+
+	#[inline]
+	fn push_float(&mut self, v : f32) {
+		self.opstack.push(unsafe { cast::transmute::<f32, u32>(v) } as u64);
+	}
+
+	#[inline]
+	fn pop_float(&mut self) -> f32 {
+		unsafe { cast::transmute(self.pop_slot() as u32) }
+	}
+
+	#[inline]
+	fn push_long(&mut self, v : i64) {
+		self.opstack.push(v as u64);
+		self.opstack.push(0);
+	}
+
+	#[inline]
+	fn pop_long(&mut self) -> i64 {
+		self.pop_slot();
+		self.pop_slot() as i64
+	}
+
+	#[inline]
+	fn push_double(&mut self, v : f64) {
+		self.opstack.push(unsafe { cast::transmute(v) });
+		self.opstack.push(0);
+	}
+
+	#[inline]
+	fn pop_double(&mut self) -> f64 {
+		self.pop_slot();
+		unsafe { cast::transmute(self.pop_slot()) }
+	}
+
+	#[inline]
+	fn push_ref(&mut self, v : JavaObjectId) {
+		self.opstack.push(v);
+	}
+
+	#[inline]
+	fn pop_ref(&mut self) -> JavaObjectId {
+		self.pop_slot()
+	}
+
+	// Push or pop a field value of the given kind in its raw
+	// representation as returned by read_field()
+	#[inline]
+	fn push_value(&mut self, kind : SlotKind, v : u64) {
+		self.opstack.push(v);
+		if is_wide_kind(kind) {
+			self.opstack.push(0);
+		}
+	}
+
+	#[inline]
+	fn pop_value(&mut self, kind : SlotKind) -> u64 {
+		if is_wide_kind(kind) {
+			self.pop_slot();
+		}
+		self.pop_slot()
+	}
+
+
+	// ----------------------------------------------
+	// Access to the local variables of the top frame. Indices beyond
+	// max_locals are flagged, reads then yield 0 and writes are dropped.
+	#[inline]
+	fn check_local(&mut self, index : uint) -> bool {
+		if index >= self.frames[self.frames.len() - 1].max_locals {
+			self.verify_error = Some("bad local variable index");
+			return false;
+		}
+		true
+	}
+
+	#[inline]
+	fn get_local(&mut self, index : uint) -> u64 {
+		if !self.check_local(index) {
+			return 0;
+		}
+		self.locals[self.frames[self.frames.len() - 1].locals_base + index]
+	}
+
+	#[inline]
+	fn set_local(&mut self, index : uint, v : u64) {
+		if !self.check_local(index) {
+			return;
+		}
+		let base = self.frames[self.frames.len() - 1].locals_base;
+		self.locals[base + index] = v;
+	}
+
+
+	// ----------------------------------------------
+	// ldc, ldc_w and ldc2_w
+	fn load_constant(&mut self, pool : uint, index : uint) {
+		// either a primitive value, the contents of a String literal
+		// or a class reference
+		let (value, string, is_class) = {
+			let constants = self.constant_pools[pool].get_class().get().get_constants();
+			if index == 0 || index > constants.len() {
+				(None, None, false)
+			}
+			else {
+				match constants[index - 1] {
+					CONSTANT_integer_info(v) => (Some((v as u32 as u64, SK_Int)), None, false),
+					CONSTANT_float_info(v) => 
+						(Some((unsafe { cast::transmute::<f32, u32>(v) } as u64, SK_Float)), None, false),
+					CONSTANT_long_info(v) => (Some((v as u64, SK_Long)), None, false),
+					CONSTANT_double_info(v) => (Some((unsafe { cast::transmute(v) }, SK_Double)), None, false),
+					CONSTANT_string_info(utf8_index) if utf8_index as uint - 1 < constants.len() => {
+						match constants[utf8_index as uint - 1] {
+							CONSTANT_utf8_info(ref s) => (None, Some(s.to_utf16()), false),
+							_ => (None, None, false)
+						}
+					},
+					CONSTANT_class_info(_) => (None, None, true),
+					_ => (None, None, false)
+				}
+			}
+		};
+		match (value, string, is_class) {
+			(Some((v, kind)), _, _) => self.push_value(kind, v),
+			(None, Some(chars), _) => {
+				match self.intern_string(chars, None) {
+					Ok(oid) => self.push_ref(oid),
+					Err(name) => self.throw_exception(name, None)
+				}
+			},
+			// the class is resolved, but not initialized
+			(None, None, true) => {
+				let jclass = match self.resolve_class_ref(pool, index) {
+					Err(e) => {
+						self.throw_linkage_error(&e);
+						return;
+					},
+					Ok(c) => c
+				};
+				match self.get_class_object(&jclass) {
+					Ok(oid) => self.push_ref(oid),
+					Err(name) => self.throw_exception(name, None)
+				}
+			},
+			// TODO: MethodType and MethodHandle constants
+			(None, None, false) => self.throw_exception("java.lang.InternalError", 
+				Some(format!("unsupported constant pool entry {} for ldc", index).as_slice()))
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Resolve a field reference for getstatic or putstatic and ensure
+	// the declaring class is initialized. Returns the field along with 
+	// the oid of the object holding the static fields of the class, or
+	// None if an exception was thrown.
+	fn resolve_static_field(&mut self, pool : uint, index : uint) -> Option<(ResolvedField, JavaObjectId)> {
+		let field = match self.resolve_field_ref(pool, index) {
+			Err(e) => {
+				self.throw_linkage_error(&e);
+				return None;
+			},
+			Ok(f) => f
+		};
+		if !field.is_static() {
+			self.throw_exception("java.lang.IncompatibleClassChangeError", 
+				Some(format!("expected static field {}", *field.get_slot().get_name()).as_slice()));
+			return None;
+		}
+		match self.initialize_class(field.get_class()) {
+			Err(e) => {
+				self.rethrow(e);
+				None
+			},
+			Ok(oid) => Some((field, oid))
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Resolve a field reference for getfield or putfield. Returns None 
+	// if an exception was thrown.
+	fn resolve_instance_field(&mut self, pool : uint, index : uint) -> Option<ResolvedField> {
+		let field = match self.resolve_field_ref(pool, index) {
+			Err(e) => {
+				self.throw_linkage_error(&e);
+				return None;
+			},
+			Ok(f) => f
+		};
+		if field.is_static() {
+			self.throw_exception("java.lang.IncompatibleClassChangeError", 
+				Some(format!("expected non-static field {}", *field.get_slot().get_name()).as_slice()));
+			return None;
+		}
+		Some(field)
+	}
+
+
+	// ----------------------------------------------
+	fn get_static(&mut self, pool : uint, index : uint) {
+		let (field, oid) = match self.resolve_static_field(pool, index) {
+			None => return,
+			Some(res) => res
+		};
+		let mut v = 0u64;
+		self.heap.access_object(OBJECT_ACCESS_Normal, oid, |obj| {
+			v = read_field(obj, field.get_offset(), field.get_kind());
+		});
+		self.push_value(field.get_kind(), v);
+	}
+
+
+	// ----------------------------------------------
+	fn put_static(&mut self, pool : uint, index : uint) {
+		let (field, oid) = match self.resolve_static_field(pool, index) {
+			None => return,
+			Some(res) => res
+		};
+		let v = self.pop_value(field.get_kind());
+		self.heap.access_object(OBJECT_ACCESS_Normal, oid, |obj| {
+			write_field(obj, field.get_offset(), field.get_kind(), v);
+		});
+	}
+
+
+	// ----------------------------------------------
+	fn get_field(&mut self, pool : uint, index : uint) {
+		let field = match self.resolve_instance_field(pool, index) {
+			None => return,
+			Some(f) => f
+		};
+		let oid = self.pop_ref();
+		if oid == JAVA_NULL_OID {
+			self.throw_exception("java.lang.NullPointerException", None);
+			return;
+		}
+		let mut v = 0u64;
+		self.heap.access_object(OBJECT_ACCESS_Normal, oid, |obj| {
+			v = read_field(obj, field.get_offset(), field.get_kind());
+		});
+		self.push_value(field.get_kind(), v);
+	}
+
+
+	// ----------------------------------------------
+	fn put_field(&mut self, pool : uint, index : uint) {
+		let field = match self.resolve_instance_field(pool, index) {
+			None => return,
+			Some(f) => f
+		};
+		let v = self.pop_value(field.get_kind());
+		let oid = self.pop_ref();
+		if oid == JAVA_NULL_OID {
+			self.throw_exception("java.lang.NullPointerException", None);
+			return;
+		}
+		self.heap.access_object(OBJECT_ACCESS_Normal, oid, |obj| {
+			write_field(obj, field.get_offset(), field.get_kind(), v);
+		});
+	}
+
+
+	// ----------------------------------------------
+	// Execute the next instruction of the top frame
+	fn op(&mut self) {
+		let (op, pool, code_len) = {
+			let top = self.frames.len() - 1;
+			let frame = &mut self.frames[top];
+			let (op, code_len) = {
+				let code = frame.jclass.get().get_method_by_index(frame.method).get_code().unwrap();
+				let opcodes = code.get_opcodes();
+				if frame.pc >= opcodes.len() {
+					(None, opcodes.len())
+				}
+				else {
+					frame.pc += 1;
+					(Some(opcodes[frame.pc - 1].clone()), opcodes.len())
+				}
+			};
+			(op, frame.pool, code_len)
+		};
+		let op = match op {
+			Some(op) => op,
+			None => {
+				self.throw_exception("java.lang.VerifyError", Some("falling off the end of the code"));
+				return;
+			}
+		};
+
+		match op {
+			DecodedOpcode_nop => (),
+
+			// constants
+			DecodedOpcode_aconst_null => self.push_ref(JAVA_NULL_OID),
+			DecodedOpcode_iconst(v) => self.push_int(v),
+			DecodedOpcode_lconst(v) => self.push_long(v),
+			DecodedOpcode_fconst(v) => self.push_float(v),
+			DecodedOpcode_dconst(v) => self.push_double(v),
+			DecodedOpcode_ldc(idx) | DecodedOpcode_ldc2_w(idx) => self.load_constant(pool, idx as uint),
+
+			// loads and stores. The second slot of long and double 
+			// locals is set to zero on store, but never read.
+			DecodedOpcode_iload(n) | DecodedOpcode_fload(n) | DecodedOpcode_aload(n) => {
+				let v = self.get_local(n);
+				self.opstack.push(v);
+			},
+			DecodedOpcode_lload(n) | DecodedOpcode_dload(n) => {
+				self.check_local(n + 1);
+				let v = self.get_local(n);
+				self.opstack.push(v);
+				self.opstack.push(0);
+			},
+			DecodedOpcode_istore(n) | DecodedOpcode_fstore(n) | DecodedOpcode_astore(n) => {
+				let v = self.pop_slot();
+				self.set_local(n, v);
+			},
+			DecodedOpcode_lstore(n) | DecodedOpcode_dstore(n) => {
+				self.pop_slot();
+				let v = self.pop_slot();
+				self.set_local(n, v);
+				self.set_local(n + 1, 0);
+			},
+
+			// stack manipulation. long and double values take two slots, 
+			// so the category 2 forms need no special treatment.
+			DecodedOpcode_pop => {
+				self.pop_slot();
+			},
+			DecodedOpcode_pop2 => {
+				self.pop_slot();
+				self.pop_slot();
+			},
+			DecodedOpcode_dup => {
+				let v1 = self.pop_slot();
+				self.opstack.push_all([v1, v1]);
+			},
+			DecodedOpcode_dup_x1 => {
+				let v1 = self.pop_slot();
+				let v2 = self.pop_slot();
+				self.opstack.push_all([v1, v2, v1]);
+			},
+			DecodedOpcode_dup_x2 => {
+				let v1 = self.pop_slot();
+				let v2 = self.pop_slot();
+				let v3 = self.pop_slot();
+				self.opstack.push_all([v1, v3, v2, v1]);
+			},
+			DecodedOpcode_dup2 => {
+				let v1 = self.pop_slot();
+				let v2 = self.pop_slot();
+				self.opstack.push_all([v2, v1, v2, v1]);
+			},
+			DecodedOpcode_dup2_x1 => {
+				let v1 = self.pop_slot();
+				let v2 = self.pop_slot();
+				let v3 = self.pop_slot();
+				self.opstack.push_all([v2, v1, v3, v2, v1]);
+			},
+			DecodedOpcode_dup2_x2 => {
+				let v1 = self.pop_slot();
+				let v2 = self.pop_slot();
+				let v3 = self.pop_slot();
+				let v4 = self.pop_slot();
+				self.opstack.push_all([v2, v1, v4, v3, v2, v1]);
+			},
+			DecodedOpcode_swap => {
+				let v1 = self.pop_slot();
+				let v2 = self.pop_slot();
+				self.opstack.push_all([v1, v2]);
+			},
+
+			// int arithmetic, which wraps around on overflow
+			DecodedOpcode_iadd => { let b = self.pop_int(); let a = self.pop_int(); self.push_int(a + b) },
+			DecodedOpcode_isub => { let b = self.pop_int(); let a = self.pop_int(); self.push_int(a - b) },
+			DecodedOpcode_imul => { let b = self.pop_int(); let a = self.pop_int(); self.push_int(a * b) },
+			DecodedOpcode_idiv => {
+				let b = self.pop_int();
+				let a = self.pop_int();
+				if b == 0 {
+					self.throw_exception("java.lang.ArithmeticException", Some("/ by zero"));
+					return;
+				}
+				// MIN_VALUE / -1 overflows to MIN_VALUE
+				self.push_int(if b == -1 { -a } else { a / b });
+			},
+			DecodedOpcode_irem => {
+				let b = self.pop_int();
+				let a = self.pop_int();
+				if b == 0 {
+					self.throw_exception("java.lang.ArithmeticException", Some("/ by zero"));
+					return;
+				}
+				self.push_int(if b == -1 { 0 } else { a % b });
+			},
+			DecodedOpcode_ineg => { let a = self.pop_int(); self.push_int(-a) },
+			DecodedOpcode_ishl => { let b = self.pop_int(); let a = self.pop_int(); self.push_int(a << (b & 0x1f)) },
+			DecodedOpcode_ishr => { let b = self.pop_int(); let a = self.pop_int(); self.push_int(a >> (b & 0x1f)) },
+			DecodedOpcode_iushr => {
+				let b = self.pop_int();
+				let a = self.pop_int();
+				self.push_int(((a as u32) >> ((b & 0x1f) as u32)) as i32);
+			},
+			DecodedOpcode_iand => { let b = self.pop_int(); let a = self.pop_int(); self.push_int(a & b) },
+			DecodedOpcode_ior => { let b = self.pop_int(); let a = self.pop_int(); self.push_int(a | b) },
+			DecodedOpcode_ixor => { let b = self.pop_int(); let a = self.pop_int(); self.push_int(a ^ b) },
+			DecodedOpcode_iinc(n, c) => {
+				let v = self.get_local(n) as u32 as i32;
+				self.set_local(n, (v + c) as u32 as u64);
+			},
+
+			// long arithmetic
+			DecodedOpcode_ladd => { let b = self.pop_long(); let a = self.pop_long(); self.push_long(a + b) },
+			DecodedOpcode_lsub => { let b = self.pop_long(); let a = self.pop_long(); self.push_long(a - b) },
+			DecodedOpcode_lmul => { let b = self.pop_long(); let a = self.pop_long(); self.push_long(a * b) },
+			DecodedOpcode_ldiv => {
+				let b = self.pop_long();
+				let a = self.pop_long();
+				if b == 0 {
+					self.throw_exception("java.lang.ArithmeticException", Some("/ by zero"));
+					return;
+				}
+				self.push_long(if b == -1 { -a } else { a / b });
+			},
+			DecodedOpcode_lrem => {
+				let b = self.pop_long();
+				let a = self.pop_long();
+				if b == 0 {
+					self.throw_exception("java.lang.ArithmeticException", Some("/ by zero"));
+					return;
+				}
+				self.push_long(if b == -1 { 0 } else { a % b });
+			},
+			DecodedOpcode_lneg => { let a = self.pop_long(); self.push_long(-a) },
+			DecodedOpcode_lshl => { 
+				let b = self.pop_int(); 
+				let a = self.pop_long(); 
+				self.push_long(a << ((b & 0x3f) as i64));
+			},
+			DecodedOpcode_lshr => { 
+				let b = self.pop_int(); 
+				let a = self.pop_long(); 
+				self.push_long(a >> ((b & 0x3f) as i64));
+			},
+			DecodedOpcode_lushr => {
+				let b = self.pop_int();
+				let a = self.pop_long();
+				self.push_long(((a as u64) >> ((b & 0x3f) as u64)) as i64);
+			},
+			DecodedOpcode_land => { let b = self.pop_long(); let a = self.pop_long(); self.push_long(a & b) },
+			DecodedOpcode_lor => { let b = self.pop_long(); let a = self.pop_long(); self.push_long(a | b) },
+			DecodedOpcode_lxor => { let b = self.pop_long(); let a = self.pop_long(); self.push_long(a ^ b) },
+
+			// float and double arithmetic. frem and drem truncate like
+			// C's fmod, which is what Rust's % does for floats.
+			DecodedOpcode_fadd => { let b = self.pop_float(); let a = self.pop_float(); self.push_float(a + b) },
+			DecodedOpcode_fsub => { let b = self.pop_float(); let a = self.pop_float(); self.push_float(a - b) },
+			DecodedOpcode_fmul => { let b = self.pop_float(); let a = self.pop_float(); self.push_float(a * b) },
+			DecodedOpcode_fdiv => { let b = self.pop_float(); let a = self.pop_float(); self.push_float(a / b) },
+			DecodedOpcode_frem => { let b = self.pop_float(); let a = self.pop_float(); self.push_float(a % b) },
+			DecodedOpcode_fneg => { let a = self.pop_float(); self.push_float(-a) },
+			DecodedOpcode_dadd => { let b = self.pop_double(); let a = self.pop_double(); self.push_double(a + b) },
+			DecodedOpcode_dsub => { let b = self.pop_double(); let a = self.pop_double(); self.push_double(a - b) },
+			DecodedOpcode_dmul => { let b = self.pop_double(); let a = self.pop_double(); self.push_double(a * b) },
+			DecodedOpcode_ddiv => { let b = self.pop_double(); let a = self.pop_double(); self.push_double(a / b) },
+			DecodedOpcode_drem => { let b = self.pop_double(); let a = self.pop_double(); self.push_double(a % b) },
+			DecodedOpcode_dneg => { let a = self.pop_double(); self.push_double(-a) },
+
+			// conversions
+			DecodedOpcode_i2l => { let a = self.pop_int(); self.push_long(a as i64) },
+			DecodedOpcode_i2f => { let a = self.pop_int(); self.push_float(a as f32) },
+			DecodedOpcode_i2d => { let a = self.pop_int(); self.push_double(a as f64) },
+			DecodedOpcode_l2i => { let a = self.pop_long(); self.push_int(a as i32) },
+			DecodedOpcode_l2f => { let a = self.pop_long(); self.push_float(a as f32) },
+			DecodedOpcode_l2d => { let a = self.pop_long(); self.push_double(a as f64) },
+			DecodedOpcode_f2i => { let a = self.pop_float(); self.push_int(double_to_int(a as f64)) },
+			DecodedOpcode_f2l => { let a = self.pop_float(); self.push_long(double_to_long(a as f64)) },
+			DecodedOpcode_f2d => { let a = self.pop_float(); self.push_double(a as f64) },
+			DecodedOpcode_d2i => { let a = self.pop_double(); self.push_int(double_to_int(a)) },
+			DecodedOpcode_d2l => { let a = self.pop_double(); self.push_long(double_to_long(a)) },
+			DecodedOpcode_d2f => { let a = self.pop_double(); self.push_float(a as f32) },
+			DecodedOpcode_i2b => { let a = self.pop_int(); self.push_int(a as i8 as i32) },
+			DecodedOpcode_i2c => { let a = self.pop_int(); self.push_int(a as u16 as i32) },
+			DecodedOpcode_i2s => { let a = self.pop_int(); self.push_int(a as i16 as i32) },
+
+			// comparisons
+			DecodedOpcode_lcmp => {
+				let b = self.pop_long();
+				let a = self.pop_long();
+				self.push_int(if a > b { 1 } else if a == b { 0 } else { -1 });
+			},
+			DecodedOpcode_fcmpl => { 
+				let b = self.pop_float(); 
+				let a = self.pop_float(); 
+				self.push_int(compare_doubles(a as f64, b as f64, -1));
+			},
+			DecodedOpcode_fcmpg => { 
+				let b = self.pop_float(); 
+				let a = self.pop_float(); 
+				self.push_int(compare_doubles(a as f64, b as f64, 1));
+			},
+			DecodedOpcode_dcmpl => { 
+				let b = self.pop_double(); 
+				let a = self.pop_double(); 
+				self.push_int(compare_doubles(a, b, -1));
+			},
+			DecodedOpcode_dcmpg => { 
+				let b = self.pop_double(); 
+				let a = self.pop_double(); 
+				self.push_int(compare_doubles(a, b, 1));
+			},
+
+			// branches
+			DecodedOpcode_ifeq(t) => { if self.pop_int() == 0 { self.jump(t) } },
+			DecodedOpcode_ifne(t) => { if self.pop_int() != 0 { self.jump(t) } },
+			DecodedOpcode_iflt(t) => { if self.pop_int() < 0 { self.jump(t) } },
+			DecodedOpcode_ifge(t) => { if self.pop_int() >= 0 { self.jump(t) } },
+			DecodedOpcode_ifgt(t) => { if self.pop_int() > 0 { self.jump(t) } },
+			DecodedOpcode_ifle(t) => { if self.pop_int() <= 0 { self.jump(t) } },
+			DecodedOpcode_if_icmpeq(t) => { let b = self.pop_int(); if self.pop_int() == b { self.jump(t) } },
+			DecodedOpcode_if_icmpne(t) => { let b = self.pop_int(); if self.pop_int() != b { self.jump(t) } },
+			DecodedOpcode_if_icmplt(t) => { let b = self.pop_int(); if self.pop_int() < b { self.jump(t) } },
+			DecodedOpcode_if_icmpge(t) => { let b = self.pop_int(); if self.pop_int() >= b { self.jump(t) } },
+			DecodedOpcode_if_icmpgt(t) => { let b = self.pop_int(); if self.pop_int() > b { self.jump(t) } },
+			DecodedOpcode_if_icmple(t) => { let b = self.pop_int(); if self.pop_int() <= b { self.jump(t) } },
+			DecodedOpcode_if_acmpeq(t) => { let b = self.pop_ref(); if self.pop_ref() == b { self.jump(t) } },
+			DecodedOpcode_if_acmpne(t) => { let b = self.pop_ref(); if self.pop_ref() != b { self.jump(t) } },
+			DecodedOpcode_ifnull(t) => { if self.pop_ref() == JAVA_NULL_OID { self.jump(t) } },
+			DecodedOpcode_ifnonnull(t) => { if self.pop_ref() != JAVA_NULL_OID { self.jump(t) } },
+			DecodedOpcode_goto(t) => self.jump(t),
+
+			// the return address of jsr is the index of the next instruction
+			DecodedOpcode_jsr(t) => {
+				let ret = self.frames[self.frames.len() - 1].pc;
+				self.opstack.push(ret as u64);
+				self.jump(t);
+			},
+			DecodedOpcode_ret(n) => {
+				let ret = self.get_local(n);
+				if ret >= code_len as u64 {
+					self.throw_exception("java.lang.VerifyError", Some("bad return address"));
+					return;
+				}
+				self.jump(ret as uint);
+			},
+			DecodedOpcode_tableswitch(default, low, targets) => {
+				let key = self.pop_int() as i64 - low as i64;
+				if key >= 0 && key < targets.len() as i64 {
+					self.jump(targets[key as uint]);
+				}
+				else {
+					self.jump(default);
+				}
+			},
+			DecodedOpcode_lookupswitch(default, pairs) => {
+				let key = self.pop_int();
+				let mut target = default;
+				for p in pairs.iter() {
+					let (k, t) = *p;
+					if k == key {
+						target = t;
+						break;
+					}
+				}
+				self.jump(target);
+			},
+
+			// returns
+			DecodedOpcode_ireturn | DecodedOpcode_freturn | DecodedOpcode_areturn => self.pop_frame(1),
+			DecodedOpcode_lreturn | DecodedOpcode_dreturn => self.pop_frame(2),
+			DecodedOpcode_return => self.pop_frame(0),
+
+			// field access
+			DecodedOpcode_getstatic(idx) => self.get_static(pool, idx as uint),
+			DecodedOpcode_putstatic(idx) => self.put_static(pool, idx as uint),
+			DecodedOpcode_getfield(idx) => self.get_field(pool, idx as uint),
+			DecodedOpcode_putfield(idx) => self.put_field(pool, idx as uint),
+
+			// method invocation
+			DecodedOpcode_invokestatic(idx) => self.invoke_static(pool, idx as uint),
+			DecodedOpcode_invokespecial(idx) => self.invoke_special(pool, idx as uint),
+			DecodedOpcode_invokevirtual(idx) => self.invoke_virtual(pool, idx as uint),
+			DecodedOpcode_invokeinterface(idx, _) => self.invoke_interface(pool, idx as uint),
+
+			DecodedOpcode_new(idx) => self.new_object(pool, idx as uint),
+
+			// arrays
+			DecodedOpcode_newarray(atype) => self.new_primitive_array(atype),
+			DecodedOpcode_anewarray(idx) => self.new_reference_array(pool, idx as uint),
+			DecodedOpcode_multianewarray(idx, dims) => self.new_multi_array(pool, idx as uint, dims),
+			DecodedOpcode_arraylength => self.array_length(),
+			DecodedOpcode_iaload | DecodedOpcode_laload | DecodedOpcode_faload | 
+			DecodedOpcode_daload | DecodedOpcode_aaload | DecodedOpcode_baload | 
+			DecodedOpcode_caload | DecodedOpcode_saload => self.array_load(),
+			DecodedOpcode_iastore | DecodedOpcode_bastore | 
+			DecodedOpcode_castore | DecodedOpcode_sastore => self.array_store(SK_Int),
+			DecodedOpcode_lastore => self.array_store(SK_Long),
+			DecodedOpcode_fastore => self.array_store(SK_Float),
+			DecodedOpcode_dastore => self.array_store(SK_Double),
+			DecodedOpcode_aastore => self.array_store(SK_Reference),
+
+			DecodedOpcode_athrow => self.throw_object(),
+
+			DecodedOpcode_monitorenter => self.monitor_enter(),
+			DecodedOpcode_monitorexit => self.monitor_exit(),
+
+			_ => self.throw_exception("java.lang.InternalError", Some("unsupported instruction"))
+		}
+	}
+}
+
+
+impl PendingException {
+
+	// ----------------------------------------------
+	pub fn new(class_name : &str, message : Option<&str>) -> PendingException {
+		PendingException {
+			class_name : class_name.into_owned(),
+			message : message.map(|m| m.into_owned()),
+			oid : JAVA_NULL_OID,
+			jclass : None,
+			stack_trace : ~[],
+			cause : None,
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Get a report for the exception in the format HotSpot uses for 
+	// uncaught exceptions, i.e.
+	//
+	//   Exception in thread "main" java.lang.ArithmeticException: / by zero
+	//       at Foo.bar(Foo.java:5)
+	//
+	// followed by a "Caused by: " entry for every cause.
+	pub fn get_report(&self, thread_name : &str) -> ~str {
+		let mut s = format!("Exception in thread \"{}\" ", thread_name);
+		let mut e = self;
+		loop {
+			s.push_str(e.class_name);
+			match e.message {
+				Some(ref m) => s.push_str(format!(": {}", *m)),
+				None => ()
+			}
+			s.push_char('\n');
+			for frame in e.stack_trace.iter() {
+				s.push_str(format!("\tat {}\n", *frame));
+			}
+			match e.cause {
+				Some(ref c) => {
+					s.push_str("Caused by: ");
+					e = &**c;
+				},
+				None => break
+			}
+		}
+		s
+	}
+}
+
+
+// ----------------------------------------------
+// Select the method to invoke on an instance of class `recv` given a 
+// resolved method reference, using the vtable of the class or, for 
+// methods declared by interfaces, the itable of the interface. Returns
+// the class declaring the selected method and the index of the method,
+// or the name of the exception to raise if there is none.
+fn select_method(recv : &JavaClassRef, m : &ResolvedMethod) -> Result<(JavaClassRef, uint), &'static str> {
+	let decl = m.get_class().get();
+	let tables = recv.get().get_dispatch_tables();
+	let target = if decl.is_interface() {
+		tables.get_itable_entry(decl.get_name().as_slice(), m.get_index())
+	}
+	else {
+		match decl.get_dispatch_tables().get_vtable_index(m.get_index()) {
+			Some(slot) => tables.get_vtable_entry(slot),
+			// not dispatched virtually
+			None => return Ok((m.get_class().clone(), m.get_index()))
+		}
+	};
+	match target {
+		Some(&DT_Method(Some(ref c), idx)) => Ok((c.clone(), idx)),
+		Some(&DT_Method(None, idx)) => Ok((recv.clone(), idx)),
+		Some(&DT_Conflict) => Err("java.lang.IncompatibleClassChangeError"),
+		Some(&DT_Abstract) | None => Err("java.lang.AbstractMethodError")
+	}
+}
+
+
+// ----------------------------------------------
+// Check if an exception is an instance of the class with the given 
+// name. If the class of the exception could not be loaded, only its 
+// name is compared.
+fn is_exception_instance_of(e : &PendingException, name : &str) -> bool {
+	match e.jclass {
+		Some(ref c) => c.get().is_subclass_of(name),
+		None => e.class_name.as_slice() == name
+	}
+}
+
+
+// ----------------------------------------------
+// Format a method as Class.name(descriptor) for use in exception messages
+fn describe_method(jclass : &JavaClassRef, method : uint) -> ~str {
+	let m = jclass.get().get_method_by_index(method);
+	format!("{}.{}{}", *jclass.get().get_name(), *m.get_name(), *m.get_desc())
+}
+
+
+// ----------------------------------------------
+// Check if values of a kind take two operand stack slots
+#[inline]
+fn is_wide_kind(kind : SlotKind) -> bool {
+	match kind {
+		SK_Long | SK_Double => true,
+		_ => false
+	}
+}
+
+
+// ----------------------------------------------
+// Read a field value in its raw representation as used in 
+// operand stack slots
+#[inline]
+fn read_field(obj : &JavaObject, offset : uint, kind : SlotKind) -> u64 {
+	match kind {
+		SK_Int => obj.get_int(offset) as u32 as u64,
+		SK_Float => unsafe { cast::transmute::<f32, u32>(obj.get_float(offset)) } as u64,
+		SK_Long => obj.get_long(offset) as u64,
+		SK_Double => unsafe { cast::transmute(obj.get_double(offset)) },
+		SK_Reference => obj.get_ref(offset)
+	}
+}
+
+
+// ----------------------------------------------
+#[inline]
+fn write_field(obj : &mut JavaObject, offset : uint, kind : SlotKind, v : u64) {
+	match kind {
+		SK_Int => obj.set_int(offset, v as u32 as i32),
+		SK_Float => obj.set_float(offset, unsafe { cast::transmute(v as u32) }),
+		SK_Long => obj.set_long(offset, v as i64),
+		SK_Double => obj.set_double(offset, unsafe { cast::transmute(v) }),
+		SK_Reference => obj.set_ref(offset, v)
+	}
+}
+
+
+// ----------------------------------------------
+// Compare two floating-point values for [fd]cmp[lg], `nan_result` 
+// being the result if either value is NaN.
+#[inline]
+fn compare_doubles(a : f64, b : f64, nan_result : i32) -> i32 {
+	if a > b { 
+		1 
+	} 
+	else if a == b { 
+		0 
+	} 
+	else if a < b { 
+		-1 
+	} 
+	else { 
+		nan_result 
+	}
+}
+
+
+// ----------------------------------------------
+// Convert to int as per f2i and d2i: NaN becomes zero and values
+// outside the range of int saturate.
+#[inline]
+fn double_to_int(v : f64) -> i32 {
+	if v != v {
+		0
+	}
+	else if v >= i32::max_value as f64 {
+		i32::max_value
+	}
+	else if v <= i32::min_value as f64 {
+		i32::min_value
+	}
+	else {
+		v as i32
+	}
+}
+
+
+// ----------------------------------------------
+// Convert to long as per f2l and d2l
+#[inline]
+fn double_to_long(v : f64) -> i64 {
+	if v != v {
+		0
+	}
+	else if v >= i64::max_value as f64 {
+		i64::max_value
+	}
+	else if v <= i64::min_value as f64 {
+		i64::min_value
+	}
+	else {
+		v as i64
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use thread::*;
+	use objectbroker::{ObjectBroker, ObjectBrokerMessage, OB_THREAD_REMOTE_OP, OB_VM_TO_BROKER, 
+		OBJECT_ACCESS_Normal};
+	use threadmanager::{THREAD_INTERRUPT};
+	use natives::{JavaValue, JV_Int, JV_Long, JV_Double, JV_Reference};
+	use localheap::{LocalHeap};
+	use layout::{SK_Double};
+	use classloader::{AbstractClassLoader, ClassLoader};
+	use classloader::tests::{test_get_real_classloader};
+	use class::{JavaClassRef};
+	use util::{assert_no_err};
+	use object::{JavaObjectId, JAVA_NULL_OID};
+	use vm;
+	use std::io::File;
+	use std::path::PosixPath;
+	use std::{cast, task, i32, i64};
+
+	// ----------------------------------------------
+	fn test_call(t : &mut ThreadContext, jclass : &JavaClassRef, name : &str, 
+		args : &[u64]) -> Result<~[u64], PendingException> 
+	{
+		let idx = jclass.get().get_methods().iter().position(|m| {
+			m.get_name().as_slice() == name
+		}).unwrap();
+		t.run_method(jclass, idx, args)
+	}
+
+	// ----------------------------------------------
+	// Create a thread for tests. The tests only exercise the classes
+	// they load, so the initializer of java.lang.Object is skipped by
+	// marking the class as initialized in this thread. The object 
+	// broker does not know about this.
+	fn test_thread(cl : ClassLoader, broker_chan : SharedChan<ObjectBrokerMessage>) -> ~ThreadContext {
+		let mut t = ThreadContext::new(~cl as ~AbstractClassLoader, broker_chan);
+		t.initialized_classes.insert(~"java.lang.Object", JAVA_NULL_OID);
+		t
+	}
+
+	// ----------------------------------------------
+	// Create a thread connected to a new object broker, see test_thread(),
+	// and load the class with the given name.
+	fn test_thread_and_class(name : &str) -> (~ThreadContext, JavaClassRef) {
+		let (_, chan) = Chan::new();
+		let broker_chan = ObjectBroker::new(chan).launch();
+
+		let mut t = test_thread(test_get_real_classloader(), broker_chan);
+		let v = t.classloader.load(name).await();
+		assert_no_err(&v);
+		(t, v.unwrap())
+	}
+
+	fn int_result(r : Result<~[u64], PendingException>) -> i32 {
+		let slots = r.unwrap();
+		assert_eq!(slots.len(), 1);
+		slots[0] as u32 as i32
+	}
+
+	fn long_result(r : Result<~[u64], PendingException>) -> i64 {
+		let slots = r.unwrap();
+		assert_eq!(slots.len(), 2);
+		slots[0] as i64
+	}
+
+	fn int(v : i32) -> u64 {
+		v as u32 as u64
+	}
+
+	fn float(v : f32) -> u64 {
+		unsafe { cast::transmute::<f32, u32>(v) as u64 }
+	}
+
+	fn double(v : f64) -> u64 {
+		unsafe { cast::transmute(v) }
+	}
+
+
+	#[test]
+	fn test_interpret_arithmetics() {
+		let (mut t, c) = test_thread_and_class("Arithmetics");
+
+		// two's complement wraparound
+		assert_eq!(int_result(test_call(&mut *t, &c, "intOverflow", [int(i32::max_value)])), i32::min_value);
+		assert_eq!(int_result(test_call(&mut *t, &c, "intDiv", [int(i32::min_value), int(-1)])), i32::min_value);
+		assert_eq!(int_result(test_call(&mut *t, &c, "intDiv", [int(-7), int(2)])), -3);
+		assert_eq!(int_result(test_call(&mut *t, &c, "intRem", [int(-7), int(2)])), -1);
+		assert_eq!(int_result(test_call(&mut *t, &c, "intRem", [int(i32::min_value), int(-1)])), 0);
+
+		// division by zero
+		match test_call(&mut *t, &c, "intDiv", [int(1), int(0)]) {
+			Err(e) => assert_eq!(e.class_name, ~"java.lang.ArithmeticException"),
+			Ok(_) => fail!("expected ArithmeticException")
+		}
+		match test_call(&mut *t, &c, "intRem", [int(1), int(0)]) {
+			Err(e) => assert_eq!(e.class_name, ~"java.lang.ArithmeticException"),
+			Ok(_) => fail!("expected ArithmeticException")
+		}
+
+		let (a, b) = (-12345678901i64, 987654321i64);
+		assert_eq!(long_result(test_call(&mut *t, &c, "longOps", [a as u64, 0, b as u64, 0])), 
+			(a * b) ^ (((a as u64) >> 3) as i64 + (b << 62)));
+		assert_eq!(int_result(test_call(&mut *t, &c, "shifts", [int(-17), int(33)])), 
+			(-17 << 1) + (-17 >> 1) + ((-17i32 as u32) >> 1) as i32);
+
+		// NaN is unordered
+		let nan = 0.0f32 / 0.0f32;
+		assert_eq!(int_result(test_call(&mut *t, &c, "floatCompare", [float(1.0), float(2.0)])), -1);
+		assert_eq!(int_result(test_call(&mut *t, &c, "floatCompare", [float(2.0), float(1.0)])), 1);
+		assert_eq!(int_result(test_call(&mut *t, &c, "floatCompare", [float(2.0), float(2.0)])), 0);
+		assert_eq!(int_result(test_call(&mut *t, &c, "floatCompare", [float(nan), float(1.0)])), 2);
+		assert_eq!(int_result(test_call(&mut *t, &c, "floatCompare", [float(1.0), float(nan)])), 2);
+
+		// saturating conversions
+		assert_eq!(int_result(test_call(&mut *t, &c, "f2i", [float(nan)])), 0);
+		assert_eq!(int_result(test_call(&mut *t, &c, "f2i", [float(1e20)])), i32::max_value);
+		assert_eq!(int_result(test_call(&mut *t, &c, "f2i", [float(-1e20)])), i32::min_value);
+		assert_eq!(int_result(test_call(&mut *t, &c, "f2i", [float(-3.99)])), -3);
+		assert_eq!(long_result(test_call(&mut *t, &c, "d2l", [double(1e300), 0])), i64::max_value);
+		assert_eq!(long_result(test_call(&mut *t, &c, "d2l", [double(-1e300), 0])), i64::min_value);
+		assert_eq!(long_result(test_call(&mut *t, &c, "d2l", [double(0.0 / 0.0), 0])), 0);
+
+		// (byte)0x18081 + (char)0x18081 + (short)0x18081
+		assert_eq!(int_result(test_call(&mut *t, &c, "narrow", [int(0x18081)])), -127 + 0x8081 + -0x7f7f);
+
+		let r = test_call(&mut *t, &c, "doubleRem", [double(-5.5), 0, double(2.0), 0]).unwrap();
+		assert_eq!(unsafe { cast::transmute::<u64, f64>(r[0]) }, -1.5);
+
+		// branches and switches
+		assert_eq!(int_result(test_call(&mut *t, &c, "sum", [int(100)])), 4950);
+		assert_eq!(int_result(test_call(&mut *t, &c, "tableSwitch", [int(2)])), 20);
+		assert_eq!(int_result(test_call(&mut *t, &c, "tableSwitch", [int(4)])), -1);
+		assert_eq!(int_result(test_call(&mut *t, &c, "tableSwitch", [int(i32::min_value)])), -1);
+		assert_eq!(int_result(test_call(&mut *t, &c, "lookupSwitch", [int(-100)])), 1);
+		assert_eq!(int_result(test_call(&mut *t, &c, "lookupSwitch", [int(1000)])), 3);
+		assert_eq!(int_result(test_call(&mut *t, &c, "lookupSwitch", [int(6)])), 0);
+	}
+
+
+	#[test]
+	fn test_interpret_class_init() {
+		// the class is initialized once, after its <clinit> ran
+		let (mut t, c) = test_thread_and_class("ClassInit");
+		let statics = t.initialize_class(&c).unwrap();
+		assert_eq!(*t.initialized_classes.get(&~"ClassInit"), statics);
+		assert_eq!(int_result(test_call(&mut *t, &c, "getValue", [])), 42);
+		assert_eq!(t.initialize_class(&c).unwrap(), statics);
+		assert_eq!(int_result(test_call(&mut *t, &c, "getInitCount", [])), 1);
+
+		// a failing <clinit> leaves the class erroneous. Its exception
+		// is the cause of the ExceptionInInitializerError raised.
+		let f = t.classloader.load("ClassInitFails").await().unwrap();
+		let e = t.initialize_class(&f).unwrap_err();
+		assert_eq!(e.class_name.as_slice(), "java.lang.ExceptionInInitializerError");
+		assert_eq!(e.cause.get_ref().class_name.as_slice(), "java.lang.ArithmeticException");
+		assert!(e.get_report("main").contains("Caused by: java.lang.ArithmeticException"));
+		assert!(!t.initialized_classes.contains_key(&~"ClassInitFails"));
+		let e = t.initialize_class(&f).unwrap_err();
+		assert_eq!(e.class_name.as_slice(), "java.lang.NoClassDefFoundError");
+
+		// Errors are not wrapped
+		let f = t.classloader.load("ClassInitError").await().unwrap();
+		let e = t.initialize_class(&f).unwrap_err();
+		assert_eq!(e.class_name.as_slice(), "java.lang.AssertionError");
+		assert!(e.cause.is_none());
+	}
+
+
+	#[test]
+	fn test_interpret_integer_arithmetics() {
+		let (vm_port, chan) = Chan::new();
+		let broker_chan = ObjectBroker::new(chan).launch();
+
+		let mut t = test_thread(test_get_real_classloader(), broker_chan.clone());
+
+		// <clinit> loads the Class constant to ask for the assertion 
+		// status, main() ends with System.exit(4), which shuts down
+		// the VM without reporting the ThreadDeath that stops main()
+		t.set_context("IntegerArithmetics", "main", "([Ljava/lang/String;)V", None);
+		t.run_startup_method();
+		t.die();
+
+		match vm_port.recv() {
+			vm::BROKER_TO_VM_DID_SHUTDOWN(exit_code) => assert_eq!(exit_code, 4),
+			_ => assert!(false)
+		}
+		broker_chan.send(OB_VM_TO_BROKER(vm::VM_TO_BROKER_ACK_SHUTDOWN));
+	}
+
+
+	#[test]
+	fn test_interpret_invocations() {
+		let (mut t, c) = test_thread_and_class("InvokeDerived");
+		let base = c.get().get_superclass().unwrap().clone();
+
+		let derived_obj = t.heap.new_object(c.clone());
+		let base_obj = t.heap.new_object(base.clone());
+
+		// virtual dispatch by receiver class
+		assert_eq!(int_result(test_call(&mut *t, &c, "callVirtual", [derived_obj])), 2);
+		assert_eq!(int_result(test_call(&mut *t, &c, "callVirtual", [base_obj])), 1);
+		match test_call(&mut *t, &c, "callVirtual", [JAVA_NULL_OID]) {
+			Err(e) => assert_eq!(e.class_name, ~"java.lang.NullPointerException"),
+			Ok(_) => fail!("expected NullPointerException")
+		}
+
+		// private methods are not overridden
+		assert_eq!(int_result(test_call(&mut *t, &base, "callSecret", [derived_obj])), 10);
+
+		// super calls bypass the override in InvokeDerived
+		assert_eq!(int_result(test_call(&mut *t, &c, "superValue", [derived_obj])), 1);
+
+		assert_eq!(int_result(test_call(&mut *t, &c, "callInterface", [derived_obj])), 6);
+		match test_call(&mut *t, &c, "callInterface", [base_obj]) {
+			Err(e) => assert_eq!(e.class_name, ~"java.lang.IncompatibleClassChangeError"),
+			Ok(_) => fail!("expected IncompatibleClassChangeError")
+		}
+
+		// static methods, including one inherited from InvokeBase
+		assert_eq!(int_result(test_call(&mut *t, &c, "callStatic", [int(5)])), 11);
+		assert_eq!(long_result(test_call(&mut *t, &c, "fib", [15, 0])), 610);
+		assert_eq!(int_result(test_call(&mut *t, &c, "callSumArgs", [])), 10);
+
+		match test_call(&mut *t, &c, "recurse", [int(0)]) {
+			Err(e) => assert_eq!(e.class_name, ~"java.lang.StackOverflowError"),
+			Ok(_) => fail!("expected StackOverflowError")
+		}
+	}
+
+
+	#[test]
+	fn test_interpret_default_methods() {
+		let (mut t, c) = test_thread_and_class("DefaultImpl");
+		let v = t.classloader.load("DefaultConflict").await();
+		assert_no_err(&v);
+		let conflict = v.unwrap();
+
+		let obj = t.heap.new_object(c.clone());
+		let conflict_obj = t.heap.new_object(conflict);
+
+		assert_eq!(int_result(test_call(&mut *t, &c, "callWhich", [obj])), 2);
+		assert_eq!(int_result(test_call(&mut *t, &c, "callWhichVirtual", [obj])), 2);
+		assert_eq!(int_result(test_call(&mut *t, &c, "callArea", [obj])), 4);
+		assert_eq!(int_result(test_call(&mut *t, &c, "callArea", [conflict_obj])), 5);
+		match test_call(&mut *t, &c, "callWhich", [conflict_obj]) {
+			Err(e) => assert_eq!(e.class_name, ~"java.lang.IncompatibleClassChangeError"),
+			Ok(_) => fail!("expected IncompatibleClassChangeError")
+		}
+	}
+
+
+	#[test]
+	fn test_interpret_exceptions() {
+		let (mut t, c) = test_thread_and_class("Exceptions");
+		t.initialized_classes.insert(~"java.lang.String", JAVA_NULL_OID);
+
+		assert_eq!(int_result(test_call(&mut *t, &c, "catchArithmetic", [int(7), int(0)])), -1);
+		assert_eq!(int_result(test_call(&mut *t, &c, "catchArithmetic", [int(7), int(2)])), 3);
+		assert_eq!(int_result(test_call(&mut *t, &c, "catchAcrossFrames", [int(0)])), -2);
+		assert_eq!(int_result(test_call(&mut *t, &c, "nested", [int(0)])), 2);
+
+		// catch-all handlers, as used for finally
+		assert_eq!(int_result(test_call(&mut *t, &c, "finallyBlock", [int(2)])), 105);
+		match test_call(&mut *t, &c, "finallyBlock", [int(0)]) {
+			Err(e) => assert_eq!(e.class_name, ~"java.lang.ArithmeticException"),
+			Ok(_) => fail!("expected ArithmeticException")
+		}
+
+		// athrow passes on the caught exception object, along with
+		// the detail message stored in it
+		match test_call(&mut *t, &c, "rethrow", [int(0)]) {
+			Err(e) => {
+				assert_eq!(e.class_name, ~"java.lang.ArithmeticException");
+				assert!(e.oid != JAVA_NULL_OID);
+				assert_eq!(e.message, Some(~"/ by zero"));
+			},
+			Ok(_) => fail!("expected ArithmeticException")
+		}
+
+		match test_call(&mut *t, &c, "uncaught", []) {
+			Err(e) => {
+				assert_eq!(e.stack_trace, ~[~"Exceptions.thrower(Exceptions.java:4)", 
+					~"Exceptions.uncaught(Exceptions.java:60)"]);
+				assert_eq!(e.get_report("main"), ~"Exception in thread \"main\" " + 
+					"java.lang.ArithmeticException: / by zero\n" +
+					"\tat Exceptions.thrower(Exceptions.java:4)\n" + 
+					"\tat Exceptions.uncaught(Exceptions.java:60)\n");
+			},
+			Ok(_) => fail!("expected ArithmeticException")
+		}
+
+		// all frames were unwound
+		assert_eq!(t.frames.len(), 0);
+		assert_eq!(t.opstack.len(), 0);
+		assert_eq!(t.locals.len(), 0);
+	}
+
+
+	// ----------------------------------------------
+	fn patch_code(bytes : &mut ~[u8], from : &[u8], to : &[u8]) {
+		let pos = bytes.windows(from.len()).position(|w| w == from).unwrap();
+		for (i, b) in to.iter().enumerate() {
+			bytes[pos + i] = *b;
+		}
+	}
+
+
+	#[test]
+	fn test_interpret_verify_errors() {
+		// the Code attributes of the methods of VerifyFails start with 
+		// max_stack, max_locals and code_length. See VerifyFails.java
+		// for how their code is broken.
+		let mut bytes = File::open(&PosixPath::new("../test/java/VerifyFails.class")).read_to_end();
+		patch_code(&mut bytes, [0, 1, 0, 0, 0, 0, 0, 2, 0x04, 0xac], [0, 1, 0, 0, 0, 0, 0, 2, 0x00]);
+		patch_code(&mut bytes, [0, 0, 0, 0, 0, 0, 0, 1, 0xb1], [0, 0, 0, 0, 0, 0, 0, 1, 0x00]);
+		patch_code(&mut bytes, [0, 1, 0, 1, 0, 0, 0, 2, 0x1a, 0xac], [0, 1, 0, 1, 0, 0, 0, 2, 0xa9, 0x00]);
+		patch_code(&mut bytes, [0, 2, 0, 2, 0, 0, 0, 2, 0x1e, 0xad], [0, 2, 0, 2, 0, 0, 0, 2, 0x1f, 0xad]);
+
+		let mut cl = test_get_real_classloader();
+		let v = cl.add_from_bytes("VerifyFails", bytes).await();
+		assert_no_err(&v);
+		let c = v.unwrap();
+
+		let (_, chan) = Chan::new();
+		let broker_chan = ObjectBroker::new(chan).launch();
+		let mut t = test_thread(cl, broker_chan);
+
+		let verify_error = |r : Result<~[u64], PendingException>, message : &str| {
+			let e = r.unwrap_err();
+			assert_eq!(e.class_name.as_slice(), "java.lang.VerifyError");
+			assert_eq!(e.message.unwrap().as_slice(), message);
+		};
+		verify_error(test_call(&mut *t, &c, "underflow", []), "operand stack underflow");
+		verify_error(test_call(&mut *t, &c, "fallOff", []), "falling off the end of the code");
+		verify_error(test_call(&mut *t, &c, "badRet", [int(1000)]), "bad return address");
+		verify_error(test_call(&mut *t, &c, "badLocal", [5, 0]), "bad local variable index");
+
+		// the thread is still usable
+		assert_eq!(t.opstack.len(), 0);
+		assert_eq!(t.frames.len(), 0);
+	}
+
+
+	#[test]
+	fn test_interpret_arrays() {
+		let (mut t, c) = test_thread_and_class("ArrayOps");
+
+		assert_eq!(int_result(test_call(&mut *t, &c, "sumSquares", [int(4)])), 14);
+		assert_eq!(int_result(test_call(&mut *t, &c, "sumSquares", [int(0)])), 0);
+
+		// -56 + 65535 - 25536 + 2
+		assert_eq!(int_result(test_call(&mut *t, &c, "narrowElements", [])), 39945);
+		assert_eq!(long_result(test_call(&mut *t, &c, "wideElements", [])), i64::max_value - 15);
+
+		assert_eq!(int_result(test_call(&mut *t, &c, "multiDimensional", [])), 241);
+		assert_eq!(int_result(test_call(&mut *t, &c, "partialDimensions", [])), 3);
+
+		assert_eq!(int_result(test_call(&mut *t, &c, "outOfBounds", [int(1)])), 0);
+		assert_eq!(int_result(test_call(&mut *t, &c, "outOfBounds", [int(2)])), -1);
+		assert_eq!(int_result(test_call(&mut *t, &c, "outOfBounds", [int(-1)])), -1);
+		assert_eq!(int_result(test_call(&mut *t, &c, "negativeSize", [int(3)])), 3);
+		assert_eq!(int_result(test_call(&mut *t, &c, "negativeSize", [int(-1)])), -2);
+		assert_eq!(int_result(test_call(&mut *t, &c, "nullArray", [])), -3);
+
+		assert_eq!(int_result(test_call(&mut *t, &c, "covariantStore", [])), 3);
+		assert_eq!(int_result(test_call(&mut *t, &c, "invalidStore", [])), -4);
+
+		let ints = t.classloader.load("[[[I").await().unwrap();
+		assert_eq!(ints.get().get_component_type().get_ref().to_str(), ~"[[I");
+	}
+
+
+	#[test]
+	fn test_interpret_strings() {
+		let (mut t, c) = test_thread_and_class("Strings");
+		t.initialized_classes.insert(~"java.lang.String", JAVA_NULL_OID);
+		let other = t.classloader.load("StringsOther").await().unwrap();
+
+		// identical literals are the same object, across classes
+		let hello = test_call(&mut *t, &c, "literal", []).unwrap()[0];
+		assert!(hello != JAVA_NULL_OID);
+		assert_eq!(test_call(&mut *t, &c, "literal", []).unwrap()[0], hello);
+		assert_eq!(test_call(&mut *t, &other, "literal", []).unwrap()[0], hello);
+		assert!(test_call(&mut *t, &c, "other", []).unwrap()[0] != hello);
+		assert_eq!(t.get_string_chars(hello), "hello".to_utf16());
+
+		// static final String fields with a ConstantValue are interned
+		let statics = t.initialize_class(&c).unwrap();
+		let offset = c.get().get_static_layout().find("GREETING", "Ljava/lang/String;").unwrap().get_offset();
+		let mut greeting = JAVA_NULL_OID;
+		t.heap.access_object(OBJECT_ACCESS_Normal, statics, |obj| {
+			greeting = obj.get_ref(offset);
+		});
+		assert_eq!(greeting, hello);
+
+		// String.intern() of an interned String is the String itself
+		assert_eq!(int_result(test_call(&mut *t, &c, "internLiteral", [])), 1);
+
+		// a String interned before its literal is first loaded becomes
+		// the object the literal refers to.
+		let fresh = t.new_string("fresh".to_utf16()).unwrap();
+		assert_eq!(t.intern_string("fresh".to_utf16(), Some(fresh)), Ok(fresh));
+		assert_eq!(test_call(&mut *t, &c, "fresh", []).unwrap()[0], fresh);
+
+		// the table is shared by all threads
+		let mut t2 = test_thread(test_get_real_classloader(), t.broker_chan.clone());
+		t2.initialized_classes.insert(~"java.lang.String", JAVA_NULL_OID);
+		assert_eq!(t2.intern_string("hello".to_utf16(), None), Ok(hello));
+	}
+
+
+	fn is_monitor_locked(t : &mut ThreadContext, oid : JavaObjectId) -> bool {
+		let mut locked = false;
+		t.heap.access_object(OBJECT_ACCESS_Normal, oid, |obj| {
+			locked = obj.monitor().is_locked();
+		});
+		locked
+	}
+
+
+	#[test]
+	fn test_interpret_monitors() {
+		let (mut t, c) = test_thread_and_class("Monitors");
+		let m = t.heap.new_object(c.clone());
+
+		// recursive locking by synchronized methods and blocks
+		assert_eq!(int_result(test_call(&mut *t, &c, "nested", [m, int(3)])), 3);
+		assert!(!is_monitor_locked(&mut *t, m));
+
+		// static synchronized methods lock the class
+		assert_eq!(int_result(test_call(&mut *t, &c, "staticSync", [int(1)])), 2);
+		let class_object = t.get_class_object(&c).unwrap();
+		assert!(class_object != JAVA_NULL_OID);
+		assert_eq!(t.get_class_object(&c).unwrap(), class_object);
+		assert!(!is_monitor_locked(&mut *t, class_object));
+
+		// monitors are released when exceptions propagate
+		assert!(test_call(&mut *t, &c, "syncThrows", [m, int(0)]).is_err());
+		assert!(!is_monitor_locked(&mut *t, m));
+		assert!(test_call(&mut *t, &c, "blockThrows", [m, int(0)]).is_err());
+		assert!(!is_monitor_locked(&mut *t, m));
+		assert_eq!(int_result(test_call(&mut *t, &c, "blockThrows", [m, int(5)])), 2);
+
+		// leaving a monitor that is not held
+		t.push_ref(m);
+		t.monitor_exit();
+		assert_eq!(t.pending_exception.take().unwrap().class_name, 
+			~"java.lang.IllegalMonitorStateException");
+
+		t.push_ref(JAVA_NULL_OID);
+		t.monitor_enter();
+		assert_eq!(t.pending_exception.take().unwrap().class_name, 
+			~"java.lang.NullPointerException");
+	}
+
+
+	#[test]
+	fn test_interpret_monitors_concurrent() {
+		let (_, chan) = Chan::new();
+		let broker_chan = ObjectBroker::new(chan).launch();
+		let cl = test_get_real_classloader();
+
+		let (result_port, result_chan) = SharedChan::new();
+		let mut done_chans = ~[];
+		for _ in range(0, 2) {
+			let (done_port, done_chan) = Chan::new();
+			done_chans.push(done_chan);
+
+			let cl = cl.clone();
+			let broker_chan = broker_chan.clone();
+			let result_chan = result_chan.clone();
+			do spawn {
+				let mut t = test_thread(cl, broker_chan);
+				let c = t.classloader.load("Monitors").await().unwrap();
+				result_chan.send(int_result(test_call(&mut *t, &c, "countConcurrently", [int(500)])));
+
+				// keep serving requests for objects owned by this thread
+				// until the other thread is done as well.
+				loop {
+					match done_port.try_recv() {
+						Some(()) => break,
+						None => {
+							t.handle_pending_messages();
+							task::deschedule();
+						}
+					}
+				}
+				t.die();
+			}
+		}
+
+		// no increment is lost, the thread finishing last sees all of them
+		let a = result_port.recv();
+		let b = result_port.recv();
+		assert_eq!(if a > b { a } else { b }, 1000);
+
+		for chan in done_chans.iter() {
+			chan.send(());
+		}
+	}
+
+
+	// ----------------------------------------------
+	// Run a static method of a test class in a new thread, sending the
+	// tid of the thread and then the int result of the method. The 
+	// thread keeps serving requests for objects it owns until it gets
+	// a message on `done_port`.
+	fn spawn_test_thread(cl : ClassLoader, broker_chan : SharedChan<ObjectBrokerMessage>, 
+		class : ~str, method : ~str, args : ~[u64], tid_chan : Chan<uint>, 
+		result_chan : Chan<i32>, done_port : Port<()>) 
+	{
+		do spawn {
+			let mut t = test_thread(cl, broker_chan);
+			tid_chan.send(t.get_tid());
+
+			let c = t.classloader.load(class).await().unwrap();
+			result_chan.send(int_result(test_call(&mut *t, &c, method, args)));
+
+			loop {
+				match done_port.try_recv() {
+					Some(()) => break,
+					None => {
+						t.handle_pending_messages();
+						task::deschedule();
+					}
+				}
+			}
+			t.die();
+		}
+	}
+
+
+	#[test]
+	fn test_interpret_wait_notify() {
+		let (mut t, c) = test_thread_and_class("WaitNotify");
+		let object_class = t.classloader.load("java.lang.Object").await().unwrap();
+		let o = t.heap.new_object(object_class);
+
+		// timed wait, after which the monitor is held as often as before
+		assert_eq!(int_result(test_call(&mut *t, &c, "timedWait", [o, 10, 0])), 1);
+		assert!(!is_monitor_locked(&mut *t, o));
+
+		// the monitor must be held for wait and notify
+		assert_eq!(int_result(test_call(&mut *t, &c, "notifyUnowned", [o])), -1);
+		assert_eq!(int_result(test_call(&mut *t, &c, "waitUnowned", [o])), -1);
+
+		// a pending interrupt ends the next wait, clearing the status
+		let tid = t.get_tid();
+		t.interrupt_thread(tid);
+		assert_eq!(int_result(test_call(&mut *t, &c, "waitForInterrupt", [])), -1);
+		assert!(!t.interrupted);
+
+		match test_call(&mut *t, &c, "timedWait", [o, -1i64 as u64, 0]) {
+			Err(e) => assert_eq!(e.class_name, ~"java.lang.IllegalArgumentException"),
+			Ok(_) => fail!("expected IllegalArgumentException")
+		}
+	}
+
+
+	#[test]
+	fn test_interpret_wait_notify_concurrent() {
+		let (_, chan) = Chan::new();
+		let broker_chan = ObjectBroker::new(chan).launch();
+		let cl = test_get_real_classloader();
+
+		let mut results = ~[];
+		let mut tids = ~[];
+		let mut done_chans = ~[];
+		let calls = ~[(~"produce", ~[int(100)]), (~"consume", ~[int(100)]), (~"waitForInterrupt", ~[])];
+		for (method, args) in calls.move_iter() {
+			let (tid_port, tid_chan) = Chan::new();
+			let (result_port, result_chan) = Chan::new();
+			let (done_port, done_chan) = Chan::new();
+
+			spawn_test_thread(cl.clone(), broker_chan.clone(), ~"WaitNotify", method, 
+				args, tid_chan, result_chan, done_port);
+			tids.push(tid_port.recv());
+			results.push(result_port);
+			done_chans.push(done_chan);
+		}
+
+		// producer and consumer hand over every value through the
+		// one-element queue
+		assert_eq!(results[0].recv(), 100);
+		assert_eq!(results[1].recv(), 5050);
+
+		// the waiting thread is woken by an interrupt
+		broker_chan.send(OB_THREAD_REMOTE_OP(0, tids[2], THREAD_INTERRUPT));
+		assert_eq!(results[2].recv(), -1);
+
+		for chan in done_chans.iter() {
+			chan.send(());
+		}
+	}
+
+
+	fn native_add(_ : &mut ThreadContext, _ : &mut LocalHeap, args : &[JavaValue]) -> Option<JavaValue> {
+		match (args[0], args[1]) {
+			(JV_Int(a), JV_Long(b)) => Some(JV_Int(a + b as i32)),
+			_ => fail!("unexpected arguments")
+		}
+	}
+
+	fn native_scaled(_ : &mut ThreadContext, heap : &mut LocalHeap, args : &[JavaValue]) -> Option<JavaValue> {
+		let (this, factor) = match (args[0], args[1]) {
+			(JV_Reference(oid), JV_Double(f)) => (oid, f),
+			_ => fail!("unexpected arguments")
+		};
+		let mut value = 0;
+		heap.access_object(OBJECT_ACCESS_Normal, this, |obj| {
+			let jclass = obj.get_class();
+			value = obj.get_int(jclass.get().get_instance_layout().find("value", "I").unwrap().get_offset());
+		});
+		Some(JV_Double(value as f64 * factor))
+	}
+
+	fn native_greet(t : &mut ThreadContext, _ : &mut LocalHeap, args : &[JavaValue]) -> Option<JavaValue> {
+		let name = match args[0] {
+			JV_Reference(oid) => t.get_string_chars(oid),
+			_ => fail!("unexpected arguments")
+		};
+		match t.new_string("Hello, ".to_utf16() + name) {
+			Ok(oid) => Some(JV_Reference(oid)),
+			Err(e) => {
+				t.throw_exception(e, None);
+				None
+			}
+		}
+	}
+
+	fn native_check(t : &mut ThreadContext, _ : &mut LocalHeap, args : &[JavaValue]) -> Option<JavaValue> {
+		if args[0] == JV_Int(0) {
+			t.throw_exception("java.lang.IllegalStateException", Some("check failed"));
+		}
+		None
+	}
+
+	fn native_call_back(t : &mut ThreadContext, _ : &mut LocalHeap, args : &[JavaValue]) -> Option<JavaValue> {
+		let c = t.load_class("Natives").unwrap();
+		match t.call_method(&c, "twice", "(I)I", args) {
+			Ok(Some(JV_Int(v))) => Some(JV_Int(v + 1)),
+			Ok(_) => fail!("expected int result"),
+			Err(e) => {
+				t.rethrow(e);
+				None
+			}
+		}
+	}
+
+	fn native_wrong_type(_ : &mut ThreadContext, _ : &mut LocalHeap, _ : &[JavaValue]) -> Option<JavaValue> {
+		Some(JV_Long(1))
+	}
+
+
+	#[test]
+	fn test_interpret_natives() {
+		let (mut t, c) = test_thread_and_class("Natives");
+		t.initialized_classes.insert(~"java.lang.String", JAVA_NULL_OID);
+
+		t.register_native("Natives", "add", "(IJ)I", native_add);
+		t.register_native("Natives", "scaled", "(D)D", native_scaled);
+		t.register_native("Natives", "greet", "(Ljava/lang/String;)Ljava/lang/String;", native_greet);
+		t.register_native("Natives", "check", "(Z)V", native_check);
+		t.register_native("Natives", "callBack", "(I)I", native_call_back);
+		t.register_native("Natives", "wrongType", "()I", native_wrong_type);
+
+		// typed arguments and return values, also for instance methods
+		assert_eq!(int_result(test_call(&mut *t, &c, "callAdd", [int(2), 40, 0])), 43);
+		let r = test_call(&mut *t, &c, "callScaled", [int(3), double(1.5), 0]).unwrap();
+		assert_eq!(JavaValue::from_raw(SK_Double, r[0]), JV_Double(4.5));
+
+		// allocation of objects
+		let name = t.new_string("VM".to_utf16()).unwrap();
+		assert_eq!(int_result(test_call(&mut *t, &c, "greetLength", [name])), 9);
+
+		// exceptions thrown by natives, and by Java code they call
+		assert_eq!(int_result(test_call(&mut *t, &c, "callCheck", [int(1)])), 1);
+		assert_eq!(int_result(test_call(&mut *t, &c, "callCheck", [int(0)])), -1);
+		assert_eq!(int_result(test_call(&mut *t, &c, "callCallBack", [int(20)])), 41);
+		assert_eq!(int_result(test_call(&mut *t, &c, "callCallBack", [int(-1)])), -1);
+
+		match test_call(&mut *t, &c, "callWrongType", []) {
+			Err(e) => assert_eq!(e.class_name, ~"java.lang.InternalError"),
+			Ok(_) => fail!("expected InternalError")
+		}
+		match test_call(&mut *t, &c, "callMissing", []) {
+			Err(e) => assert_eq!(e.class_name, ~"java.lang.UnsatisfiedLinkError"),
+			Ok(_) => fail!("expected UnsatisfiedLinkError")
+		}
+	}
+}
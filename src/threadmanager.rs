@@ -45,7 +45,15 @@ pub enum RemoteThreadOpMessage {
 	THREAD_JOIN,
 	THREAD_NOTIFY_TERMINATION,
 	THREAD_SET_PRIORITY(int),
-	THREAD_SET_NAME(~str)
+	THREAD_SET_NAME(~str),
+
+	// the following are not processed by the threadmanager, but
+	// delivered to the destination thread by the broker.
+
+	// Thread.interrupt()
+	THREAD_INTERRUPT,
+	// end of a timed wait(), tagged with the wait's sequence number
+	THREAD_WAKEUP(uint),
 }
 
 
@@ -180,6 +188,7 @@ impl ThreadManager {
 			THREAD_NOTIFY_TERMINATION => fail!("THREAD_NOTIFY_TERMINATION unexpected"),
 			THREAD_SET_PRIORITY(prio) => (),
 			THREAD_SET_NAME(name) => (),
+			THREAD_INTERRUPT | THREAD_WAKEUP(_) => fail!("thread messages are delivered by the broker"),
 		}
 	}
 }
@@ -20,11 +20,11 @@
 //
 
 // --------------------------------------------------------------------
-// Assert that given `Result< ???, ~str>` is not an error, otherwise
+// Assert that given `Result< ???, E>` is not an error, otherwise
 // print the error message attached to it.
-pub fn assert_no_err<T> (given : &Result<T, ~str>) {
+pub fn assert_no_err<T, E : ToStr> (given : &Result<T, E>) {
 	match *given {
-		Err(ref s) => fail!("expected no error, error is: {}", s.clone()),
+		Err(ref s) => fail!("expected no error, error is: {}", s.to_str()),
 		_ => ()
 	}
 }
@@ -111,9 +111,9 @@ impl VM {
 
 
 	// ----------------------------------------------
-	// Spawn a new Java thread given a class, method and (optional but
-	// required if the given method is an instance method) a Java object
-	// to set as the *this* object for the method.
+	// Spawn a new Java thread given a class, method name and descriptor
+	// and (optional but required if the given method is an instance 
+	// method) a Java object to set as the *this* object for the method.
 	// 
 	// The thread is immediately able to run, but the exact time where
 	// it starts is determined by the scheduler. run_thread() returns
@@ -132,7 +132,7 @@ impl VM {
 	// fatal exception in that thread, causing it to fail before it runs
 	// any user code.
 	//
-	pub fn run_thread(&mut self, class : &str, method : &str, obj : Option<JavaObjectId>) -> Option<uint> {
+	pub fn run_thread(&mut self, class : &str, method : &str, desc : &str, obj : Option<JavaObjectId>) -> Option<uint> {
 		// Problem: if broker is already exited, broker_chan is hung up and
 		// causes propagating failure as soon as ThreadContext registers.
 		//
@@ -151,7 +151,7 @@ impl VM {
 		let mut t = ThreadContext::new(ld, self.broker_chan.clone());
 
 		let tid = t.get_tid();
-		t.set_context(class, method, obj);
+		t.set_context(class, method, desc, obj);
 
 		// this transfers ownership into a new task, which interprets the thread code
 		t.execute();
@@ -272,14 +272,14 @@ mod tests {
 		assert!(!v.is_exited());
 
 		// CREATED -> RUNNING
-		assert!(v.run_thread("","",None).is_some());
+		assert!(v.run_thread("","","",None).is_some());
 
 		// busy wait for EXITED, but do not kill the VM. This simulates what
 		// happens if the VM is exited because of the Java program terminating.
 		while !v.is_exited() {}
 
 		assert!(v.is_exited());
-		assert!(v.run_thread("","",None).is_none());
+		assert!(v.run_thread("","","",None).is_none());
 	}
 
 	#[test]
@@ -288,8 +288,8 @@ mod tests {
 		let mut v = VM::new(test_get_real_classloader());
 
 		// CREATED -> RUNNING
-		assert!(v.run_thread("","",None).is_some());
-		assert!(v.run_thread("","",None).is_some());
+		assert!(v.run_thread("","","",None).is_some());
+		assert!(v.run_thread("","","",None).is_some());
 		assert!(v.exit() < 0);
 	}
 
@@ -297,8 +297,8 @@ mod tests {
 	fn test_vm_init_threads_empty_main() {
 		// these threads are going to succeed however - the corresponding program is empty
 		let mut v = VM::new(test_get_real_classloader());
-		assert!(v.run_thread("EmptyClassWithMain","main",None).is_some());
-		assert!(v.run_thread("EmptyClassWithMain","main",None).is_some());
+		assert!(v.run_thread("EmptyClassWithMain","main","([Ljava/lang/String;)V",None).is_some());
+		assert!(v.run_thread("EmptyClassWithMain","main","([Ljava/lang/String;)V",None).is_some());
 		assert_eq!(v.exit(), 0);
 	} */
 }
@@ -0,0 +1,74 @@
+public class Arithmetics {
+
+	public static int intOverflow(int a) {
+		return a + 1;
+	}
+
+	public static int intDiv(int a, int b) {
+		return a / b;
+	}
+
+	public static int intRem(int a, int b) {
+		return a % b;
+	}
+
+	public static long longOps(long a, long b) {
+		return (a * b) ^ ((a >>> 3) + (b << 62));
+	}
+
+	public static int shifts(int a, int s) {
+		return (a << s) + (a >> s) + (a >>> s);
+	}
+
+	public static int floatCompare(float a, float b) {
+		if (a < b) {
+			return -1;
+		}
+		if (a > b) {
+			return 1;
+		}
+		return a == b ? 0 : 2;
+	}
+
+	public static int f2i(float f) {
+		return (int)f;
+	}
+
+	public static long d2l(double d) {
+		return (long)d;
+	}
+
+	public static int narrow(int i) {
+		return (byte)i + (char)i + (short)i;
+	}
+
+	public static double doubleRem(double a, double b) {
+		return a % b;
+	}
+
+	public static int sum(int n) {
+		int s = 0;
+		for (int i = 0; i < n; ++i) {
+			s += i;
+		}
+		return s;
+	}
+
+	public static int tableSwitch(int k) {
+		switch (k) {
+			case 1: return 10;
+			case 2: return 20;
+			case 3: return 30;
+			default: return -1;
+		}
+	}
+
+	public static int lookupSwitch(int k) {
+		switch (k) {
+			case -100: return 1;
+			case 5: return 2;
+			case 1000: return 3;
+			default: return 0;
+		}
+	}
+}
@@ -0,0 +1,94 @@
+public class ArrayOps {
+
+	public static int sumSquares(int n) {
+		int[] a = new int[n];
+		for (int i = 0; i < a.length; ++i) {
+			a[i] = i * i;
+		}
+		int sum = 0;
+		for (int i = 0; i < a.length; ++i) {
+			sum += a[i];
+		}
+		return sum;
+	}
+
+	public static int narrowElements() {
+		byte[] b = new byte[1];
+		char[] c = new char[1];
+		short[] s = new short[1];
+		boolean[] z = new boolean[2];
+		b[0] = (byte)200;
+		c[0] = (char)-1;
+		s[0] = (short)40000;
+		z[1] = true;
+		return b[0] + c[0] + s[0] + (z[0] ? 1 : 0) + (z[1] ? 2 : 0);
+	}
+
+	public static long wideElements() {
+		long[] l = new long[2];
+		double[] d = { 1.5, 2.5 };
+		float[] f = { 0.5f };
+		l[1] = Long.MAX_VALUE;
+		return l[1] - l[0] - (long)(d[0] * d[1] * f[0] * 8);
+	}
+
+	public static int multiDimensional() {
+		int[][][] m = new int[2][3][4];
+		m[1][2][3] = 7;
+		return m.length * 100 + m[1].length * 10 + m[1][2].length + m[1][2][3];
+	}
+
+	public static int partialDimensions() {
+		int[][] m = new int[3][];
+		return m[1] == null ? m.length : -1;
+	}
+
+	public static int outOfBounds(int i) {
+		int[] a = new int[2];
+		try {
+			return a[i];
+		}
+		catch (ArrayIndexOutOfBoundsException e) {
+			return -1;
+		}
+	}
+
+	public static int negativeSize(int n) {
+		try {
+			return new float[n].length;
+		}
+		catch (NegativeArraySizeException e) {
+			return -2;
+		}
+	}
+
+	public static int nullArray() {
+		int[] a = null;
+		try {
+			return a.length;
+		}
+		catch (NullPointerException e) {
+			return -3;
+		}
+	}
+
+	public static int covariantStore() {
+		Object[] o = new InvokeBase[2];
+		o[0] = new InvokeDerived();
+		o[1] = null;
+		Object[] p = new Object[1];
+		p[0] = new int[1];
+		return o.length + p.length;
+	}
+
+	public static int invalidStore() {
+		Object[] o = new InvokeBase[1];
+		try {
+			o[0] = new Object();
+			return 0;
+		}
+		catch (ArrayStoreException e) {
+			return -4;
+		}
+	}
+}
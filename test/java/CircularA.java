@@ -0,0 +1,7 @@
+
+// CircularA and CircularB extend each other, which javac refuses to
+// compile. To produce the .class files, each of the two classes is
+// compiled against a stub version of the other class that has no
+// super class.
+public class CircularA extends CircularB {
+}
@@ -0,0 +1,4 @@
+
+// see CircularA.java
+public class CircularB extends CircularA {
+}
@@ -0,0 +1,21 @@
+public class ClassInit {
+
+	static int value = compute();
+	static int initCount;
+
+	static {
+		++initCount;
+	}
+
+	static int compute() {
+		return 7 * 6;
+	}
+
+	public static int getValue() {
+		return value;
+	}
+
+	public static int getInitCount() {
+		return initCount;
+	}
+}
@@ -0,0 +1,12 @@
+public class ClassInitError {
+
+	static int value = fail();
+
+	static int fail() {
+		throw new AssertionError();
+	}
+
+	public static int getValue() {
+		return value;
+	}
+}
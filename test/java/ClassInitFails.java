@@ -0,0 +1,12 @@
+public class ClassInitFails {
+
+	static int value = 1 / zero();
+
+	static int zero() {
+		return 0;
+	}
+
+	public static int getValue() {
+		return value;
+	}
+}
@@ -0,0 +1,8 @@
+public interface DefaultA {
+
+	default int which() {
+		return 1;
+	}
+
+	int area();
+}
@@ -0,0 +1,8 @@
+public interface DefaultB extends DefaultA {
+
+	// more specific than DefaultA.which
+	@Override
+	default int which() {
+		return 2;
+	}
+}
@@ -0,0 +1,7 @@
+public interface DefaultC {
+
+	// not present when DefaultConflict was compiled
+	default int which() {
+		return 3;
+	}
+}
@@ -0,0 +1,9 @@
+// compiled while DefaultC did not declare which(), so DefaultA.which
+// and DefaultC.which are both maximally-specific at runtime.
+public class DefaultConflict implements DefaultA, DefaultC {
+
+	@Override
+	public int area() {
+		return 5;
+	}
+}
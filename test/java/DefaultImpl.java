@@ -0,0 +1,19 @@
+public class DefaultImpl implements DefaultB, DefaultA {
+
+	@Override
+	public int area() {
+		return 4;
+	}
+
+	public static int callWhich(DefaultA a) {
+		return a.which();
+	}
+
+	public static int callArea(DefaultA a) {
+		return a.area();
+	}
+
+	public static int callWhichVirtual(DefaultImpl d) {
+		return d.which();
+	}
+}
@@ -0,0 +1,62 @@
+public class Exceptions {
+
+	static int thrower(int a) {
+		return 10 / a;
+	}
+
+	public static int catchArithmetic(int a, int b) {
+		try {
+			return a / b;
+		}
+		catch (ArithmeticException e) {
+			return -1;
+		}
+	}
+
+	public static int catchAcrossFrames(int a) {
+		try {
+			return thrower(a);
+		}
+		catch (RuntimeException e) {
+			return -2;
+		}
+	}
+
+	public static int nested(int a) {
+		try {
+			try {
+				return thrower(a);
+			}
+			catch (NullPointerException e) {
+				return 1;
+			}
+		}
+		catch (ArithmeticException e) {
+			return 2;
+		}
+	}
+
+	public static int finallyBlock(int a) {
+		int r = 0;
+		try {
+			r = 10 / a;
+		}
+		finally {
+			r += 100;
+		}
+		return r;
+	}
+
+	public static int rethrow(int a) {
+		try {
+			return thrower(a);
+		}
+		catch (ArithmeticException e) {
+			throw e;
+		}
+	}
+
+	public static void uncaught() {
+		thrower(0);
+	}
+}
@@ -0,0 +1,10 @@
+public class HierarchyImpl extends LayoutDerived implements SubInterface {
+
+	@Override
+	public void foo(String bar) {
+	}
+
+	@Override
+	public void bar() {
+	}
+}
@@ -0,0 +1,18 @@
+public class InvokeBase {
+
+	public int value() {
+		return 1;
+	}
+
+	private int secret() {
+		return 10;
+	}
+
+	public int callSecret() {
+		return secret();
+	}
+
+	public static int twice(int a) {
+		return a * 2;
+	}
+}
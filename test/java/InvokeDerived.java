@@ -0,0 +1,50 @@
+public class InvokeDerived extends InvokeBase implements InvokeIface {
+
+	@Override
+	public int value() {
+		return 2;
+	}
+
+	// does not override InvokeBase.secret
+	private int secret() {
+		return 20;
+	}
+
+	public int superValue() {
+		return super.value();
+	}
+
+	@Override
+	public int area() {
+		return 6;
+	}
+
+	public static int callVirtual(InvokeBase b) {
+		return b.value();
+	}
+
+	public static int callInterface(InvokeIface i) {
+		return i.area();
+	}
+
+	public static int callStatic(int a) {
+		// resolves to InvokeBase.twice
+		return twice(a) + 1;
+	}
+
+	public static long fib(long n) {
+		return n < 2 ? n : fib(n - 1) + fib(n - 2);
+	}
+
+	public static int sumArgs(int a, long b, double c, int d) {
+		return a + (int)b + (int)c + d;
+	}
+
+	public static int callSumArgs() {
+		return sumArgs(1, 2L, 3.0, 4);
+	}
+
+	public static int recurse(int n) {
+		return recurse(n + 1) + 1;
+	}
+}
@@ -0,0 +1,3 @@
+public interface InvokeIface {
+	int area();
+}
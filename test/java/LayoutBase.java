@@ -0,0 +1,6 @@
+public class LayoutBase {
+
+	public int a;
+	public long b;
+	public static Object shared;
+}
@@ -0,0 +1,8 @@
+public class LayoutDerived extends LayoutBase {
+
+	public Object c;
+
+	// hides LayoutBase.a
+	public double a;
+	public static int counter;
+}
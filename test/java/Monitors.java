@@ -0,0 +1,39 @@
+public class Monitors {
+
+	static final Object LOCK = new Object();
+	static int counter;
+
+	public synchronized int nested(int n) {
+		if (n == 0) {
+			return 0;
+		}
+		synchronized (this) {
+			return nested(n - 1) + 1;
+		}
+	}
+
+	public static synchronized int staticSync(int a) {
+		return a + 1;
+	}
+
+	public synchronized int syncThrows(int a) {
+		return 10 / a;
+	}
+
+	public static int blockThrows(Object o, int a) {
+		synchronized (o) {
+			return 10 / a;
+		}
+	}
+
+	public static int countConcurrently(int n) {
+		for (int i = 0; i < n; ++i) {
+			synchronized (LOCK) {
+				++counter;
+			}
+		}
+		synchronized (LOCK) {
+			return counter;
+		}
+	}
+}
@@ -0,0 +1,60 @@
+public class Natives {
+
+	int value;
+
+	static native int add(int a, long b);
+	native double scaled(double factor);
+	static native String greet(String name);
+	static native void check(boolean ok);
+	static native int callBack(int a);
+	static native int wrongType();
+	static native void missing();
+
+	static int twice(int a) {
+		if (a < 0) {
+			throw new IllegalArgumentException();
+		}
+		return 2 * a;
+	}
+
+	public static int callAdd(int a, long b) {
+		return add(a, b) + 1;
+	}
+
+	public static double callScaled(int value, double factor) {
+		Natives n = new Natives();
+		n.value = value;
+		return n.scaled(factor);
+	}
+
+	public static int greetLength(String name) {
+		return greet(name).length();
+	}
+
+	public static int callCheck(boolean ok) {
+		try {
+			check(ok);
+			return 1;
+		}
+		catch (IllegalStateException e) {
+			return -1;
+		}
+	}
+
+	public static int callCallBack(int a) {
+		try {
+			return callBack(a);
+		}
+		catch (IllegalArgumentException e) {
+			return -1;
+		}
+	}
+
+	public static int callWrongType() {
+		return wrongType();
+	}
+
+	public static void callMissing() {
+		missing();
+	}
+}
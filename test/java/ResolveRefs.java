@@ -0,0 +1,18 @@
+public class ResolveRefs {
+
+	public static void run(HierarchyImpl h) {
+		// instance fields declared by super classes
+		h.c = null;
+		h.b = 1;
+
+		// static field
+		LayoutDerived.counter = 2;
+
+		// class and interface methods
+		h.bar();
+		((SubInterface)h).foo(null);
+
+		// compiled against a class that does not exist at runtime
+		Missing.call();
+	}
+}
@@ -0,0 +1,10 @@
+
+public class StaticConstants {
+
+	public static final long LONG_VALUE = 1234567890123L;
+	public static final int INT_VALUE = 42;
+	public static final double DOUBLE_VALUE = 2.5;
+	public static final String STRING_VALUE = "rusty";
+
+	public static int counter = 1;
+}
@@ -0,0 +1,21 @@
+public class Strings {
+
+	public static final String GREETING = "hello";
+
+	public static String literal() {
+		return "hello";
+	}
+
+	public static String other() {
+		return "world";
+	}
+
+	public static String fresh() {
+		return "fresh";
+	}
+
+	public static boolean internLiteral() {
+		String s = literal();
+		return s.intern() == s;
+	}
+}
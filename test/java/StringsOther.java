@@ -0,0 +1,6 @@
+public class StringsOther {
+
+	public static String literal() {
+		return "hello";
+	}
+}
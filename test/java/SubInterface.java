@@ -0,0 +1,5 @@
+public interface SubInterface extends TestInterface {
+	int CONSTANT = 7;
+
+	void bar();
+}
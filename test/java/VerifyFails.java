@@ -0,0 +1,23 @@
+// The bytecode of these methods is made invalid by the test using
+// this class, see test_interpret_verify_errors in thread.rs
+public class VerifyFails {
+
+	// the constant is replaced by a nop, so ireturn finds no value
+	static int underflow() {
+		return 1;
+	}
+
+	// the return is replaced by a nop
+	static void fallOff() {
+	}
+
+	// iload_0 and ireturn are replaced by ret 0
+	static int badRet(int x) {
+		return x;
+	}
+
+	// lload_0 is replaced by lload_1, whose second slot is out of range
+	static long badLocal(long x) {
+		return x;
+	}
+}
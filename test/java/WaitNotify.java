@@ -0,0 +1,87 @@
+public class WaitNotify {
+
+	static final Object LOCK = new Object();
+	static int slot;
+	static boolean full;
+
+	public static int timedWait(Object o, long millis) throws InterruptedException {
+		synchronized (o) {
+			synchronized (o) {
+				o.wait(millis);
+			}
+			// the monitor is still held once
+			o.notify();
+		}
+		return 1;
+	}
+
+	public static int notifyUnowned(Object o) {
+		try {
+			o.notifyAll();
+			return 0;
+		}
+		catch (IllegalMonitorStateException e) {
+			return -1;
+		}
+	}
+
+	public static int waitUnowned(Object o) throws InterruptedException {
+		try {
+			o.wait();
+			return 0;
+		}
+		catch (IllegalMonitorStateException e) {
+			return -1;
+		}
+	}
+
+	public static int waitForInterrupt() {
+		Object o = new Object();
+		synchronized (o) {
+			try {
+				o.wait();
+				return 0;
+			}
+			catch (InterruptedException e) {
+				return -1;
+			}
+		}
+	}
+
+	public static void put(int value) throws InterruptedException {
+		synchronized (LOCK) {
+			while (full) {
+				LOCK.wait();
+			}
+			slot = value;
+			full = true;
+			LOCK.notifyAll();
+		}
+	}
+
+	public static int take() throws InterruptedException {
+		synchronized (LOCK) {
+			while (!full) {
+				LOCK.wait();
+			}
+			full = false;
+			LOCK.notifyAll();
+			return slot;
+		}
+	}
+
+	public static int produce(int n) throws InterruptedException {
+		for (int i = 1; i <= n; ++i) {
+			put(i);
+		}
+		return n;
+	}
+
+	public static int consume(int n) throws InterruptedException {
+		int sum = 0;
+		for (int i = 0; i < n; ++i) {
+			sum += take();
+		}
+		return sum;
+	}
+}